@@ -1,5 +1,7 @@
 pub mod motion_validator;
+pub mod objectives;
 pub mod param;
+pub mod problem_definition;
 pub mod spaces;
 pub mod state;
 pub mod state_allocator;
@@ -0,0 +1,568 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    sync::{mpsc, Arc},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::base::{
+    state_allocator::{StateId, StateKey},
+    state_validity_checker::StateValidityChecker,
+    statespace::StateSpace,
+};
+
+use super::{MotionCheckStats, MotionValidator};
+
+/// A validity check over the real values of a state (as given by
+/// `StateSpace::copy_to_reals`) that can be called from several threads at once.
+pub type ThreadSafeValidityFn = Arc<dyn Fn(&[f64]) -> bool + Send + Sync>;
+
+/// An interior state to check: the index of its motion, its time along the motion and its
+/// real values.
+type Sample = (usize, f64, Vec<f64>);
+
+/// A checked interior state: the index of its motion, its time and its validity.
+type CheckedSample = (usize, f64, bool);
+
+/// The channels to a worker thread: samples to check are sent, and checked samples received.
+type WorkerChannels = (
+    mpsc::Sender<Vec<Sample>>,
+    mpsc::Receiver<Vec<CheckedSample>>,
+);
+
+/// Worker threads checking samples with a `ThreadSafeValidityFn`.
+///
+/// Batches are checked one bisection level at a time, so that the samples of motions found
+/// invalid are never computed. The threads are therefore kept for as long as the pool lives
+/// rather than started for every level.
+struct CheckerPool {
+    workers: Vec<WorkerChannels>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl CheckerPool {
+    fn new(checker: ThreadSafeValidityFn, threads: usize) -> Self {
+        let mut workers = Vec::with_capacity(threads);
+        let mut handles = Vec::with_capacity(threads);
+        for _ in 0..threads {
+            let (job_sender, jobs) = mpsc::channel::<Vec<Sample>>();
+            let (result_sender, results) = mpsc::channel();
+            let checker = checker.clone();
+            handles.push(thread::spawn(move || {
+                for samples in jobs {
+                    let checked: Vec<CheckedSample> = samples
+                        .into_iter()
+                        .map(|(i, t, reals)| (i, t, checker(&reals)))
+                        .collect();
+                    if result_sender.send(checked).is_err() {
+                        break;
+                    }
+                }
+            }));
+            workers.push((job_sender, results));
+        }
+        Self { workers, handles }
+    }
+
+    /// Check the samples, spread evenly over the workers.
+    fn check(&self, mut samples: Vec<Sample>) -> Vec<CheckedSample> {
+        let chunk = samples.len().div_ceil(self.workers.len()).max(1);
+        let mut busy = 0;
+        while !samples.is_empty() {
+            let rest = samples.split_off(samples.len().saturating_sub(chunk));
+            self.workers[busy]
+                .0
+                .send(rest)
+                .expect("motion checking thread panicked");
+            busy += 1;
+        }
+        self.workers[..busy]
+            .iter()
+            .flat_map(|(_, results)| results.recv().expect("motion checking thread panicked"))
+            .collect()
+    }
+}
+
+impl Drop for CheckerPool {
+    fn drop(&mut self) {
+        // closing the job channels stops the workers
+        self.workers.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Indices of the interior states of a motion split in `nd` segments, in the order in which
+/// they are checked: the midpoint first, then the midpoints of each half, and so on. Invalid
+/// states are usually found sooner than by walking from one end.
+fn bisection_order(nd: u32) -> Vec<u32> {
+    let mut order = Vec::with_capacity(nd.saturating_sub(1) as usize);
+    let mut intervals = VecDeque::new();
+    if nd >= 2 {
+        intervals.push_back((1, nd - 1));
+    }
+    while let Some((first, second)) = intervals.pop_front() {
+        let mid = (first + second) / 2;
+        order.push(mid);
+        if first < mid {
+            intervals.push_back((first, mid - 1));
+        }
+        if second > mid {
+            intervals.push_back((mid + 1, second));
+        }
+    }
+    order
+}
+
+/// A discrete motion validator designed for validating many motions at once, as done by
+/// planners that connect a new state to all of its neighbours.
+///
+/// Single motions are checked as by `DiscreteMotionValidator`. Batches of motions
+/// (`check_motions`) are checked endpoints first, then one interior state of every pending
+/// motion at a time, so that cheap rejections are found before long motions are checked
+/// in full. The validity of endpoints shared by several motions is computed once, and motions
+/// repeated in the batch (in either direction) share their interior checks. As checks are
+/// shared between the motions of a batch, they are not attributed to any one of them:
+/// `MotionCheckStats::last_motion_state_checks` counts the checks of the whole batch.
+///
+/// When a thread-safe validity check is given with `set_thread_safe_checker`, the interior
+/// states of a batch are computed on the calling thread, one bisection level at a time, and
+/// each level is checked by a pool of worker threads.
+pub struct BatchMotionValidator {
+    state_space: Rc<dyn StateSpace>,
+    checker: Arc<dyn StateValidityChecker>,
+    stats: RefCell<MotionCheckStats>,
+    parallel: Option<CheckerPool>,
+}
+
+impl BatchMotionValidator {
+    /// Check the states of batches with `checker` on `threads` worker threads, which live
+    /// until the checker is cleared or the validator dropped. `checker` must agree with the
+    /// state validity checker of this validator.
+    pub fn set_thread_safe_checker(&mut self, checker: ThreadSafeValidityFn, threads: usize) {
+        if threads == 0 {
+            panic!("At least one worker thread is needed");
+        }
+        self.parallel = Some(CheckerPool::new(checker, threads));
+    }
+
+    pub fn clear_thread_safe_checker(&mut self) {
+        self.parallel = None;
+    }
+
+    /// Check motions one at a time, from the cheapest (fewest states to check) to the most
+    /// expensive. Results are produced as the iterator is advanced, so callers that only need
+    /// some of the valid motions can stop early.
+    pub fn check_motions_lazy<'a>(
+        &'a self,
+        motions: &'a [(&'a StateId, &'a StateId)],
+    ) -> LazyMotionChecks<'a> {
+        let mut order: Vec<(u32, usize)> = motions
+            .iter()
+            .enumerate()
+            .map(|(i, &(s1, s2))| (self.state_space.valid_segment_count(s1, s2), i))
+            .collect();
+        order.sort();
+        LazyMotionChecks {
+            validator: self,
+            motions,
+            order: order.into_iter().map(|(_, i)| i).collect(),
+            position: 0,
+            endpoints: HashMap::new(),
+            interiors: HashMap::new(),
+        }
+    }
+
+    /// Record a motion split in `nd` segments, given the time at which it was found invalid.
+    fn record(&self, failure: Option<f64>, nd: u32) {
+        self.stats
+            .borrow_mut()
+            .record_motion(failure.is_none(), nd, failure);
+    }
+
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.stats
+            .borrow_mut()
+            .time_check(|| self.checker.is_valid(state))
+    }
+
+    fn interpolate(&self, s1: &StateId, s2: &StateId, t: f64, state: &mut StateId) {
+        self.stats
+            .borrow_mut()
+            .time_interpolation(|| self.state_space.interpolate(s1, s2, t, state));
+    }
+
+    /// Check the interior states of the motion from s1 to s2, split in `nd` segments. Returns
+    /// the time of the first invalid state found, if any.
+    fn check_interior(&self, s1: &StateId, s2: &StateId, nd: u32) -> Option<f64> {
+        let order = bisection_order(nd);
+        if order.is_empty() {
+            return None;
+        }
+        let mut test = self.state_space.alloc_state();
+        let failure = order.iter().map(|&j| j as f64 / nd as f64).find(|&t| {
+            self.interpolate(s1, s2, t, &mut test);
+            !self.is_valid(&test)
+        });
+        self.state_space.free_state(&test);
+        failure
+    }
+
+    /// For every motion of the batch, the index of the first motion with the same endpoints
+    /// (in either direction).
+    fn representatives(motions: &[(&StateId, &StateId)]) -> Vec<usize> {
+        (0..motions.len())
+            .map(|i| {
+                let (a, b) = motions[i];
+                (0..i)
+                    .find(|&j| {
+                        let (c, d) = motions[j];
+                        (a == c && b == d) || (a == d && b == c)
+                    })
+                    .unwrap_or(i)
+            })
+            .collect()
+    }
+
+    fn check_batch_interiors_sequential(
+        &self,
+        motions: &[(&StateId, &StateId)],
+        pending: &[(usize, u32)],
+        interior: &mut [Option<f64>],
+    ) {
+        let mut work: Vec<(usize, u32, Vec<u32>)> = pending
+            .iter()
+            .map(|&(i, nd)| (i, nd, bisection_order(nd)))
+            .collect();
+
+        let mut test = self.state_space.alloc_state();
+        let mut step = 0;
+        while !work.is_empty() {
+            work.retain(|(i, nd, order)| {
+                let Some(&j) = order.get(step) else {
+                    return false;
+                };
+                let (s1, s2) = motions[*i];
+                let t = j as f64 / *nd as f64;
+                self.interpolate(s1, s2, t, &mut test);
+                if !self.is_valid(&test) {
+                    interior[*i] = Some(t);
+                    return false;
+                }
+                true
+            });
+            step += 1;
+        }
+        self.state_space.free_state(&test);
+    }
+
+    fn check_batch_interiors_parallel(
+        &self,
+        motions: &[(&StateId, &StateId)],
+        pending: &[(usize, u32)],
+        interior: &mut [Option<f64>],
+        pool: &CheckerPool,
+    ) {
+        let mut work: Vec<(usize, u32, Vec<u32>)> = pending
+            .iter()
+            .map(|&(i, nd)| (i, nd, bisection_order(nd)))
+            .collect();
+
+        let mut test = self.state_space.alloc_state();
+        let mut step = 0;
+        while !work.is_empty() {
+            work.retain(|(_, _, order)| step < order.len());
+            // the state space is not thread-safe, so interpolate on this thread
+            let samples: Vec<Sample> = work
+                .iter()
+                .map(|(i, nd, order)| {
+                    let (s1, s2) = motions[*i];
+                    let t = order[step] as f64 / *nd as f64;
+                    self.interpolate(s1, s2, t, &mut test);
+                    let mut reals = Vec::new();
+                    self.state_space.copy_to_reals(&mut reals, &test);
+                    (*i, t, reals)
+                })
+                .collect();
+
+            let start = Instant::now();
+            let checked = pool.check(samples);
+            let mut stats = self.stats.borrow_mut();
+            stats.checker_time += start.elapsed();
+            stats.state_checks += checked.len() as u64;
+            stats.last_motion_state_checks += checked.len() as u64;
+            for (i, t, valid) in checked {
+                if !valid {
+                    interior[i] = Some(t);
+                }
+            }
+            work.retain(|(i, _, _)| interior[*i].is_none());
+            step += 1;
+        }
+        self.state_space.free_state(&test);
+    }
+}
+
+impl MotionValidator for BatchMotionValidator {
+    fn new(state_space: Rc<dyn StateSpace>, checker: Arc<dyn StateValidityChecker>) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            state_space,
+            checker,
+            stats: RefCell::new(MotionCheckStats::default()),
+            parallel: None,
+        }
+    }
+
+    fn get_motion_check_stats(&self) -> &RefCell<MotionCheckStats> {
+        &self.stats
+    }
+
+    fn check_motion(&self, s1: &StateId, s2: &StateId) -> bool {
+        self.stats.borrow_mut().begin_motion();
+        let nd = self.state_space.valid_segment_count(s1, s2);
+        let failure = if self.is_valid(s2) {
+            self.check_interior(s1, s2, nd)
+        } else {
+            Some(1.0)
+        };
+        self.record(failure, nd);
+        failure.is_none()
+    }
+
+    fn check_motion_with_last_valid(
+        &self,
+        s1: &StateId,
+        s2: &StateId,
+        last_valid: &mut (Option<StateId>, f64),
+    ) -> bool {
+        // assume motion starts in a valid configuration
+
+        self.stats.borrow_mut().begin_motion();
+        let nd = self.state_space.valid_segment_count(s1, s2).max(1);
+        let mut test = self.state_space.alloc_state();
+        let mut invalid_at = None;
+        for j in 1..=nd {
+            self.interpolate(s1, s2, j as f64 / nd as f64, &mut test);
+            if !self.is_valid(&test) {
+                invalid_at = Some(j);
+                break;
+            }
+        }
+        self.state_space.free_state(&test);
+
+        if let Some(j) = invalid_at {
+            last_valid.1 = (j - 1) as f64 / nd as f64;
+            if let Some(s) = &mut last_valid.0 {
+                self.state_space.interpolate(s1, s2, last_valid.1, s);
+            }
+        }
+
+        self.record(invalid_at.map(|j| j as f64 / nd as f64), nd);
+        invalid_at.is_none()
+    }
+
+    fn check_motions(&self, motions: &[(&StateId, &StateId)]) -> Vec<bool> {
+        // the whole batch counts as one motion for `last_motion_state_checks`
+        self.stats.borrow_mut().begin_motion();
+        let representatives = Self::representatives(motions);
+
+        // endpoints first, each distinct state being checked once
+        let mut endpoints: HashMap<StateKey, bool> = HashMap::new();
+        let end_valid: Vec<bool> = motions
+            .iter()
+            .map(|&(_, s2)| {
+                *endpoints
+                    .entry(s2.key())
+                    .or_insert_with(|| self.is_valid(s2))
+            })
+            .collect();
+
+        // interior states are only checked for one motion of each group of repeated motions,
+        // and only if one of them has a valid endpoint
+        let mut pending: Vec<(usize, u32)> = (0..motions.len())
+            .filter(|&i| {
+                representatives[i] == i
+                    && (i..motions.len()).any(|k| representatives[k] == i && end_valid[k])
+            })
+            .map(|i| {
+                let (s1, s2) = motions[i];
+                (i, self.state_space.valid_segment_count(s1, s2))
+            })
+            .collect();
+        pending.sort_by_key(|&(i, nd)| (nd, i));
+
+        let mut interior = vec![None; motions.len()];
+        match &self.parallel {
+            Some(pool) => {
+                self.check_batch_interiors_parallel(motions, &pending, &mut interior, pool)
+            }
+            None => self.check_batch_interiors_sequential(motions, &pending, &mut interior),
+        }
+
+        (0..motions.len())
+            .map(|i| {
+                let (s1, s2) = motions[i];
+                let failure = if !end_valid[i] {
+                    Some(1.0)
+                } else if representatives[i] == i || motions[representatives[i]].0 == s1 {
+                    interior[representatives[i]]
+                } else {
+                    // the representative goes the other way
+                    interior[representatives[i]].map(|t| 1.0 - t)
+                };
+                self.record(failure, self.state_space.valid_segment_count(s1, s2));
+                failure.is_none()
+            })
+            .collect()
+    }
+}
+
+/// Results of `BatchMotionValidator::check_motions_lazy`: pairs of the index of a motion and
+/// its validity, from the cheapest motion to the most expensive one.
+pub struct LazyMotionChecks<'a> {
+    validator: &'a BatchMotionValidator,
+    motions: &'a [(&'a StateId, &'a StateId)],
+    order: Vec<usize>,
+    position: usize,
+    endpoints: HashMap<StateKey, bool>,
+    interiors: HashMap<(StateKey, StateKey), Option<f64>>,
+}
+
+impl Iterator for LazyMotionChecks<'_> {
+    type Item = (usize, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &index = self.order.get(self.position)?;
+        self.position += 1;
+
+        let validator = self.validator;
+        validator.stats.borrow_mut().begin_motion();
+        let (s1, s2) = self.motions[index];
+        let nd = validator.state_space.valid_segment_count(s1, s2);
+        let end_valid = *self
+            .endpoints
+            .entry(s2.key())
+            .or_insert_with(|| validator.is_valid(s2));
+
+        let failure = if !end_valid {
+            Some(1.0)
+        } else if let Some(reversed) = self.interiors.get(&(s2.key(), s1.key())) {
+            reversed.map(|t| 1.0 - t)
+        } else {
+            *self
+                .interiors
+                .entry((s1.key(), s2.key()))
+                .or_insert_with(|| validator.check_interior(s1, s2, nd))
+        };
+        validator.record(failure, nd);
+        Some((index, failure.is_none()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.order.len() - self.position;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::FunctionalStateValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_batch_motion_validator() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        // a wall at x in [0.45, 0.55], below y = 0.8
+        let wall = |v: &[f64]| !(v[0] > 0.45 && v[0] < 0.55 && v[1] < 0.8);
+        let checks = Rc::new(Cell::new(0));
+        let checker = {
+            let (space, checks) = (space.clone(), checks.clone());
+            Arc::new(FunctionalStateValidityChecker::new(Box::new(move |s| {
+                checks.set(checks.get() + 1);
+                space.with_state(s, |s| wall(s.values.as_slice()))
+            })))
+        };
+        let mut validator = BatchMotionValidator::new(space.clone(), checker);
+
+        let states = [
+            (state(0.1, 0.1), state(0.9, 0.1)),
+            (state(0.1, 0.9), state(0.9, 0.9)),
+            (state(0.1, 0.1), state(0.2, 0.1)),
+            (state(0.1, 0.1), state(0.5, 0.5)),
+        ];
+        let motions: Vec<(&StateId, &StateId)> = states.iter().map(|(a, b)| (a, b)).collect();
+        let expected = vec![false, true, true, false];
+        let single: Vec<bool> = motions
+            .iter()
+            .map(|&(s1, s2)| validator.check_motion(s1, s2))
+            .collect();
+        assert_eq!(single, expected);
+        assert_eq!(validator.check_motions(&motions), expected);
+
+        // the wall is hit by the first state checked after the endpoint
+        let before = checks.get();
+        assert_eq!(validator.check_motions(&motions[..1]), vec![false]);
+        assert_eq!(checks.get() - before, 2);
+
+        // repeated motions, in either direction, share their checks
+        let (a, b) = (state(0.1, 0.9), state(0.9, 0.9));
+        let nd = space.valid_segment_count(&a, &b);
+        let repeated = vec![(&a, &b), (&b, &a)];
+        let before = checks.get();
+        assert_eq!(validator.check_motions(&repeated), vec![true, true]);
+        assert_eq!(checks.get() - before, 2 + nd as usize - 1);
+        assert_eq!(
+            validator
+                .get_motion_check_stats()
+                .borrow()
+                .last_motion_state_checks,
+            2 + nd as u64 - 1
+        );
+
+        let lazy: Vec<(usize, bool)> = validator.check_motions_lazy(&motions).collect();
+        assert_eq!(lazy, vec![(2, true), (3, false), (0, false), (1, true)]);
+
+        // the parallel path stops checking a motion at its first invalid state, as the
+        // sequential one does
+        validator.set_thread_safe_checker(Arc::new(wall), 3);
+        let before = validator.get_motion_check_stats().borrow().state_checks;
+        assert_eq!(validator.check_motions(&motions[..1]), vec![false]);
+        assert_eq!(
+            validator.get_motion_check_stats().borrow().state_checks - before,
+            2
+        );
+        assert_eq!(
+            validator
+                .get_motion_check_stats()
+                .borrow()
+                .last_motion_state_checks,
+            2
+        );
+        assert_eq!(validator.check_motions(&motions), expected);
+
+        let stats = validator.get_motion_check_stats().borrow();
+        assert_eq!(stats.checked_motion_count(), 4 + 4 + 1 + 2 + 4 + 1 + 4);
+    }
+}
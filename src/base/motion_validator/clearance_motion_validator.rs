@@ -0,0 +1,300 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+
+use super::{MotionCheckStats, MotionValidator};
+
+/// A motion validator that uses the clearance reported by the state validity checker to
+/// certify whole portions of a motion at once.
+///
+/// If a state has clearance `c` and the workspace moves by at most `L` for every unit of
+/// state space distance (`L` being the Lipschitz constant), every state within state space
+/// distance `c / L` is collision-free. The validator therefore advances along the motion by
+/// that amount at every step, which cannot miss obstacles regardless of how thin they are.
+///
+/// When the checker does not report a clearance, the remainder of the motion is checked at the
+/// resolution given by `StateSpace::valid_segment_count`, as in `DiscreteMotionValidator`.
+pub struct ClearanceMotionValidator {
+    state_space: Rc<dyn StateSpace>,
+    checker: Arc<dyn StateValidityChecker>,
+    stats: RefCell<MotionCheckStats>,
+    lipschitz_constant: f64,
+    min_clearance: f64,
+}
+
+impl ClearanceMotionValidator {
+    /// Set the Lipschitz constant relating state space distance to workspace displacement.
+    /// It must be strictly positive.
+    pub fn set_lipschitz_constant(&mut self, lipschitz_constant: f64) {
+        if lipschitz_constant <= 0.0 {
+            panic!("The Lipschitz constant must be strictly positive");
+        }
+        self.lipschitz_constant = lipschitz_constant;
+    }
+
+    pub fn get_lipschitz_constant(&self) -> f64 {
+        self.lipschitz_constant
+    }
+
+    /// Set the clearance below which a state is considered to be in contact. Motions that
+    /// reach such a state are rejected, which guarantees that the validator makes progress.
+    pub fn set_min_clearance(&mut self, min_clearance: f64) {
+        if min_clearance <= 0.0 {
+            panic!("The minimum clearance must be strictly positive");
+        }
+        self.min_clearance = min_clearance;
+    }
+
+    pub fn get_min_clearance(&self) -> f64 {
+        self.min_clearance
+    }
+
+    /// Walk along the motion from s1 to s2. Returns `None` if the whole motion is certified
+    /// valid, or the times of the last state known to be valid and of the state that was
+    /// rejected otherwise. The number of steps taken is added to `steps`.
+    fn walk(
+        &self,
+        s1: &StateId,
+        s2: &StateId,
+        stats: &mut MotionCheckStats,
+        steps: &mut u32,
+    ) -> Option<(f64, f64)> {
+        let distance = self.state_space.distance(s1, s2);
+        if distance <= f64::EPSILON {
+            return if stats.time_check(|| self.checker.is_valid(s2)) {
+                None
+            } else {
+                Some((0.0, 1.0))
+            };
+        }
+
+        let mut test = self.state_space.clone_state(s1);
+        let mut t = 0.0;
+        let mut last_valid = 0.0;
+
+        let result = loop {
+            let mut clearance = None;
+            let valid = stats.time_check(|| {
+                let (valid, c) = self.checker.is_valid_with_distance(&test);
+                clearance = c;
+                valid
+            });
+            if !valid {
+                break Some((last_valid, t));
+            }
+            last_valid = t;
+            if t >= 1.0 {
+                break None;
+            }
+
+            match clearance {
+                // the state is valid but in contact: it is the one rejected
+                Some(clearance) if clearance < self.min_clearance => break Some((last_valid, t)),
+                Some(clearance) => {
+                    let step = clearance / (self.lipschitz_constant * distance);
+                    t = f64::min(t + step, 1.0);
+                    *steps += 1;
+                    stats.time_interpolation(|| self.state_space.interpolate(s1, s2, t, &mut test));
+                }
+                None => break self.walk_discrete(s1, s2, t, &mut test, stats, steps),
+            }
+        };
+
+        self.state_space.free_state(&test);
+        result
+    }
+
+    /// Check the remainder of the motion, starting at time `t`, at the resolution of the state
+    /// space. `test` holds the state at time `t`, which is known to be valid.
+    fn walk_discrete(
+        &self,
+        s1: &StateId,
+        s2: &StateId,
+        t: f64,
+        test: &mut StateId,
+        stats: &mut MotionCheckStats,
+        steps: &mut u32,
+    ) -> Option<(f64, f64)> {
+        let nd = self.state_space.valid_segment_count(test, s2);
+        *steps += nd;
+
+        let mut last_valid = t;
+        for j in 1..=nd {
+            let tj = t + (1.0 - t) * j as f64 / nd as f64;
+            stats.time_interpolation(|| self.state_space.interpolate(s1, s2, tj, test));
+
+            if !stats.time_check(|| self.checker.is_valid(test)) {
+                return Some((last_valid, tj));
+            }
+            last_valid = tj;
+        }
+
+        if nd == 0 && !stats.time_check(|| self.checker.is_valid(s2)) {
+            return Some((last_valid, 1.0));
+        }
+        None
+    }
+
+    /// Walk along the motion, recording it in the statistics. Returns the time of the last
+    /// valid state if the motion is invalid.
+    fn check(&self, s1: &StateId, s2: &StateId) -> Option<f64> {
+        let mut stats = self.stats.borrow_mut();
+        stats.begin_motion();
+        let mut steps = 0;
+        let result = self.walk(s1, s2, &mut stats, &mut steps);
+        let failure = result.map(|(_, failure)| failure);
+        stats.record_motion(result.is_none(), steps, failure);
+        result.map(|(last_valid, _)| last_valid)
+    }
+}
+
+impl MotionValidator for ClearanceMotionValidator {
+    fn new(state_space: Rc<dyn StateSpace>, checker: Arc<dyn StateValidityChecker>) -> Self
+    where
+        Self: Sized,
+    {
+        Self {
+            state_space,
+            checker,
+            stats: RefCell::new(MotionCheckStats::default()),
+            lipschitz_constant: 1.0,
+            min_clearance: 1e-6,
+        }
+    }
+
+    fn get_motion_check_stats(&self) -> &RefCell<MotionCheckStats> {
+        &self.stats
+    }
+
+    fn check_motion(&self, s1: &StateId, s2: &StateId) -> bool {
+        self.check(s1, s2).is_none()
+    }
+
+    fn check_motion_with_last_valid(
+        &self,
+        s1: &StateId,
+        s2: &StateId,
+        last_valid: &mut (Option<StateId>, f64),
+    ) -> bool {
+        // assume motion starts in a valid configuration
+
+        match self.check(s1, s2) {
+            None => true,
+            Some(t) => {
+                last_valid.1 = t;
+                if let Some(s) = &mut last_valid.0 {
+                    self.state_space.interpolate(s1, s2, t, s);
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::AllValidStateValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    /// A 2D disc obstacle, reporting the exact distance to its boundary as clearance.
+    struct DiscChecker {
+        space: Rc<RealVectorStateSpace>,
+        center: DVector<f64>,
+        radius: f64,
+    }
+
+    impl StateValidityChecker for DiscChecker {
+        fn is_valid(&self, state: &StateId) -> bool {
+            self.clearance(state).unwrap() > 0.0
+        }
+
+        fn clearance(&self, state: &StateId) -> Option<f64> {
+            Some(
+                self.space
+                    .with_state(state, |s| (&s.values - &self.center).norm() - self.radius),
+            )
+        }
+    }
+
+    fn make_space() -> Rc<RealVectorStateSpace> {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        Rc::new(space)
+    }
+
+    fn make_state(space: &RealVectorStateSpace, values: [f64; 2]) -> StateId {
+        space.alloc_arena_state_with_value(RealVectorState {
+            values: DVector::from_row_slice(&values),
+        })
+    }
+
+    #[test]
+    fn test_clearance_motion_validator() {
+        let space = make_space();
+        let checker: Arc<dyn StateValidityChecker> = Arc::new(DiscChecker {
+            space: space.clone(),
+            center: DVector::from_vec(vec![0.5, 0.5]),
+            // thinner than the resolution used by the discrete validator
+            radius: 0.001,
+        });
+        let validator = ClearanceMotionValidator::new(space.clone(), checker);
+
+        let s1 = make_state(&space, [0.1, 0.5]);
+        let s2 = make_state(&space, [0.9, 0.5]);
+        let s3 = make_state(&space, [0.9, 0.6]);
+
+        assert!(!validator.check_motion(&s1, &s2));
+        assert!(validator.check_motion(&s1, &s3));
+
+        let mut last_valid = (Some(space.alloc_state()), 0.0);
+        assert!(!validator.check_motion_with_last_valid(&s1, &s2, &mut last_valid));
+        assert!(last_valid.1 < 0.5);
+        let x = space.with_state(last_valid.0.as_ref().unwrap(), |s| s.values[0]);
+        assert!(x < 0.5);
+
+        let stats = validator.get_motion_check_stats().borrow();
+        assert_eq!(stats.valid_motion_count(), 1);
+        assert_eq!(stats.invalid_motion_count(), 2);
+        assert!(stats.mean_failure_fraction() > 0.4 && stats.mean_failure_fraction() < 0.5);
+        assert_eq!(stats.failure_fraction_histogram[4], 2);
+        // the rejected state is recorded, which is the last valid one when it is in contact
+        assert!(stats.mean_failure_fraction() >= last_valid.1);
+    }
+
+    #[test]
+    fn test_clearance_motion_validator_fallback() {
+        let space = make_space();
+        let mut validator =
+            ClearanceMotionValidator::new(space.clone(), Arc::new(AllValidStateValidityChecker));
+
+        let s1 = make_state(&space, [0.1, 0.5]);
+        let s2 = make_state(&space, [0.9, 0.5]);
+
+        assert!(validator.check_motion(&s1, &s2));
+
+        // the start state, then every state at the resolution of the space
+        let nd = space.valid_segment_count(&s1, &s2);
+        let mut stats = validator.get_motion_check_stats().borrow().clone();
+        assert_eq!(stats.state_checks, nd as u64 + 1);
+        assert_eq!(stats.last_motion_state_checks, nd as u64 + 1);
+        assert_eq!(stats.segment_count_histogram.get(&64), Some(&1));
+
+        let other = stats.clone();
+        stats.merge(&other);
+        assert_eq!(stats.valid_motion_count(), 2);
+        assert_eq!(stats.state_checks, 2 * (nd as u64 + 1));
+        assert_eq!(stats.segment_count_histogram.get(&64), Some(&2));
+
+        validator.reset_motion_counter();
+        assert_eq!(validator.get_motion_check_stats().borrow().state_checks, 0);
+    }
+}
@@ -31,13 +31,17 @@ impl MotionValidator for DiscreteMotionValidator {
         s1: &crate::base::state_allocator::StateId,
         s2: &crate::base::state_allocator::StateId,
     ) -> bool {
-        if (self.checker.is_valid(s2)) {
-            self.stats.borrow_mut().invalid += 1;
+        let mut stats = self.stats.borrow_mut();
+        stats.begin_motion();
+        let nd = self.state_space.valid_segment_count(s1, s2);
+
+        if !stats.time_check(|| self.checker.is_valid(s2)) {
+            stats.record_motion(false, nd, Some(1.0));
             return false;
         }
 
         let mut result = true;
-        let nd = self.state_space.valid_segment_count(s1, s2);
+        let mut failure = None;
 
         let mut pos = VecDeque::new();
         if nd >= 2 {
@@ -47,11 +51,14 @@ impl MotionValidator for DiscreteMotionValidator {
 
             while let Some((first, second)) = pos.pop_front() {
                 let mid = (first + second) / 2;
-                self.state_space
-                    .interpolate(s1, s2, mid as f64 / nd as f64, &mut test);
+                stats.time_interpolation(|| {
+                    self.state_space
+                        .interpolate(s1, s2, mid as f64 / nd as f64, &mut test)
+                });
 
-                if !self.checker.is_valid(&test) {
+                if !stats.time_check(|| self.checker.is_valid(&test)) {
                     result = false;
+                    failure = Some(mid as f64 / nd as f64);
                     break;
                 }
 
@@ -66,11 +73,7 @@ impl MotionValidator for DiscreteMotionValidator {
             self.state_space.free_state(&test);
         }
 
-        if result {
-            self.stats.borrow_mut().valid += 1;
-        } else {
-            self.stats.borrow_mut().invalid += 1;
-        }
+        stats.record_motion(result, nd, failure);
         result
     }
 
@@ -82,17 +85,24 @@ impl MotionValidator for DiscreteMotionValidator {
     ) -> bool {
         // assume motion starts in a valid configuration
 
+        let mut stats = self.stats.borrow_mut();
+        stats.begin_motion();
+
         let mut result = true;
+        let mut failure = None;
         let nd = self.state_space.valid_segment_count(s1, s2);
 
         if nd > 1 {
             let mut test = self.state_space.alloc_state();
 
             for j in 1..nd {
-                self.state_space
-                    .interpolate(s1, s2, j as f64 / nd as f64, &mut test);
+                stats.time_interpolation(|| {
+                    self.state_space
+                        .interpolate(s1, s2, j as f64 / nd as f64, &mut test)
+                });
 
-                if !self.checker.is_valid(&test) {
+                if !stats.time_check(|| self.checker.is_valid(&test)) {
+                    failure = Some(j as f64 / nd as f64);
                     last_valid.1 = (j - 1) as f64 / nd as f64;
                     if let Some(s) = &mut last_valid.0 {
                         self.state_space.interpolate(s1, s2, last_valid.1, s);
@@ -106,20 +116,67 @@ impl MotionValidator for DiscreteMotionValidator {
             self.state_space.free_state(&test);
         }
 
-        if result && !self.checker.is_valid(s2) {
+        if result && !stats.time_check(|| self.checker.is_valid(s2)) {
             last_valid.1 = (nd - 1) as f64 / nd as f64;
             if let Some(s) = &mut last_valid.0 {
                 self.state_space.interpolate(s1, s2, last_valid.1, s);
             }
+            failure = Some(1.0);
             result = false;
         }
 
-        if result {
-            self.stats.borrow_mut().valid += 1;
-        } else {
-            self.stats.borrow_mut().invalid += 1;
-        }
-
+        stats.record_motion(result, nd, failure);
         result
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::FunctionalStateValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_discrete_motion_validator() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        // a wall at x in [0.45, 0.55], below y = 0.8
+        let checker = {
+            let space = space.clone();
+            Arc::new(FunctionalStateValidityChecker::new(Box::new(move |s| {
+                space.with_state(s, |s| {
+                    !(s.values[0] > 0.45 && s.values[0] < 0.55 && s.values[1] < 0.8)
+                })
+            })))
+        };
+        let validator = DiscreteMotionValidator::new(space.clone(), checker);
+
+        assert!(validator.check_motion(&state(0.1, 0.9), &state(0.9, 0.9)));
+        assert!(!validator.check_motion(&state(0.1, 0.1), &state(0.9, 0.1)));
+        assert!(!validator.check_motion(&state(0.1, 0.1), &state(0.5, 0.1)));
+
+        let mut last_valid = (Some(space.alloc_state()), 0.0);
+        let (s1, s2) = (state(0.1, 0.1), state(0.9, 0.1));
+        assert!(!validator.check_motion_with_last_valid(&s1, &s2, &mut last_valid));
+        assert!((last_valid.1 - 0.4375).abs() < 0.02);
+
+        let stats = validator.get_motion_check_stats().borrow();
+        assert_eq!(stats.valid_motion_count(), 1);
+        assert_eq!(stats.invalid_motion_count(), 3);
+        // motions with an invalid end state are counted with their segments too
+        assert!(!stats.segment_count_histogram.contains_key(&0));
+    }
+}
@@ -1,14 +1,46 @@
-use std::{cell::RefCell, rc::Rc, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
 
 use super::{state_allocator::StateId, statespace::StateSpace};
 use crate::base::state_validity_checker::StateValidityChecker;
 
+pub mod batch_motion_validator;
+pub mod clearance_motion_validator;
 pub mod discrete_motion_validator;
 
+/// Number of bins of the histogram of the times at which invalid motions failed.
+pub const FAILURE_FRACTION_BINS: usize = 10;
+
+/// Statistics gathered by a motion validator.
+///
+/// Besides the number of valid and invalid motions, this records the number of state validity
+/// checks, the time spent in the validity checker and in interpolation, how many segments
+/// motions were split into, and where along invalid motions the failure was found.
 #[derive(Clone, Debug, Default)]
 pub struct MotionCheckStats {
     pub valid: u32,
     pub invalid: u32,
+    /// Total number of state validity checks.
+    pub state_checks: u64,
+    /// Number of state validity checks done for the most recent motion, or for the most recent
+    /// batch by validators sharing checks between the motions of a batch.
+    pub last_motion_state_checks: u64,
+    pub checker_time: Duration,
+    pub interpolation_time: Duration,
+    /// Number of motions for each number of segments, bucketed by powers of two: the motions
+    /// split in `n` segments are counted in bucket `n.next_power_of_two() / 2` (bucket 0
+    /// holding the motions that were not split).
+    pub segment_count_histogram: BTreeMap<u32, u64>,
+    /// Number of invalid motions whose failure was found in each tenth of the motion.
+    pub failure_fraction_histogram: [u64; FAILURE_FRACTION_BINS],
+    pub failure_fraction_sum: f64,
 }
 
 impl MotionCheckStats {
@@ -32,9 +64,101 @@ impl MotionCheckStats {
         }
     }
 
+    /// Average time, between 0 and 1, at which invalid motions were found to be invalid.
+    pub fn mean_failure_fraction(&self) -> f64 {
+        if self.invalid == 0 {
+            0.0
+        } else {
+            self.failure_fraction_sum / self.invalid as f64
+        }
+    }
+
+    pub fn state_checks_per_motion(&self) -> f64 {
+        match self.checked_motion_count() {
+            0 => 0.0,
+            n => self.state_checks as f64 / n as f64,
+        }
+    }
+
     pub fn reset(&mut self) {
-        self.valid = 0;
-        self.invalid = 0;
+        *self = Self::default();
+    }
+
+    /// Add the statistics of `other` to these, e.g. to aggregate the statistics of several
+    /// validators.
+    pub fn merge(&mut self, other: &MotionCheckStats) {
+        self.valid += other.valid;
+        self.invalid += other.invalid;
+        self.state_checks += other.state_checks;
+        self.last_motion_state_checks = other.last_motion_state_checks;
+        self.checker_time += other.checker_time;
+        self.interpolation_time += other.interpolation_time;
+        for (bucket, count) in &other.segment_count_histogram {
+            *self.segment_count_histogram.entry(*bucket).or_default() += count;
+        }
+        for (bin, count) in self
+            .failure_fraction_histogram
+            .iter_mut()
+            .zip(&other.failure_fraction_histogram)
+        {
+            *bin += count;
+        }
+        self.failure_fraction_sum += other.failure_fraction_sum;
+    }
+
+    /// Start recording a new motion.
+    pub fn begin_motion(&mut self) {
+        self.last_motion_state_checks = 0;
+    }
+
+    /// Run a state validity check, counting it and its duration.
+    pub fn time_check(&mut self, check: impl FnOnce() -> bool) -> bool {
+        let start = Instant::now();
+        let valid = check();
+        self.checker_time += start.elapsed();
+        self.state_checks += 1;
+        self.last_motion_state_checks += 1;
+        valid
+    }
+
+    /// Run an interpolation, counting its duration.
+    pub fn time_interpolation(&mut self, interpolate: impl FnOnce()) {
+        let start = Instant::now();
+        interpolate();
+        self.interpolation_time += start.elapsed();
+    }
+
+    /// Record the outcome of a motion split in `segments` segments. For invalid motions,
+    /// `failure_fraction` is the time (between 0 and 1) of the invalid state that was found.
+    pub fn record_motion(&mut self, valid: bool, segments: u32, failure_fraction: Option<f64>) {
+        let bucket = segments.checked_next_power_of_two().unwrap_or(1 << 31) / 2;
+        *self.segment_count_histogram.entry(bucket).or_default() += 1;
+
+        if valid {
+            self.valid += 1;
+        } else {
+            self.invalid += 1;
+            let fraction = failure_fraction.unwrap_or(1.0).clamp(0.0, 1.0);
+            let bin =
+                ((fraction * FAILURE_FRACTION_BINS as f64) as usize).min(FAILURE_FRACTION_BINS - 1);
+            self.failure_fraction_histogram[bin] += 1;
+            self.failure_fraction_sum += fraction;
+        }
+    }
+
+    /// Emit the statistics as a `tracing` event.
+    pub fn log(&self) {
+        info!(
+            valid = self.valid,
+            invalid = self.invalid,
+            state_checks = self.state_checks,
+            checker_time_us = self.checker_time.as_micros() as u64,
+            interpolation_time_us = self.interpolation_time.as_micros() as u64,
+            mean_failure_fraction = self.mean_failure_fraction(),
+            segment_count_histogram = ?self.segment_count_histogram,
+            failure_fraction_histogram = ?self.failure_fraction_histogram,
+            "motion check statistics"
+        );
     }
 }
 
@@ -70,6 +194,17 @@ pub trait MotionValidator {
         last_valid: &mut (Option<StateId>, f64),
     ) -> bool;
 
+    /// Check a batch of motions, each of which is assumed to start in a valid state. Motions
+    /// may share their states. Returns the validity of every motion, in order.
+    ///
+    /// This function updates the number of valid and invalid segments.
+    fn check_motions(&self, motions: &[(&StateId, &StateId)]) -> Vec<bool> {
+        motions
+            .iter()
+            .map(|&(s1, s2)| self.check_motion(s1, s2))
+            .collect()
+    }
+
     fn reset_motion_counter(&mut self) {
         self.get_motion_check_stats().borrow_mut().reset();
     }
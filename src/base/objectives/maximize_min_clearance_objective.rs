@@ -0,0 +1,116 @@
+use std::{rc::Rc, sync::Arc};
+
+use super::{for_each_motion_state, Cost, OptimizationObjective, OptimizationObjectiveData};
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+
+/// The objective of maximizing the smallest clearance along the path, as reported by
+/// `StateValidityChecker::clearance`, to keep the path away from obstacles.
+///
+/// Costs are clearances: larger costs are better, and combining costs keeps the smallest one.
+/// States whose clearance the checker does not compute are given a clearance of 0.
+pub struct MaximizeMinClearanceObjective {
+    data: OptimizationObjectiveData,
+    checker: Arc<dyn StateValidityChecker>,
+}
+
+impl MaximizeMinClearanceObjective {
+    pub fn new(state_space: Rc<dyn StateSpace>, checker: Arc<dyn StateValidityChecker>) -> Self {
+        let mut data = OptimizationObjectiveData::new(state_space, "Maximize Minimum Clearance");
+        // no clearance is large enough by default
+        data.threshold = Cost(f64::INFINITY);
+        Self { data, checker }
+    }
+}
+
+impl OptimizationObjective for MaximizeMinClearanceObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, state: &StateId) -> Cost {
+        Cost(self.checker.clearance(state).unwrap_or(0.0))
+    }
+
+    /// The smallest clearance of the states along the motion, at the resolution of the space.
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        let space = self.data.state_space.as_ref();
+        let mut cost = self.identity_cost();
+        for_each_motion_state(space, s1, s2, |s| {
+            cost = self.combine_costs(cost, self.state_cost(s));
+        });
+        cost
+    }
+
+    fn is_cost_better_than(&self, c1: Cost, c2: Cost) -> bool {
+        c1.value() > c2.value()
+    }
+
+    fn combine_costs(&self, c1: Cost, c2: Cost) -> Cost {
+        Cost(c1.value().min(c2.value()))
+    }
+
+    fn identity_cost(&self) -> Cost {
+        Cost(f64::INFINITY)
+    }
+
+    fn infinite_cost(&self) -> Cost {
+        Cost(f64::NEG_INFINITY)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    /// The clearance from a wall at y = 0.
+    struct WallChecker {
+        space: Rc<RealVectorStateSpace>,
+    }
+
+    impl StateValidityChecker for WallChecker {
+        fn is_valid(&self, state: &StateId) -> bool {
+            self.clearance(state).unwrap() > 0.0
+        }
+
+        fn clearance(&self, state: &StateId) -> Option<f64> {
+            Some(self.space.with_state(state, |s| s.values[1]))
+        }
+    }
+
+    #[test]
+    fn test_maximize_min_clearance() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+        let checker = Arc::new(WallChecker {
+            space: space.clone(),
+        });
+        let objective = MaximizeMinClearanceObjective::new(space.clone(), checker);
+
+        let path = [state(0.0, 0.5), state(0.5, 0.2), state(1.0, 0.8)];
+        assert_eq!(objective.motion_cost(&path[1], &path[2]), Cost(0.2));
+        assert_eq!(objective.path_cost(&path), Cost(0.2));
+        assert!(objective.is_cost_better_than(Cost(0.5), Cost(0.2)));
+        assert_eq!(objective.better_cost(Cost(0.5), Cost(0.2)), Cost(0.5));
+        assert!(objective.is_finite(Cost(0.0)));
+        assert!(!objective.is_satisfied(Cost(1.0)));
+    }
+}
@@ -0,0 +1,89 @@
+use std::rc::Rc;
+
+use super::{motion_length, Cost, OptimizationObjective, OptimizationObjectiveData, StateCostFn};
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+
+/// The objective of minimizing the mechanical work done along the path, for a state cost such
+/// as the elevation: only increases of the state cost are accrued, as moving downhill costs no
+/// work. A small multiple of the path length is added so that, among paths doing the same
+/// work, shorter ones are preferred.
+pub struct MechanicalWorkOptimizationObjective {
+    data: OptimizationObjectiveData,
+    state_cost: StateCostFn,
+    path_length_weight: f64,
+}
+
+impl MechanicalWorkOptimizationObjective {
+    pub fn new(state_space: Rc<dyn StateSpace>, state_cost: StateCostFn) -> Self {
+        Self {
+            data: OptimizationObjectiveData::new(state_space, "Mechanical Work"),
+            state_cost,
+            path_length_weight: 0.00001,
+        }
+    }
+
+    pub fn set_path_length_weight(&mut self, weight: f64) {
+        if weight < 0.0 {
+            panic!("The path length weight must be non-negative");
+        }
+        self.path_length_weight = weight;
+    }
+
+    pub fn get_path_length_weight(&self) -> f64 {
+        self.path_length_weight
+    }
+}
+
+impl OptimizationObjective for MechanicalWorkOptimizationObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, state: &StateId) -> Cost {
+        (self.state_cost)(state)
+    }
+
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        let work = (self.state_cost(s2).value() - self.state_cost(s1).value()).max(0.0);
+        let length = motion_length(self.data.state_space.as_ref(), s1, s2);
+        Cost(work + self.path_length_weight * length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_mechanical_work() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 10.0);
+        space.add_dimension(None, 0.0, 10.0);
+        let space = Rc::new(space);
+        let state = |x: f64, elevation: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, elevation]),
+            })
+        };
+        let cost_space = space.clone();
+        let elevation: StateCostFn =
+            Box::new(move |s| Cost(cost_space.with_state(s, |s| s.values[1])));
+        let mut objective = MechanicalWorkOptimizationObjective::new(space.clone(), elevation);
+        objective.set_path_length_weight(0.0);
+
+        let (low, high) = (state(0.0, 1.0), state(0.0, 4.0));
+        assert_eq!(objective.motion_cost(&low, &high), Cost(3.0));
+        assert_eq!(objective.motion_cost(&high, &low), Cost(0.0));
+
+        objective.set_path_length_weight(0.5);
+        assert_eq!(objective.get_path_length_weight(), 0.5);
+        assert_eq!(objective.motion_cost(&high, &low), Cost(1.5));
+    }
+}
@@ -0,0 +1,211 @@
+use std::{fmt, rc::Rc};
+
+use downcast_rs::{impl_downcast, Downcast};
+
+use super::{state_allocator::StateId, statespace::StateSpace};
+
+pub mod maximize_min_clearance_objective;
+pub mod mechanical_work_optimization_objective;
+pub mod multi_optimization_objective;
+pub mod path_length_optimization_objective;
+pub mod state_cost_integral_objective;
+
+/// A user-defined cost of being in a state.
+pub type StateCostFn = Box<dyn Fn(&StateId) -> Cost>;
+
+/// The cost of a state, a motion or a path, as defined by an `OptimizationObjective`.
+///
+/// Costs are only meaningful to the objective that computed them: comparing and combining them
+/// must go through the objective (`is_cost_better_than`, `combine_costs`, ...), since not all
+/// objectives minimize a sum.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Cost(pub f64);
+
+impl Cost {
+    pub fn new(value: f64) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The data shared by all optimization objectives.
+#[derive(Debug)]
+pub struct OptimizationObjectiveData {
+    pub state_space: Rc<dyn StateSpace>,
+    pub description: String,
+    /// Costs at least as good as this one satisfy the objective.
+    pub threshold: Cost,
+}
+
+impl OptimizationObjectiveData {
+    pub fn new(state_space: Rc<dyn StateSpace>, description: &str) -> Self {
+        Self {
+            state_space,
+            description: description.to_string(),
+            threshold: Cost(0.0),
+        }
+    }
+}
+
+/// The objective of an optimizing planner, defining the cost of states and motions and how
+/// costs are combined and compared.
+///
+/// The default methods describe the most common objective: minimizing the sum of the motion
+/// costs along the path, with the cost of states ignored.
+pub trait OptimizationObjective: Downcast {
+    fn objective_data(&self) -> &OptimizationObjectiveData;
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData;
+
+    /// The cost of being in a state.
+    fn state_cost(&self, state: &StateId) -> Cost;
+
+    /// The cost of the motion from `s1` to `s2`.
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost;
+
+    fn get_description(&self) -> &str {
+        &self.objective_data().description
+    }
+
+    fn get_state_space(&self) -> &Rc<dyn StateSpace> {
+        &self.objective_data().state_space
+    }
+
+    fn get_cost_threshold(&self) -> Cost {
+        self.objective_data().threshold
+    }
+
+    fn set_cost_threshold(&mut self, threshold: Cost) {
+        self.objective_data_mut().threshold = threshold;
+    }
+
+    /// Whether a path of the given cost satisfies the objective, i.e. is better than the cost
+    /// threshold. Planners stop optimizing once their solution satisfies the objective.
+    fn is_satisfied(&self, cost: Cost) -> bool {
+        self.is_cost_better_than(cost, self.get_cost_threshold())
+    }
+
+    /// Whether `c1` is strictly better than `c2`.
+    fn is_cost_better_than(&self, c1: Cost, c2: Cost) -> bool {
+        c1.value() < c2.value()
+    }
+
+    fn is_cost_equivalent_to(&self, c1: Cost, c2: Cost) -> bool {
+        !self.is_cost_better_than(c1, c2) && !self.is_cost_better_than(c2, c1)
+    }
+
+    /// Whether the cost is better than the infinite cost.
+    fn is_finite(&self, cost: Cost) -> bool {
+        self.is_cost_better_than(cost, self.infinite_cost())
+    }
+
+    /// The better of two costs.
+    fn better_cost(&self, c1: Cost, c2: Cost) -> Cost {
+        if self.is_cost_better_than(c2, c1) {
+            c2
+        } else {
+            c1
+        }
+    }
+
+    /// The cost of two consecutive parts of a path.
+    fn combine_costs(&self, c1: Cost, c2: Cost) -> Cost {
+        Cost(c1.value() + c2.value())
+    }
+
+    /// The cost that leaves any cost unchanged when combined with it, e.g. the cost of an empty
+    /// path.
+    fn identity_cost(&self) -> Cost {
+        Cost(0.0)
+    }
+
+    /// A cost worse than any other.
+    fn infinite_cost(&self) -> Cost {
+        Cost(f64::INFINITY)
+    }
+
+    /// The cost of starting a path at `state`.
+    fn initial_cost(&self, _state: &StateId) -> Cost {
+        self.identity_cost()
+    }
+
+    /// The cost of ending a path at `state`.
+    fn terminal_cost(&self, _state: &StateId) -> Cost {
+        self.identity_cost()
+    }
+
+    /// A heuristic estimate of the cost of the best path from `state` to `goal`. It must never
+    /// overestimate that cost for the planners using it to remain optimal; the default, the
+    /// identity cost, is always admissible.
+    fn cost_to_go(&self, _state: &StateId, _goal: &StateId) -> Cost {
+        self.identity_cost()
+    }
+
+    /// A heuristic estimate of the cost of the motion from `s1` to `s2`, which must not
+    /// overestimate `motion_cost`.
+    fn motion_cost_heuristic(&self, _s1: &StateId, _s2: &StateId) -> Cost {
+        self.identity_cost()
+    }
+
+    /// The cost of the path going through the given states, including its initial and
+    /// terminal costs.
+    fn path_cost(&self, states: &[StateId]) -> Cost {
+        let (Some(first), Some(last)) = (states.first(), states.last()) else {
+            return self.identity_cost();
+        };
+        let motions = states
+            .windows(2)
+            .map(|w| self.motion_cost(&w[0], &w[1]))
+            .fold(self.initial_cost(first), |c1, c2| {
+                self.combine_costs(c1, c2)
+            });
+        self.combine_costs(motions, self.terminal_cost(last))
+    }
+}
+
+impl_downcast!(OptimizationObjective);
+
+/// The distance from `s1` to `s2`, which may be the same state.
+pub(crate) fn motion_length(space: &dyn StateSpace, s1: &StateId, s2: &StateId) -> f64 {
+    if s1 == s2 {
+        // spaces cannot borrow the same state twice
+        0.0
+    } else {
+        space.distance(s1, s2)
+    }
+}
+
+/// Call `f` on the states along the motion from `s1` to `s2`, in order and both included, at
+/// the resolution of the space (see `StateSpace::valid_segment_count`). Returns the number of
+/// segments the motion was split in.
+pub(crate) fn for_each_motion_state(
+    space: &dyn StateSpace,
+    s1: &StateId,
+    s2: &StateId,
+    mut f: impl FnMut(&StateId),
+) -> u32 {
+    f(s1);
+    let nd = if s1 == s2 {
+        1
+    } else {
+        space.valid_segment_count(s1, s2).max(1)
+    };
+    if nd >= 2 {
+        let mut state = space.alloc_state();
+        for i in 1..nd {
+            space.interpolate(s1, s2, i as f64 / nd as f64, &mut state);
+            f(&state);
+        }
+        space.free_state(&state);
+    }
+    f(s2);
+    nd
+}
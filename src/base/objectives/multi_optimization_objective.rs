@@ -0,0 +1,287 @@
+use std::{cell::Cell, cmp::Ordering, rc::Rc};
+
+use super::{Cost, OptimizationObjective, OptimizationObjectiveData};
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+
+struct Component {
+    objective: Rc<dyn OptimizationObjective>,
+    weight: Cell<f64>,
+    priority: u32,
+}
+
+/// An objective combining several objectives, e.g. the path length and the clearance.
+///
+/// Costs are weighted sums of the costs of the components. Components that maximize their cost
+/// (larger costs being better, as with `MaximizeMinClearanceObjective`) enter the sum negated,
+/// so that the combined cost is always minimized. The weights can be changed between queries,
+/// even once the objective is shared with a planner.
+///
+/// Only `path_cost` gives the exact cost of a path, each component combining its own motion
+/// costs before they are weighted. `combine_costs` and `identity_cost` add costs, as a weighted
+/// sum cannot be split back into its components: accumulating motion costs with them agrees
+/// with `path_cost` only when every component adds its costs too, as the path length does.
+///
+/// Components can also be given priorities, 0 being the most important. A `Cost` being a single
+/// value, the costs planners compare with `is_cost_better_than` are weighted sums over all the
+/// components whatever their priorities; only `is_path_better_than` compares paths
+/// lexicographically, by the weighted sum of the components of each priority in turn.
+///
+/// The cost threshold is minus infinity, so that no path satisfies the objective by default.
+pub struct MultiOptimizationObjective {
+    data: OptimizationObjectiveData,
+    components: Vec<Component>,
+}
+
+impl MultiOptimizationObjective {
+    pub fn new(state_space: Rc<dyn StateSpace>) -> Self {
+        let mut data = OptimizationObjectiveData::new(state_space, "Multi-Objective");
+        // the weighted sums may be negative, so no cost is small enough by default
+        data.threshold = Cost(f64::NEG_INFINITY);
+        Self {
+            data,
+            components: Vec::new(),
+        }
+    }
+
+    /// Add an objective of the highest priority.
+    pub fn add_objective(&mut self, objective: Rc<dyn OptimizationObjective>, weight: f64) {
+        self.add_objective_with_priority(objective, weight, 0);
+    }
+
+    pub fn add_objective_with_priority(
+        &mut self,
+        objective: Rc<dyn OptimizationObjective>,
+        weight: f64,
+        priority: u32,
+    ) {
+        check_weight(weight);
+        self.components.push(Component {
+            objective,
+            weight: Cell::new(weight),
+            priority,
+        });
+    }
+
+    pub fn get_objective_count(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn get_objective(&self, index: usize) -> &Rc<dyn OptimizationObjective> {
+        &self.components[index].objective
+    }
+
+    pub fn get_objective_weight(&self, index: usize) -> f64 {
+        self.components[index].weight.get()
+    }
+
+    pub fn set_objective_weight(&self, index: usize, weight: f64) {
+        check_weight(weight);
+        self.components[index].weight.set(weight);
+    }
+
+    pub fn get_objective_priority(&self, index: usize) -> u32 {
+        self.components[index].priority
+    }
+
+    /// The cost of the path according to each component, unweighted, e.g. to report the
+    /// length and the clearance of a solution.
+    pub fn component_costs(&self, states: &[StateId]) -> Vec<Cost> {
+        self.components
+            .iter()
+            .map(|c| c.objective.path_cost(states))
+            .collect()
+    }
+
+    /// The weighted cost of the path for each priority, from the most important one.
+    pub fn priority_costs(&self, states: &[StateId]) -> Vec<Cost> {
+        let costs = self.component_costs(states);
+        let mut priorities: Vec<u32> = self.components.iter().map(|c| c.priority).collect();
+        priorities.sort_unstable();
+        priorities.dedup();
+        priorities
+            .into_iter()
+            .map(|priority| {
+                self.weighted_sum(
+                    self.components
+                        .iter()
+                        .zip(&costs)
+                        .filter(|(c, _)| c.priority == priority)
+                        .map(|(c, cost)| (c, *cost)),
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the path through the states `a` is better than the one through `b`, comparing
+    /// the costs of each priority in turn.
+    pub fn is_path_better_than(&self, a: &[StateId], b: &[StateId]) -> bool {
+        let ordering = self
+            .priority_costs(a)
+            .into_iter()
+            .zip(self.priority_costs(b))
+            .map(|(ca, cb)| ca.value().total_cmp(&cb.value()))
+            .find(|o| o.is_ne());
+        ordering == Some(Ordering::Less)
+    }
+
+    /// The weighted sum of the costs of the components, negating those of the components that
+    /// maximize their cost.
+    fn weighted_sum<'a>(&self, costs: impl Iterator<Item = (&'a Component, Cost)>) -> Cost {
+        Cost(
+            costs
+                .map(|(c, cost)| {
+                    let maximized = c.objective.is_cost_better_than(Cost(1.0), Cost(0.0));
+                    let value = if maximized {
+                        -cost.value()
+                    } else {
+                        cost.value()
+                    };
+                    c.weight.get() * value
+                })
+                .sum(),
+        )
+    }
+
+    fn combine(&self, cost: impl Fn(&dyn OptimizationObjective) -> Cost) -> Cost {
+        self.weighted_sum(
+            self.components
+                .iter()
+                .map(|c| (c, cost(c.objective.as_ref()))),
+        )
+    }
+}
+
+fn check_weight(weight: f64) {
+    if weight < 0.0 {
+        panic!("The weight of an objective must be non-negative");
+    }
+}
+
+impl OptimizationObjective for MultiOptimizationObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, state: &StateId) -> Cost {
+        self.combine(|o| o.state_cost(state))
+    }
+
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        self.combine(|o| o.motion_cost(s1, s2))
+    }
+
+    /// Compares the weighted sums of all the components, ignoring their priorities: use
+    /// `is_path_better_than` to compare paths priority by priority.
+    fn is_cost_better_than(&self, c1: Cost, c2: Cost) -> bool {
+        c1.value() < c2.value()
+    }
+
+    /// The weighted sum of the path costs of the components, each combining its own motion
+    /// costs (e.g. the clearance of a path is that of its worst motion, not their sum).
+    fn path_cost(&self, states: &[StateId]) -> Cost {
+        self.combine(|o| o.path_cost(states))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::objectives::{
+        maximize_min_clearance_objective::MaximizeMinClearanceObjective,
+        path_length_optimization_objective::PathLengthOptimizationObjective,
+    };
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::StateValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::sync::Arc;
+
+    /// The clearance from a wall at y = 0.
+    struct WallChecker {
+        space: Rc<RealVectorStateSpace>,
+    }
+
+    impl StateValidityChecker for WallChecker {
+        fn is_valid(&self, state: &StateId) -> bool {
+            self.clearance(state).unwrap() > 0.0
+        }
+
+        fn clearance(&self, state: &StateId) -> Option<f64> {
+            Some(self.space.with_state(state, |s| s.values[1]))
+        }
+    }
+
+    #[test]
+    fn test_length_and_clearance() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 4.0);
+        space.add_dimension(None, 0.0, 4.0);
+        space.state_space_data_mut().longest_valid_segment = 0.05;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+        // along the wall, or with a detour away from it
+        let short = [state(0.0, 1.0), state(4.0, 1.0)];
+        let safe = [
+            state(0.0, 1.0),
+            state(0.0, 2.0),
+            state(4.0, 2.0),
+            state(4.0, 1.0),
+        ];
+
+        let length = Rc::new(PathLengthOptimizationObjective::new(space.clone()));
+        let checker = Arc::new(WallChecker {
+            space: space.clone(),
+        });
+        let clearance = Rc::new(MaximizeMinClearanceObjective::new(space.clone(), checker));
+        let mut objective = MultiOptimizationObjective::new(space.clone());
+        objective.add_objective(length, 1.0);
+        objective.add_objective(clearance, 1.0);
+        assert_eq!(objective.get_objective_count(), 2);
+        assert_eq!(objective.get_cost_threshold(), Cost(f64::NEG_INFINITY));
+        assert!(!objective.is_satisfied(Cost(-1000.0)));
+
+        assert_eq!(objective.component_costs(&safe), vec![Cost(6.0), Cost(1.0)]);
+        // 4 - 1 against 6 - 1
+        assert_eq!(objective.path_cost(&short), Cost(3.0));
+        assert!(objective.path_cost(&short).value() < objective.path_cost(&safe).value());
+        assert_eq!(objective.motion_cost(&safe[1], &safe[2]), Cost(2.0));
+
+        objective.set_objective_weight(1, 5.0);
+        assert_eq!(objective.get_objective_weight(1), 5.0);
+        // 4 - 5 against 6 - 5, the detour not changing the clearance at the end states
+        assert!(objective.path_cost(&short).value() < objective.path_cost(&safe).value());
+
+        // a detour away from the wall, longer but with more clearance
+        let direct = [state(0.0, 3.0), state(2.0, 2.5), state(4.0, 3.0)];
+        let detour = [
+            state(0.0, 3.0),
+            state(0.0, 4.0),
+            state(4.0, 4.0),
+            state(4.0, 3.0),
+        ];
+        objective.set_objective_weight(1, 1.0);
+        assert!(objective.is_path_better_than(&direct, &detour));
+
+        // the clearance first, then the length
+        let mut lexicographic = MultiOptimizationObjective::new(space.clone());
+        lexicographic.add_objective_with_priority(objective.get_objective(0).clone(), 1.0, 1);
+        lexicographic.add_objective_with_priority(objective.get_objective(1).clone(), 1.0, 0);
+        assert_eq!(lexicographic.get_objective_priority(0), 1);
+        assert_eq!(
+            lexicographic.priority_costs(&detour),
+            vec![Cost(-3.0), Cost(6.0)]
+        );
+        assert!(lexicographic.is_path_better_than(&detour, &direct));
+        assert!(!lexicographic.is_path_better_than(&direct, &detour));
+    }
+}
@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use super::{motion_length, Cost, OptimizationObjective, OptimizationObjectiveData};
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+
+/// The objective of minimizing the length of the path, as measured by `StateSpace::distance`.
+/// This is the objective used by optimizing planners when none is given.
+pub struct PathLengthOptimizationObjective {
+    data: OptimizationObjectiveData,
+}
+
+impl PathLengthOptimizationObjective {
+    pub fn new(state_space: Rc<dyn StateSpace>) -> Self {
+        Self {
+            data: OptimizationObjectiveData::new(state_space, "Path Length"),
+        }
+    }
+}
+
+impl OptimizationObjective for PathLengthOptimizationObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, _state: &StateId) -> Cost {
+        self.identity_cost()
+    }
+
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        Cost(motion_length(self.data.state_space.as_ref(), s1, s2))
+    }
+
+    /// The distance to the goal, which no path can be shorter than.
+    fn cost_to_go(&self, state: &StateId, goal: &StateId) -> Cost {
+        Cost(motion_length(self.data.state_space.as_ref(), state, goal))
+    }
+
+    fn motion_cost_heuristic(&self, s1: &StateId, s2: &StateId) -> Cost {
+        self.motion_cost(s1, s2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_path_length_objective() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 10.0);
+        space.add_dimension(None, 0.0, 10.0);
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+        let path = [state(0.0, 0.0), state(3.0, 4.0), state(3.0, 6.0)];
+
+        let mut objective = PathLengthOptimizationObjective::new(space.clone());
+        assert_eq!(objective.get_description(), "Path Length");
+        assert_eq!(objective.motion_cost(&path[0], &path[1]), Cost(5.0));
+        assert_eq!(objective.motion_cost(&path[1], &path[1]), Cost(0.0));
+        assert_eq!(objective.state_cost(&path[0]), objective.identity_cost());
+        assert_eq!(objective.path_cost(&path), Cost(7.0));
+        assert_eq!(objective.path_cost(&[]), Cost(0.0));
+        assert!(objective.cost_to_go(&path[0], &path[2]).value() <= 7.0);
+
+        assert!(objective.is_finite(Cost(7.0)));
+        assert!(!objective.is_finite(objective.infinite_cost()));
+        assert_eq!(objective.better_cost(Cost(7.0), Cost(5.0)), Cost(5.0));
+        assert!(objective.is_cost_equivalent_to(Cost(5.0), Cost(5.0)));
+
+        // by default, no path is good enough
+        assert!(!objective.is_satisfied(Cost(7.0)));
+        objective.set_cost_threshold(Cost(7.5));
+        assert!(objective.is_satisfied(Cost(7.0)));
+        assert!(!objective.is_satisfied(Cost(8.0)));
+    }
+}
@@ -0,0 +1,108 @@
+use std::rc::Rc;
+
+use super::{
+    for_each_motion_state, motion_length, Cost, OptimizationObjective, OptimizationObjectiveData,
+    StateCostFn,
+};
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+
+/// The objective of minimizing the integral of a state cost along the path, e.g. the exposure
+/// to a hazard. Motion costs are computed with the trapezoidal rule.
+pub struct StateCostIntegralObjective {
+    data: OptimizationObjectiveData,
+    state_cost: StateCostFn,
+    interpolate_motion_cost: bool,
+}
+
+impl StateCostIntegralObjective {
+    /// With `interpolate_motion_cost`, the cost of a motion is integrated over the states
+    /// interpolated at the resolution of the space (see `StateSpace::valid_segment_count`),
+    /// which is more accurate but more expensive than only using the end states.
+    pub fn new(
+        state_space: Rc<dyn StateSpace>,
+        state_cost: StateCostFn,
+        interpolate_motion_cost: bool,
+    ) -> Self {
+        Self {
+            data: OptimizationObjectiveData::new(state_space, "State Cost Integral"),
+            state_cost,
+            interpolate_motion_cost,
+        }
+    }
+
+    pub fn is_motion_cost_interpolation_enabled(&self) -> bool {
+        self.interpolate_motion_cost
+    }
+
+    fn trapezoid(c1: Cost, c2: Cost, dist: f64) -> Cost {
+        Cost(0.5 * dist * (c1.value() + c2.value()))
+    }
+}
+
+impl OptimizationObjective for StateCostIntegralObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, state: &StateId) -> Cost {
+        (self.state_cost)(state)
+    }
+
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        let space = self.data.state_space.as_ref();
+        let length = motion_length(space, s1, s2);
+        if !self.interpolate_motion_cost {
+            return Self::trapezoid(self.state_cost(s1), self.state_cost(s2), length);
+        }
+
+        let mut costs = Vec::new();
+        let nd = for_each_motion_state(space, s1, s2, |s| costs.push(self.state_cost(s)));
+        let segment = length / nd as f64;
+        costs
+            .windows(2)
+            .map(|c| Self::trapezoid(c[0], c[1], segment))
+            .fold(self.identity_cost(), |c1, c2| self.combine_costs(c1, c2))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_state_cost_integral() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 2.0);
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        let space = Rc::new(space);
+        let state = |x: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x]),
+            })
+        };
+        let (s1, s2) = (state(0.0), state(2.0));
+
+        // the cost x², whose integral from 0 to 2 is 8/3
+        let make = |interpolate| {
+            let cost_space = space.clone();
+            let state_cost: StateCostFn =
+                Box::new(move |s| Cost(cost_space.with_state(s, |s| s.values[0].powi(2))));
+            StateCostIntegralObjective::new(space.clone(), state_cost, interpolate)
+        };
+
+        let objective = make(false);
+        assert!(!objective.is_motion_cost_interpolation_enabled());
+        assert_eq!(objective.motion_cost(&s1, &s2), Cost(4.0));
+        let objective = make(true);
+        assert!((objective.motion_cost(&s1, &s2).value() - 8.0 / 3.0).abs() < 1e-3);
+        assert_eq!(objective.motion_cost(&s2, &s2), Cost(0.0));
+    }
+}
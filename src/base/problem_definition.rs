@@ -0,0 +1,123 @@
+use std::rc::Rc;
+
+use tracing::info;
+
+use super::{
+    objectives::{
+        path_length_optimization_objective::PathLengthOptimizationObjective, Cost,
+        OptimizationObjective,
+    },
+    state_allocator::StateId,
+    statespace::StateSpace,
+};
+
+/// The definition of a motion planning problem: the start states, the goal state, and the
+/// objective optimizing planners use to rank solutions.
+pub struct ProblemDefinition {
+    state_space: Rc<dyn StateSpace>,
+    start_states: Vec<StateId>,
+    goal_state: Option<StateId>,
+    optimization_objective: Option<Rc<dyn OptimizationObjective>>,
+}
+
+impl ProblemDefinition {
+    pub fn new(state_space: Rc<dyn StateSpace>) -> Self {
+        Self {
+            state_space,
+            start_states: Vec::new(),
+            goal_state: None,
+            optimization_objective: None,
+        }
+    }
+
+    pub fn get_state_space(&self) -> &Rc<dyn StateSpace> {
+        &self.state_space
+    }
+
+    pub fn add_start_state(&mut self, state: StateId) {
+        self.start_states.push(state);
+    }
+
+    pub fn clear_start_states(&mut self) {
+        self.start_states.clear();
+    }
+
+    pub fn get_start_states(&self) -> &[StateId] {
+        &self.start_states
+    }
+
+    pub fn get_start_state_count(&self) -> usize {
+        self.start_states.len()
+    }
+
+    pub fn set_goal_state(&mut self, state: StateId) {
+        self.goal_state = Some(state);
+    }
+
+    pub fn get_goal_state(&self) -> Option<&StateId> {
+        self.goal_state.as_ref()
+    }
+
+    /// Replace the start states by `start` and set the goal state.
+    pub fn set_start_and_goal_states(&mut self, start: StateId, goal: StateId) {
+        self.clear_start_states();
+        self.add_start_state(start);
+        self.set_goal_state(goal);
+    }
+
+    pub fn set_optimization_objective(&mut self, objective: Rc<dyn OptimizationObjective>) {
+        self.optimization_objective = Some(objective);
+    }
+
+    pub fn get_optimization_objective(&self) -> Option<&Rc<dyn OptimizationObjective>> {
+        self.optimization_objective.as_ref()
+    }
+
+    pub fn has_optimization_objective(&self) -> bool {
+        self.optimization_objective.is_some()
+    }
+
+    /// The optimization objective, defaulting to the path length when none was set. This is
+    /// the objective optimizing planners should use.
+    pub fn get_optimization_objective_or_default(&mut self) -> Rc<dyn OptimizationObjective> {
+        self.optimization_objective
+            .get_or_insert_with(|| {
+                info!("No optimization objective specified. Defaulting to optimizing path length");
+                Rc::new(PathLengthOptimizationObjective::new(
+                    self.state_space.clone(),
+                ))
+            })
+            .clone()
+    }
+
+    /// Whether a solution of the given cost is good enough to stop optimizing: without an
+    /// optimization objective, any solution is.
+    pub fn is_cost_satisfactory(&self, cost: Cost) -> bool {
+        self.optimization_objective
+            .as_ref()
+            .is_none_or(|objective| objective.is_satisfied(cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::RealVectorStateSpace;
+
+    #[test]
+    fn test_default_optimization_objective() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        let space: Rc<dyn StateSpace> = Rc::new(space);
+        let mut pdef = ProblemDefinition::new(space.clone());
+        pdef.set_start_and_goal_states(space.alloc_state(), space.alloc_state());
+        assert_eq!(pdef.get_start_state_count(), 1);
+        assert!(pdef.get_goal_state().is_some());
+
+        assert!(pdef.is_cost_satisfactory(Cost(1.0)));
+        let objective = pdef.get_optimization_objective_or_default();
+        assert!(objective.is::<PathLengthOptimizationObjective>());
+        assert!(pdef.has_optimization_objective());
+        assert!(!pdef.is_cost_satisfactory(Cost(1.0)));
+    }
+}
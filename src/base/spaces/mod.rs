@@ -1,2 +1,3 @@
 pub mod real_vector_bounds;
 pub mod real_vector_state_space;
+pub mod se2_state_space;
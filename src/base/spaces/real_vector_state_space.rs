@@ -149,6 +149,18 @@ impl StateSpace for RealVectorStateSpace {
     fn free_state(&self, state: &StateId) {
         self.free_arena_state(state);
     }
+
+    #[state_id_into_inner]
+    fn copy_to_reals(&self, reals: &mut Vec<f64>, source: &StateId) {
+        reals.clear();
+        reals.extend(source.values.iter());
+    }
+
+    #[state_id_into_inner]
+    fn copy_from_reals(&self, destination: &mut StateId, reals: &Vec<f64>) {
+        let dimension = destination.values.len();
+        destination.values.copy_from_slice(&reals[..dimension]);
+    }
 }
 
 pub struct RealVectorStateSampler {
@@ -0,0 +1,278 @@
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use sbmp_derive::{state_id_into_inner, WithStateAlloc, WithStateSpaceData};
+
+use crate::base::state::State;
+use crate::base::state_allocator::{StateAllocator, StateId};
+use crate::base::state_sampler::StateSampler;
+use crate::base::statespace::{HasStateSpaceData, StateSpace, StateSpaceCommonData};
+use crate::prelude::CanStateAllocateTrait;
+use crate::randomness::RNG;
+
+use super::real_vector_bounds::RealVectorBounds;
+
+/// Wrap an angle to `[-pi, pi)`.
+pub fn normalize_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+/// Signed difference `to - from` along the shortest arc, in `[-pi, pi)`.
+pub fn angle_difference(from: f64, to: f64) -> f64 {
+    normalize_angle(to - from)
+}
+
+/// A state space representing a position in the plane and an orientation.
+///
+/// As in the compound `R^2 x SO(2)` formulation, the distance is the Euclidean distance
+/// between the positions plus the (weighted) length of the shortest arc between the
+/// orientations.
+#[derive(Debug, WithStateSpaceData, WithStateAlloc)]
+#[state_alloc(state_type = "SE2State")]
+pub struct SE2StateSpace {
+    state_space_data: StateSpaceCommonData,
+    state_allocator: StateAllocator<SE2State>,
+    pub(crate) bounds: RealVectorBounds,
+    pub(crate) rotation_weight: f64,
+}
+
+impl Default for SE2StateSpace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SE2State {
+    pub x: f64,
+    pub y: f64,
+    pub yaw: f64,
+}
+
+impl State for SE2State {}
+
+impl SE2State {
+    pub fn new(x: f64, y: f64, yaw: f64) -> Self {
+        Self { x, y, yaw }
+    }
+}
+
+impl SE2StateSpace {
+    pub fn new() -> Self {
+        Self {
+            state_space_data: StateSpaceCommonData::default(),
+            state_allocator: Self::new_state_allocator(),
+            bounds: RealVectorBounds::new(2),
+            rotation_weight: 0.5,
+        }
+    }
+
+    /// Set the bounds of the position component.
+    pub fn set_bounds(&mut self, bounds: RealVectorBounds) {
+        bounds.check();
+        assert_eq!(
+            bounds.low.len(),
+            2,
+            "Bounds do not match dimension of state space"
+        );
+        self.bounds = bounds;
+    }
+
+    pub fn get_bounds(&self) -> &RealVectorBounds {
+        &self.bounds
+    }
+
+    /// Set the weight of the orientation in the distance function.
+    pub fn set_rotation_weight(&mut self, weight: f64) {
+        if weight < 0.0 {
+            panic!("The rotation weight cannot be negative");
+        }
+        self.rotation_weight = weight;
+    }
+
+    pub fn get_rotation_weight(&self) -> f64 {
+        self.rotation_weight
+    }
+}
+
+impl StateSpace for SE2StateSpace {
+    fn get_dimension(&self) -> u32 {
+        3
+    }
+
+    fn get_maximum_extent(&self) -> f64 {
+        (&self.bounds.high - &self.bounds.low).norm() + self.rotation_weight * PI
+    }
+
+    fn get_measure(&self) -> f64 {
+        self.bounds.get_volume() * 2.0 * PI
+    }
+
+    #[state_id_into_inner]
+    fn enforce_bounds(&self, state: &mut StateId) {
+        state.x = state.x.clamp(self.bounds.low[0], self.bounds.high[0]);
+        state.y = state.y.clamp(self.bounds.low[1], self.bounds.high[1]);
+        state.yaw = normalize_angle(state.yaw);
+    }
+
+    #[state_id_into_inner]
+    fn satisfies_bounds(&self, state: &StateId) -> bool {
+        [state.x, state.y]
+            .iter()
+            .zip(&self.bounds.low)
+            .zip(&self.bounds.high)
+            .all(|((s, low), high)| s - f64::EPSILON > *low && s + f64::EPSILON < *high)
+            && (-PI..PI).contains(&state.yaw)
+    }
+
+    #[state_id_into_inner]
+    fn distance(&self, state1: &StateId, state2: &StateId) -> f64 {
+        (state1.x - state2.x).hypot(state1.y - state2.y)
+            + self.rotation_weight * angle_difference(state1.yaw, state2.yaw).abs()
+    }
+
+    #[state_id_into_inner]
+    fn equal_states(&self, state1: &StateId, state2: &StateId) -> bool {
+        (state1.x - state2.x).abs() <= f64::EPSILON * 2.0
+            && (state1.y - state2.y).abs() <= f64::EPSILON * 2.0
+            && angle_difference(state1.yaw, state2.yaw).abs() <= f64::EPSILON * 2.0
+    }
+
+    #[state_id_into_inner]
+    fn interpolate(&self, from: &StateId, to: &StateId, t: f64, state: &mut StateId) {
+        state.x = from.x + (to.x - from.x) * t;
+        state.y = from.y + (to.y - from.y) * t;
+        state.yaw = normalize_angle(from.yaw + angle_difference(from.yaw, to.yaw) * t);
+    }
+
+    /// Check the bounds and derive the longest valid segment from the maximum extent.
+    fn setup(&mut self) {
+        self.bounds.check();
+        let longest_valid_segment =
+            self.get_maximum_extent() * self.get_longest_valid_segment_fraction();
+        if longest_valid_segment < f64::EPSILON {
+            panic!("The longest valid segment for the state space must be positive");
+        }
+        self.state_space_data_mut().longest_valid_segment = longest_valid_segment;
+    }
+
+    #[state_id_into_inner]
+    fn copy_state(&self, destination: &mut StateId, source: &StateId) {
+        *destination = source.clone();
+    }
+
+    fn alloc_state(&self) -> StateId where {
+        self.alloc_arena_state_with_value(SE2State::new(0.0, 0.0, 0.0))
+    }
+
+    fn free_state(&self, state: &StateId) {
+        self.free_arena_state(state);
+    }
+
+    #[state_id_into_inner]
+    fn copy_to_reals(&self, reals: &mut Vec<f64>, source: &StateId) {
+        reals.clear();
+        reals.extend([source.x, source.y, source.yaw]);
+    }
+
+    #[state_id_into_inner]
+    fn copy_from_reals(&self, destination: &mut StateId, reals: &Vec<f64>) {
+        destination.x = reals[0];
+        destination.y = reals[1];
+        destination.yaw = reals[2];
+    }
+}
+
+pub struct SE2StateSampler {
+    space: Rc<dyn StateSpace>,
+    rng: RNG,
+}
+
+impl StateSampler for SE2StateSampler {
+    fn from_state_space(space: Rc<dyn StateSpace>) -> Self {
+        Self {
+            space,
+            rng: RNG::new(),
+        }
+    }
+
+    fn sample_uniform(&mut self, state: &mut StateId) {
+        let space = self.space.downcast_ref::<SE2StateSpace>().unwrap();
+        let (low, high) = (&space.bounds.low, &space.bounds.high);
+
+        space.with_state_mut(state, |state| {
+            state.x = self.rng.uniform_real(low[0], high[0]);
+            state.y = self.rng.uniform_real(low[1], high[1]);
+            state.yaw = self.rng.uniform_real(-PI, PI);
+        });
+    }
+
+    fn sample_uniform_near(&mut self, state: &mut StateId, near: &StateId, distance: f64) {
+        let space = self.space.downcast_ref::<SE2StateSpace>().unwrap();
+        let (low, high) = (&space.bounds.low, &space.bounds.high);
+
+        space.with_2states_mut(state, near, |state, near| {
+            state.x = self.rng.uniform_real(
+                f64::max(low[0], near.x - distance),
+                f64::min(high[0], near.x + distance),
+            );
+            state.y = self.rng.uniform_real(
+                f64::max(low[1], near.y - distance),
+                f64::min(high[1], near.y + distance),
+            );
+            state.yaw = normalize_angle(
+                near.yaw + self.rng.uniform_real(-distance.min(PI), distance.min(PI)),
+            );
+        });
+    }
+
+    fn sample_gaussian(&mut self, state: &mut StateId, mean: &StateId, std_dev: f64) {
+        let space = self.space.downcast_ref::<SE2StateSpace>().unwrap();
+        let (low, high) = (&space.bounds.low, &space.bounds.high);
+
+        space.with_2states_mut(state, mean, |state, mean| {
+            state.x = self.rng.gaussian(mean.x, std_dev).clamp(low[0], high[0]);
+            state.y = self.rng.gaussian(mean.y, std_dev).clamp(low[1], high[1]);
+            state.yaw = normalize_angle(self.rng.gaussian(mean.yaw, std_dev));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use statrs::assert_almost_eq;
+
+    #[test]
+    fn test_se2_distance_and_interpolate() {
+        let mut space = SE2StateSpace::new();
+        let mut bounds = RealVectorBounds::new(2);
+        bounds.set_low(-1.0);
+        bounds.set_high(1.0);
+        space.set_bounds(bounds);
+        space.setup();
+        let expected = space.get_maximum_extent() * space.get_longest_valid_segment_fraction();
+        assert_almost_eq!(space.get_longest_valid_segment_length(), expected, 1e-12);
+        let space = Rc::new(space);
+
+        // the shortest arc between the orientations wraps around pi
+        let state1 = space.alloc_arena_state_with_value(SE2State::new(0.0, 0.0, 3.0));
+        let state2 = space.alloc_arena_state_with_value(SE2State::new(0.3, 0.4, -3.0));
+        let arc = 2.0 * PI - 6.0;
+        assert_almost_eq!(space.distance(&state1, &state2), 0.5 + 0.5 * arc, 1e-12);
+
+        let mut result = space.alloc_state();
+        space.interpolate(&state1, &state2, 0.5, &mut result);
+        let result = space.clone_state_inner_value(&result);
+        assert_almost_eq!(result.x, 0.15, 1e-12);
+        assert_almost_eq!(result.y, 0.2, 1e-12);
+        assert_almost_eq!(result.yaw.abs(), PI, 1e-12);
+
+        let mut sampler = SE2StateSampler::from_state_space(space.clone());
+        let mut state = space.alloc_state();
+        for _ in 0..100 {
+            sampler.sample_uniform(&mut state);
+            assert!(space.satisfies_bounds(&state));
+        }
+    }
+}
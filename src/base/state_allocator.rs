@@ -12,6 +12,14 @@ use crate::datastructure::arena::{Arena, Index};
 #[derive(Debug)]
 pub struct StateId(Index);
 
+impl StateId {
+    /// A copyable key identifying this state, e.g. to index maps or nearest neighbour
+    /// structures.
+    pub fn key(&self) -> StateKey {
+        StateKey(self.0)
+    }
+}
+
 impl From<Index> for StateId {
     fn from(index: Index) -> Self {
         Self(index)
@@ -26,6 +34,22 @@ impl PartialEq for StateId {
 
 impl Eq for StateId {}
 
+/// A key identifying a state, which, unlike `StateId`, does not own the state: copies of a key
+/// can be kept in several places while the `StateId` it was taken from owns the state.
+///
+/// Keys are compared and hashed as the states they identify. A key whose state was freed
+/// refers to no state; using it to access the state panics.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct StateKey(Index);
+
+impl StateKey {
+    /// Run `closure` on a handle to the state identified by this key. The handle only lives
+    /// for the duration of the call.
+    pub fn with_id<R>(&self, closure: impl FnOnce(&StateId) -> R) -> R {
+        closure(&StateId(self.0))
+    }
+}
+
 /// A state allocator that allocates states of type `T`.
 /// This allocator uses an `Arena` to store the states.
 #[derive(Debug)]
@@ -0,0 +1,23 @@
+use std::rc::Rc;
+
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+
+use super::StateValidityChecker;
+
+/// A state validity checker that only accepts states within the bounds of the state space,
+/// as reported by `StateSpace::satisfies_bounds`.
+pub struct BoundsValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+}
+
+impl BoundsValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>) -> Self {
+        Self { state_space }
+    }
+}
+
+impl StateValidityChecker for BoundsValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.state_space.satisfies_bounds(state)
+    }
+}
@@ -0,0 +1,304 @@
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+
+use crate::base::{state_allocator::StateId, statespace::StateSpace};
+use crate::datastructure::lru_cache::LruCache;
+
+use super::{ClearanceComputationType, StateValidityChecker};
+
+/// Maps a state to the real values used to compute its cache key.
+pub type StateProjectionFn = Box<dyn Fn(&StateId, &mut Vec<f64>)>;
+
+/// Default number of cells remembered by a `CachingStateValidityChecker`.
+pub const DEFAULT_CACHE_CAPACITY: usize = 100_000;
+
+/// Hit and miss counts of a `CachingStateValidityChecker`.
+#[derive(Clone, Debug, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn lookup_count(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_fraction(&self) -> f64 {
+        if self.hits == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.lookup_count() as f64
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.evictions = 0;
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheEntry {
+    valid: Option<bool>,
+    clearance: Option<Option<f64>>,
+}
+
+/// A state validity checker that memoizes the results of another checker.
+///
+/// States are mapped to real values (by `StateSpace::copy_to_reals`, or by a user supplied
+/// projection) and discretized into cells of a fixed size; all states falling into the same
+/// cell share the cached result. This is an approximation: a cell that straddles an obstacle
+/// boundary reports the result of whichever state was checked first. When exact match mode
+/// is enabled, results are only shared between states with bitwise identical values.
+///
+/// Clearances are only cached in exact match mode: the clearance of another state of the cell
+/// could overestimate that of the state, while callers such as `ClearanceMotionValidator`
+/// rely on it being a lower bound.
+///
+/// The cache is bounded; the least recently used cell is evicted once it is full.
+pub struct CachingStateValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    checker: Arc<dyn StateValidityChecker>,
+    projection: Option<StateProjectionFn>,
+    cell_size: f64,
+    exact_match: bool,
+    cache: RefCell<LruCache<Vec<i64>, CacheEntry>>,
+    stats: RefCell<CacheStats>,
+}
+
+impl CachingStateValidityChecker {
+    pub fn new(
+        state_space: Rc<dyn StateSpace>,
+        checker: Arc<dyn StateValidityChecker>,
+        cell_size: f64,
+    ) -> Self {
+        if cell_size <= 0.0 {
+            panic!("The cell size must be strictly positive");
+        }
+        Self {
+            state_space,
+            checker,
+            projection: None,
+            cell_size,
+            exact_match: false,
+            cache: RefCell::new(LruCache::new(DEFAULT_CACHE_CAPACITY)),
+            stats: RefCell::new(CacheStats::default()),
+        }
+    }
+
+    /// Use `projection` instead of `StateSpace::copy_to_reals` to compute cache keys.
+    /// This clears the cache.
+    pub fn set_projection(&mut self, projection: StateProjectionFn) {
+        self.projection = Some(projection);
+        self.clear();
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f64) {
+        if cell_size <= 0.0 {
+            panic!("The cell size must be strictly positive");
+        }
+        self.cell_size = cell_size;
+        self.clear();
+    }
+
+    pub fn get_cell_size(&self) -> f64 {
+        self.cell_size
+    }
+
+    /// Only share cached results between states with identical values.
+    pub fn set_exact_match(&mut self, exact_match: bool) {
+        self.exact_match = exact_match;
+        self.clear();
+    }
+
+    pub fn is_exact_match(&self) -> bool {
+        self.exact_match
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.cache.get_mut().set_capacity(capacity);
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.cache.borrow().capacity()
+    }
+
+    /// Number of cells currently cached.
+    pub fn cached_count(&self) -> usize {
+        self.cache.borrow().len()
+    }
+
+    pub fn get_cache_stats(&self) -> &RefCell<CacheStats> {
+        &self.stats
+    }
+
+    /// Drop every cached result. This is needed whenever the environment changes.
+    pub fn clear(&self) {
+        self.cache.borrow_mut().clear();
+    }
+
+    fn key(&self, state: &StateId) -> Vec<i64> {
+        let mut reals = Vec::new();
+        match &self.projection {
+            Some(projection) => projection(state, &mut reals),
+            None => self.state_space.copy_to_reals(&mut reals, state),
+        }
+
+        if self.exact_match {
+            reals.iter().map(|v| v.to_bits() as i64).collect()
+        } else {
+            reals
+                .iter()
+                .map(|v| (v / self.cell_size).floor() as i64)
+                .collect()
+        }
+    }
+
+    /// Look up the entry of `state`, computing the missing parts with `fill`.
+    fn lookup<T>(
+        &self,
+        state: &StateId,
+        get: impl Fn(&CacheEntry) -> Option<T>,
+        fill: impl FnOnce(&mut CacheEntry) -> T,
+    ) -> T {
+        let key = self.key(state);
+        let mut cache = self.cache.borrow_mut();
+
+        if let Some(value) = cache.get(&key).and_then(&get) {
+            self.stats.borrow_mut().hits += 1;
+            return value;
+        }
+        self.stats.borrow_mut().misses += 1;
+
+        match cache.get_mut(&key) {
+            Some(entry) => fill(entry),
+            None => {
+                let mut entry = CacheEntry::default();
+                let value = fill(&mut entry);
+                if cache.insert(key, entry) {
+                    self.stats.borrow_mut().evictions += 1;
+                }
+                value
+            }
+        }
+    }
+}
+
+impl StateValidityChecker for CachingStateValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.lookup(
+            state,
+            |entry| entry.valid,
+            |entry| *entry.valid.insert(self.checker.is_valid(state)),
+        )
+    }
+
+    fn specs(&self) -> ClearanceComputationType {
+        self.checker.specs()
+    }
+
+    fn has_valid_direction_computation(&self) -> bool {
+        self.checker.has_valid_direction_computation()
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        if !self.exact_match {
+            return self.checker.clearance(state);
+        }
+        self.lookup(
+            state,
+            |entry| entry.clearance,
+            |entry| *entry.clearance.insert(self.checker.clearance(state)),
+        )
+    }
+
+    fn clearance_with_state(
+        &self,
+        state: &StateId,
+        valid_state: &mut StateId,
+        valid_state_available: &mut bool,
+    ) -> Option<f64> {
+        // the valid state depends on the exact state, so this is never cached
+        self.checker
+            .clearance_with_state(state, valid_state, valid_state_available)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::cell::Cell;
+
+    /// Counts the calls made to it; states with a negative first value are invalid.
+    struct CountingChecker {
+        space: Rc<RealVectorStateSpace>,
+        calls: Cell<u32>,
+    }
+
+    impl StateValidityChecker for CountingChecker {
+        fn is_valid(&self, state: &StateId) -> bool {
+            self.calls.set(self.calls.get() + 1);
+            self.space.with_state(state, |s| s.values[0] >= 0.0)
+        }
+
+        fn clearance(&self, state: &StateId) -> Option<f64> {
+            Some(self.space.with_state(state, |s| s.values[0]))
+        }
+    }
+
+    fn make_state(space: &RealVectorStateSpace, values: [f64; 2]) -> StateId {
+        space.alloc_arena_state_with_value(RealVectorState {
+            values: DVector::from_row_slice(&values),
+        })
+    }
+
+    #[test]
+    fn test_caching_state_validity_checker() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, -1.0, 1.0);
+        space.add_dimension(None, -1.0, 1.0);
+        let space = Rc::new(space);
+
+        let inner = Arc::new(CountingChecker {
+            space: space.clone(),
+            calls: Cell::new(0),
+        });
+        let mut checker = CachingStateValidityChecker::new(space.clone(), inner.clone(), 0.1);
+
+        let s1 = make_state(&space, [0.51, 0.51]);
+        let s1_near = make_state(&space, [0.52, 0.53]);
+        let s2 = make_state(&space, [-0.5, 0.5]);
+
+        assert!(checker.is_valid(&s1));
+        assert!(checker.is_valid(&s1_near));
+        assert!(!checker.is_valid(&s2));
+        assert_eq!(inner.calls.get(), 2);
+        {
+            let stats = checker.get_cache_stats().borrow();
+            assert_eq!(stats.hits, 1);
+            assert_eq!(stats.misses, 2);
+        }
+        // states sharing a cell do not share their clearance
+        assert_eq!(checker.clearance(&s1), Some(0.51));
+        assert_eq!(checker.clearance(&s1_near), Some(0.52));
+
+        checker.set_exact_match(true);
+        assert!(checker.is_valid(&s1));
+        assert!(checker.is_valid(&s1_near));
+        assert!(checker.is_valid(&s1));
+        assert_eq!(inner.calls.get(), 4);
+
+        checker.set_capacity(1);
+        assert_eq!(checker.cached_count(), 1);
+        assert!(!checker.is_valid(&s2));
+        assert!(checker.is_valid(&s1));
+        assert_eq!(inner.calls.get(), 6);
+        assert_eq!(checker.get_cache_stats().borrow().evictions, 2);
+    }
+}
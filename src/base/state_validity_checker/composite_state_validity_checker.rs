@@ -0,0 +1,305 @@
+use std::{cell::RefCell, sync::Arc};
+
+use crate::base::state_allocator::StateId;
+
+use super::StateValidityChecker;
+
+/// Minimum of the clearances reported by `checkers`, or `None` if any of them cannot compute
+/// a clearance (in which case no lower bound is known).
+fn min_clearance<'a>(
+    checkers: impl Iterator<Item = &'a Arc<dyn StateValidityChecker>>,
+    state: &StateId,
+) -> Option<f64> {
+    let mut result: Option<f64> = None;
+    for checker in checkers {
+        let clearance = checker.clearance(state)?;
+        result = Some(result.map_or(clearance, |c| c.min(clearance)));
+    }
+    result
+}
+
+/// A state is valid if it is valid for every one of the given checkers.
+///
+/// The clearance is the minimum of the clearances of the checkers, and is only available if
+/// all of them report one.
+#[derive(Default)]
+pub struct AllOf {
+    checkers: Vec<Arc<dyn StateValidityChecker>>,
+}
+
+impl AllOf {
+    pub fn new(checkers: Vec<Arc<dyn StateValidityChecker>>) -> Self {
+        Self { checkers }
+    }
+
+    pub fn add(&mut self, checker: Arc<dyn StateValidityChecker>) {
+        self.checkers.push(checker);
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkers.is_empty()
+    }
+}
+
+impl StateValidityChecker for AllOf {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.checkers.iter().all(|c| c.is_valid(state))
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        min_clearance(self.checkers.iter(), state)
+    }
+}
+
+/// A state is valid if it is valid for at least one of the given checkers.
+///
+/// The clearance is the largest clearance reported by a checker for which the state is valid,
+/// since the state remains valid for that checker within its clearance.
+#[derive(Default)]
+pub struct AnyOf {
+    checkers: Vec<Arc<dyn StateValidityChecker>>,
+}
+
+impl AnyOf {
+    pub fn new(checkers: Vec<Arc<dyn StateValidityChecker>>) -> Self {
+        Self { checkers }
+    }
+
+    pub fn add(&mut self, checker: Arc<dyn StateValidityChecker>) {
+        self.checkers.push(checker);
+    }
+
+    pub fn len(&self) -> usize {
+        self.checkers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.checkers.is_empty()
+    }
+}
+
+impl StateValidityChecker for AnyOf {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.checkers.iter().any(|c| c.is_valid(state))
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        self.checkers
+            .iter()
+            .filter_map(|c| match c.is_valid_with_distance(state) {
+                (true, clearance) => clearance,
+                (false, _) => None,
+            })
+            .reduce(f64::max)
+    }
+}
+
+/// A state is valid if it is invalid for the given checker. No clearance is available.
+pub struct Not {
+    checker: Arc<dyn StateValidityChecker>,
+}
+
+impl Not {
+    pub fn new(checker: Arc<dyn StateValidityChecker>) -> Self {
+        Self { checker }
+    }
+}
+
+impl StateValidityChecker for Not {
+    fn is_valid(&self, state: &StateId) -> bool {
+        !self.checker.is_valid(state)
+    }
+}
+
+/// Counters of a single stage of a `StagedStateValidityChecker`.
+#[derive(Clone, Debug, Default)]
+pub struct StageStats {
+    pub checked: u64,
+    pub rejected: u64,
+}
+
+impl StageStats {
+    pub fn rejection_fraction(&self) -> f64 {
+        if self.rejected == 0 {
+            0.0
+        } else {
+            self.rejected as f64 / self.checked as f64
+        }
+    }
+}
+
+struct ValidityStage {
+    name: String,
+    checker: Arc<dyn StateValidityChecker>,
+    cost: f64,
+}
+
+/// A state validity checker made of named stages, run from the cheapest to the most expensive.
+/// A state is valid if every stage accepts it; checking stops at the first stage that rejects
+/// it, and that stage is recorded.
+///
+/// As for `AllOf`, the clearance is the minimum over the stages.
+#[derive(Default)]
+pub struct StagedStateValidityChecker {
+    stages: Vec<ValidityStage>,
+    stats: RefCell<Vec<StageStats>>,
+    last_rejected: RefCell<Option<usize>>,
+}
+
+impl StagedStateValidityChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stage with the given relative cost. Stages are kept sorted by cost; stages of
+    /// equal cost run in the order they were added.
+    pub fn add_stage(&mut self, name: &str, checker: Arc<dyn StateValidityChecker>, cost: f64) {
+        let position = self.stages.partition_point(|s| s.cost <= cost);
+        self.stages.insert(
+            position,
+            ValidityStage {
+                name: name.to_string(),
+                checker,
+                cost,
+            },
+        );
+        self.stats.get_mut().insert(position, StageStats::default());
+    }
+
+    pub fn get_stage_count(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Names of the stages, in the order they are run.
+    pub fn get_stage_names(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|s| s.name.as_str())
+    }
+
+    /// Run the stages on `state` and return the name of the first one that rejects it, or
+    /// `None` if the state is valid.
+    pub fn rejecting_stage(&self, state: &StateId) -> Option<&str> {
+        let mut stats = self.stats.borrow_mut();
+        let rejected = self
+            .stages
+            .iter()
+            .zip(stats.iter_mut())
+            .position(|(s, st)| {
+                st.checked += 1;
+                let valid = s.checker.is_valid(state);
+                if !valid {
+                    st.rejected += 1;
+                }
+                !valid
+            });
+        *self.last_rejected.borrow_mut() = rejected;
+        rejected.map(|i| self.stages[i].name.as_str())
+    }
+
+    /// The stage that rejected the most recently checked state, if it was rejected.
+    pub fn last_rejecting_stage(&self) -> Option<&str> {
+        self.last_rejected
+            .borrow()
+            .map(|i| self.stages[i].name.as_str())
+    }
+
+    /// Per-stage statistics, in the order the stages are run.
+    pub fn get_stage_stats(&self) -> Vec<(&str, StageStats)> {
+        self.stages
+            .iter()
+            .zip(self.stats.borrow().iter())
+            .map(|(s, st)| (s.name.as_str(), st.clone()))
+            .collect()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats
+            .borrow_mut()
+            .iter_mut()
+            .for_each(|st| *st = StageStats::default());
+        *self.last_rejected.borrow_mut() = None;
+    }
+}
+
+impl StateValidityChecker for StagedStateValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.rejecting_stage(state).is_none()
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        min_clearance(self.stages.iter().map(|s| &s.checker), state)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::{
+        bounds_validity_checker::BoundsValidityChecker, FunctionalStateValidityChecker,
+    };
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::rc::Rc;
+
+    fn half_plane(space: &Rc<RealVectorStateSpace>, min_x: f64) -> Arc<dyn StateValidityChecker> {
+        let space = space.clone();
+        Arc::new(FunctionalStateValidityChecker::new(Box::new(
+            move |state| space.with_state(state, |s| s.values[0] > min_x),
+        )))
+    }
+
+    #[test]
+    fn test_composite_checkers() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        let space = Rc::new(space);
+
+        let state = |x: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x]),
+            })
+        };
+        let (s_low, s_mid, s_high, s_out) = (state(0.1), state(0.5), state(0.9), state(1.5));
+
+        let all = AllOf::new(vec![half_plane(&space, 0.3), half_plane(&space, 0.7)]);
+        assert!(!all.is_valid(&s_mid));
+        assert!(all.is_valid(&s_high));
+
+        let any = AnyOf::new(vec![half_plane(&space, 0.3), half_plane(&space, 0.7)]);
+        assert!(any.is_valid(&s_mid));
+        assert!(!any.is_valid(&s_low));
+
+        let not = Not::new(half_plane(&space, 0.3));
+        assert!(not.is_valid(&s_low));
+        assert!(!not.is_valid(&s_mid));
+
+        let mut staged = StagedStateValidityChecker::new();
+        staged.add_stage("collision", half_plane(&space, 0.3), 10.0);
+        staged.add_stage(
+            "bounds",
+            Arc::new(BoundsValidityChecker::new(space.clone())),
+            1.0,
+        );
+        assert_eq!(
+            staged.get_stage_names().collect::<Vec<_>>(),
+            vec!["bounds", "collision"]
+        );
+
+        assert!(staged.is_valid(&s_mid));
+        assert_eq!(staged.last_rejecting_stage(), None);
+        assert_eq!(staged.rejecting_stage(&s_out), Some("bounds"));
+        assert!(!staged.is_valid(&s_low));
+        assert_eq!(staged.last_rejecting_stage(), Some("collision"));
+
+        let stats = staged.get_stage_stats();
+        assert_eq!(stats[0].1.checked, 3);
+        assert_eq!(stats[0].1.rejected, 1);
+        assert_eq!(stats[1].1.checked, 2);
+        assert_eq!(stats[1].1.rejected, 1);
+    }
+}
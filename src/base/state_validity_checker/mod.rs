@@ -2,6 +2,10 @@ use std::sync::Arc;
 
 use super::{state_allocator::StateId, statespace::StateSpace};
 
+pub mod bounds_validity_checker;
+pub mod caching_state_validity_checker;
+pub mod composite_state_validity_checker;
+
 #[derive(Debug, Copy, Clone, Default)]
 pub enum ClearanceComputationType {
     /// No clearance computation
@@ -68,7 +72,13 @@ pub trait StateValidityChecker {
 pub type StateValidityCheckerFn = Box<dyn Fn(&StateId) -> bool>;
 
 /// A state validity checker that uses a functional approach.
-struct FunctionalStateValidityChecker(StateValidityCheckerFn);
+pub struct FunctionalStateValidityChecker(StateValidityCheckerFn);
+
+impl FunctionalStateValidityChecker {
+    pub fn new(checker: StateValidityCheckerFn) -> Self {
+        Self(checker)
+    }
+}
 
 impl StateValidityChecker for FunctionalStateValidityChecker {
     fn is_valid(&self, state: &StateId) -> bool {
@@ -818,6 +818,26 @@ impl StateSpace for CompoundStateSpace {
         self.alloc_arena_state_with_value(cstate)
     }
 
+    #[state_id_into_inner]
+    fn copy_to_reals(&self, reals: &mut Vec<f64>, source: &StateId) {
+        reals.clear();
+        let mut component_reals = Vec::new();
+        for (component, substate) in self.components.iter().zip(&source.components) {
+            component.copy_to_reals(&mut component_reals, substate);
+            reals.extend_from_slice(&component_reals);
+        }
+    }
+
+    #[state_id_into_inner]
+    fn copy_from_reals(&self, destination: &mut StateId, reals: &Vec<f64>) {
+        let mut offset = 0;
+        for (component, substate) in self.components.iter().zip(&mut destination.components) {
+            let dimension = component.get_dimension() as usize;
+            component.copy_from_reals(substate, &reals[offset..offset + dimension].to_vec());
+            offset += dimension;
+        }
+    }
+
     fn free_state(&self, state: &StateId) {
         self.with_state(state, |cstate| {
             for (component, substate) in self.components.iter().zip(cstate.components.iter()) {
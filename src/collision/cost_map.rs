@@ -0,0 +1,465 @@
+use std::{fs, path::Path, rc::Rc};
+
+use crate::base::{
+    objectives::{
+        for_each_motion_state, motion_length, Cost, OptimizationObjective,
+        OptimizationObjectiveData,
+    },
+    spaces::{real_vector_bounds::RealVectorBounds, real_vector_state_space::RealVectorStateSpace},
+    state_allocator::StateId,
+    statespace::StateSpace,
+};
+use crate::error::LoadError;
+
+use super::occupancy_grid::{
+    check_resolution, invalid, read_map_yaml, read_pgm, OccupancyThresholds, PgmImage,
+};
+
+/// A 2D or 3D grid of traversal costs (terrain roughness, congestion, ...). Cell `(0, 0, 0)`
+/// is the bottom-left cell of the lowest layer, whose lower corner is located at `origin` in
+/// the world frame; cells are squares (or cubes) of side `resolution`. A 2D map has a single
+/// layer, and its positions only have x and y coordinates.
+///
+/// Costs are non-negative; an infinite cost marks a cell that cannot be traversed.
+#[derive(Debug, Clone)]
+pub struct CostMap {
+    dimension: usize,
+    size: [usize; 3],
+    resolution: f64,
+    origin: [f64; 3],
+    costs: Vec<f64>,
+}
+
+impl CostMap {
+    /// Create a 2D map in which every cell has a zero cost.
+    pub fn new(width: usize, height: usize, resolution: f64, origin: [f64; 2]) -> Self {
+        Self::with_dimension(
+            2,
+            [width, height, 1],
+            resolution,
+            [origin[0], origin[1], 0.0],
+        )
+    }
+
+    /// Create a 3D map of `size[0] x size[1] x size[2]` cells in which every cell has a zero
+    /// cost.
+    pub fn new_3d(size: [usize; 3], resolution: f64, origin: [f64; 3]) -> Self {
+        Self::with_dimension(3, size, resolution, origin)
+    }
+
+    fn with_dimension(
+        dimension: usize,
+        size: [usize; 3],
+        resolution: f64,
+        origin: [f64; 3],
+    ) -> Self {
+        if resolution <= 0.0 {
+            panic!("The resolution of the grid must be strictly positive");
+        }
+        Self {
+            dimension,
+            size,
+            resolution,
+            origin,
+            costs: vec![0.0; size[0] * size[1] * size[2]],
+        }
+    }
+
+    /// The number of coordinates of a position in the map, 2 or 3.
+    pub fn get_dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.size[0]
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.size[1]
+    }
+
+    /// The number of layers of the map, 1 for a 2D map.
+    pub fn get_depth(&self) -> usize {
+        self.size[2]
+    }
+
+    pub fn get_resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    /// The origin of the map; its z coordinate is 0 for a 2D map.
+    pub fn get_origin(&self) -> [f64; 3] {
+        self.origin
+    }
+
+    pub fn get_cost(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.costs[self.index(x, y, z)]
+    }
+
+    pub fn set_cost(&mut self, x: usize, y: usize, z: usize, cost: f64) {
+        if cost.is_nan() || cost < 0.0 {
+            panic!("The cost of a cell must be non-negative");
+        }
+        let index = self.index(x, y, z);
+        self.costs[index] = cost;
+    }
+
+    /// The largest finite cost of the map, or 0 if there is none.
+    pub fn get_max_finite_cost(&self) -> f64 {
+        self.costs
+            .iter()
+            .copied()
+            .filter(|c| c.is_finite())
+            .fold(0.0, f64::max)
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+
+    /// The cell containing a world position, if it lies within the map. The position must
+    /// have as many coordinates as the map has dimensions.
+    pub fn world_to_cell(&self, position: &[f64]) -> Option<[usize; 3]> {
+        assert_eq!(
+            position.len(),
+            self.dimension,
+            "The position must have as many coordinates as the map"
+        );
+        let mut cell = [0; 3];
+        for (i, p) in position.iter().enumerate() {
+            let c = ((p - self.origin[i]) / self.resolution).floor();
+            if c < 0.0 || c >= self.size[i] as f64 {
+                return None;
+            }
+            cell[i] = c as usize;
+        }
+        Some(cell)
+    }
+
+    /// The world position of the center of a cell, with as many coordinates as the map has
+    /// dimensions.
+    pub fn cell_to_world(&self, x: usize, y: usize, z: usize) -> Vec<f64> {
+        [x, y, z]
+            .iter()
+            .zip(self.origin)
+            .take(self.dimension)
+            .map(|(c, o)| o + (*c as f64 + 0.5) * self.resolution)
+            .collect()
+    }
+
+    /// The cost of the cell containing a world position, if it lies within the map.
+    pub fn cost_at(&self, position: &[f64]) -> Option<f64> {
+        self.world_to_cell(position)
+            .map(|[x, y, z]| self.get_cost(x, y, z))
+    }
+
+    /// The lower and upper corners of the area covered by the map.
+    pub fn get_extent(&self) -> (Vec<f64>, Vec<f64>) {
+        let low = self.origin[..self.dimension].to_vec();
+        let high = (0..self.dimension)
+            .map(|i| self.origin[i] + self.size[i] as f64 * self.resolution)
+            .collect();
+        (low, high)
+    }
+
+    /// Set the bounds of the first dimensions of `space` to the extent of the map.
+    pub fn set_space_bounds(&self, space: &mut RealVectorStateSpace) {
+        assert!(
+            space.get_dimension() as usize >= self.dimension,
+            "The state space must have at least as many dimensions as the map"
+        );
+        let (low, high) = self.get_extent();
+        let mut bounds: RealVectorBounds = space.bounds.clone();
+        for i in 0..self.dimension {
+            bounds.set_low_at(i, low[i]);
+            bounds.set_high_at(i, high[i]);
+        }
+        space.set_bounds(bounds);
+    }
+
+    /// Parse a binary (`P5`) or plain (`P2`) PGM image into a 2D map. The first row of the
+    /// image is the top of the map. As for occupancy grids, darker pixels are more costly
+    /// (unless `negate` is set): a pixel of occupancy probability `p` (see
+    /// `OccupancyThresholds`) costs `p * max_cost`.
+    pub fn parse_pgm(
+        data: &[u8],
+        resolution: f64,
+        origin: [f64; 2],
+        max_cost: f64,
+        negate: bool,
+    ) -> Result<Self, LoadError> {
+        check_resolution("PGM", resolution)?;
+        let PgmImage {
+            width,
+            height,
+            maxval,
+            values,
+        } = read_pgm(data)?;
+        let thresholds = OccupancyThresholds {
+            negate,
+            ..Default::default()
+        };
+        let mut map = Self::new(width, height, resolution, origin);
+        for (i, value) in values.into_iter().enumerate() {
+            let (row, col) = (i / width, i % width);
+            let cost = thresholds.probability(value, maxval) * max_cost;
+            map.set_cost(col, height - 1 - row, 0, cost);
+        }
+        Ok(map)
+    }
+
+    pub fn load_pgm(
+        path: impl AsRef<Path>,
+        resolution: f64,
+        origin: [f64; 2],
+        max_cost: f64,
+        negate: bool,
+    ) -> Result<Self, LoadError> {
+        Self::parse_pgm(&fs::read(path)?, resolution, origin, max_cost, negate)
+    }
+
+    /// Load a map described by a ROS map server YAML file, as
+    /// `OccupancyGrid::load_map_yaml`. Pixels are converted to costs as by `parse_pgm`; the
+    /// thresholds of the file are ignored.
+    pub fn load_map_yaml(path: impl AsRef<Path>, max_cost: f64) -> Result<Self, LoadError> {
+        let map = read_map_yaml(path.as_ref())?;
+        Self::load_pgm(
+            map.image,
+            map.resolution,
+            map.origin,
+            max_cost,
+            map.thresholds.negate,
+        )
+    }
+
+    /// Parse a plain-text map. As for `OccupancyGrid::parse_text`, lines `resolution <r>` and
+    /// `origin <x> <y> [<z>]` set the metadata and lines starting with `//` are comments.
+    /// Every other non-empty line is a row of whitespace separated costs (`inf` marking cells
+    /// that cannot be traversed), the first one being the top of the map. A 3D map is made of
+    /// layers separated by `---` lines, from the lowest to the highest.
+    pub fn parse_text(text: &str) -> Result<Self, LoadError> {
+        const FORMAT: &str = "text cost map";
+        let mut resolution = 1.0;
+        let mut origin = [0.0; 3];
+        let mut layers: Vec<Vec<Vec<f64>>> = vec![Vec::new()];
+
+        let parse_f64 = |value: Option<&str>| {
+            value
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| invalid(FORMAT, "invalid metadata value"))
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("resolution") => resolution = parse_f64(words.next())?,
+                Some("origin") => {
+                    origin[0] = parse_f64(words.next())?;
+                    origin[1] = parse_f64(words.next())?;
+                    if let Some(z) = words.next() {
+                        origin[2] = parse_f64(Some(z))?;
+                    }
+                }
+                Some("---") => layers.push(Vec::new()),
+                _ => {
+                    let row = line
+                        .split_whitespace()
+                        .map(|v| match v.parse::<f64>() {
+                            Ok(cost) if cost >= 0.0 => Ok(cost),
+                            _ => Err(invalid(FORMAT, format!("invalid cost '{}'", v))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    layers.last_mut().unwrap().push(row);
+                }
+            }
+        }
+
+        check_resolution(FORMAT, resolution)?;
+        let height = layers[0].len();
+        let width = layers[0].first().map_or(0, |r| r.len());
+        let same_size = layers
+            .iter()
+            .all(|layer| layer.len() == height && layer.iter().all(|r| r.len() == width));
+        if !same_size {
+            return Err(invalid(FORMAT, "rows and layers must have the same size"));
+        }
+        let mut map = if layers.len() == 1 {
+            Self::new(width, height, resolution, [origin[0], origin[1]])
+        } else {
+            Self::new_3d([width, height, layers.len()], resolution, origin)
+        };
+        for (z, rows) in layers.into_iter().enumerate() {
+            for (row, costs) in rows.into_iter().enumerate() {
+                for (col, cost) in costs.into_iter().enumerate() {
+                    map.set_cost(col, height - 1 - row, z, cost);
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn load_text(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::parse_text(&fs::read_to_string(path)?)
+    }
+}
+
+/// The objective of minimizing the integral of the cell costs of a `CostMap` along the path,
+/// i.e. the sum over the cells the path goes through of their cost times the length travelled
+/// in them. Zero-cost cells are free to cross, so maps whose cells all have a small positive
+/// cost also prefer shorter paths.
+///
+/// As for `OccupancyGridValidityChecker`, the position is made of some of the values given by
+/// `StateSpace::copy_to_reals` (the first two or three by default, depending on the map), so
+/// the objective applies to 2D and 3D `RealVectorStateSpace`s as well as to `SE2StateSpace`.
+/// Motion costs are computed with the trapezoidal rule, over states interpolated at the
+/// resolution of the space (see `StateSpace::valid_segment_count`), which should be finer than
+/// the map. States outside of the map have an infinite cost.
+pub struct CostMapOptimizationObjective {
+    data: OptimizationObjectiveData,
+    map: CostMap,
+    position_indices: Vec<usize>,
+}
+
+impl CostMapOptimizationObjective {
+    pub fn new(state_space: Rc<dyn StateSpace>, map: CostMap) -> Self {
+        Self {
+            data: OptimizationObjectiveData::new(state_space, "Cost Map"),
+            position_indices: (0..map.get_dimension()).collect(),
+            map,
+        }
+    }
+
+    pub fn get_cost_map(&self) -> &CostMap {
+        &self.map
+    }
+
+    /// Select which of the real values of a state are its position, one per dimension of the
+    /// map.
+    pub fn set_position_indices(&mut self, indices: &[usize]) {
+        if indices.len() != self.map.get_dimension() {
+            panic!("There must be one position index per dimension of the cost map");
+        }
+        self.position_indices = indices.to_vec();
+    }
+
+    pub fn get_position_indices(&self) -> &[usize] {
+        &self.position_indices
+    }
+
+    fn position(&self, state: &StateId) -> Vec<f64> {
+        let mut reals = Vec::new();
+        self.data.state_space.copy_to_reals(&mut reals, state);
+        self.position_indices.iter().map(|&i| reals[i]).collect()
+    }
+}
+
+impl OptimizationObjective for CostMapOptimizationObjective {
+    fn objective_data(&self) -> &OptimizationObjectiveData {
+        &self.data
+    }
+
+    fn objective_data_mut(&mut self) -> &mut OptimizationObjectiveData {
+        &mut self.data
+    }
+
+    fn state_cost(&self, state: &StateId) -> Cost {
+        Cost(
+            self.map
+                .cost_at(&self.position(state))
+                .unwrap_or(f64::INFINITY),
+        )
+    }
+
+    fn motion_cost(&self, s1: &StateId, s2: &StateId) -> Cost {
+        let space = self.data.state_space.as_ref();
+        let length = motion_length(space, s1, s2);
+        let mut costs = Vec::new();
+        let nd = for_each_motion_state(space, s1, s2, |s| costs.push(self.state_cost(s).value()));
+        let segment = length / nd as f64;
+        Cost(
+            costs
+                .windows(2)
+                // a zero-length motion in an impassable cell would otherwise be NaN
+                .map(|c| {
+                    if segment > 0.0 {
+                        0.5 * segment * (c[0] + c[1])
+                    } else {
+                        0.0
+                    }
+                })
+                .sum(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::se2_state_space::{SE2State, SE2StateSpace};
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+
+    #[test]
+    fn test_cost_map_formats() {
+        let map = CostMap::parse_text(
+            "
+            // two layers of 3 x 2 cells
+            resolution 0.5
+            origin 0 -1 2
+            1 2 3
+            4 5 inf
+            ---
+            0 0 0
+            7 8 9
+            ",
+        )
+        .unwrap();
+        assert_eq!(map.get_dimension(), 3);
+        assert_eq!(
+            (map.get_width(), map.get_height(), map.get_depth()),
+            (3, 2, 2)
+        );
+        assert_eq!(map.get_cost(0, 1, 0), 1.0);
+        assert_eq!(map.get_cost(2, 0, 0), f64::INFINITY);
+        assert_eq!(map.get_cost(1, 0, 1), 8.0);
+        assert_eq!(map.get_max_finite_cost(), 9.0);
+        assert_eq!(map.cost_at(&[0.75, -0.75, 2.75]), Some(8.0));
+        assert_eq!(map.cost_at(&[0.75, -0.75, 3.25]), None);
+        assert_eq!(map.cell_to_world(1, 0, 1), vec![0.75, -0.75, 2.75]);
+        assert!(CostMap::parse_text("1 2\n3\n").is_err());
+
+        let pgm = b"P2\n3 1\n255\n255 0 51\n";
+        let map = CostMap::parse_pgm(pgm, 0.1, [0.0, 0.0], 10.0, false).unwrap();
+        assert_eq!(map.get_dimension(), 2);
+        assert_eq!(map.get_cost(0, 0, 0), 0.0);
+        assert_eq!(map.get_cost(1, 0, 0), 10.0);
+        assert!((map.cost_at(&[0.25, 0.05]).unwrap() - 8.0).abs() < 1e-12);
+        let negated = CostMap::parse_pgm(pgm, 0.1, [0.0, 0.0], 10.0, true).unwrap();
+        assert_eq!(negated.get_cost(0, 0, 0), 10.0);
+        assert!(CostMap::parse_pgm(pgm, -0.1, [0.0, 0.0], 10.0, false).is_err());
+        assert!(CostMap::parse_text("resolution 0\n1 2\n").is_err());
+    }
+
+    #[test]
+    fn test_cost_map_objective_se2() {
+        let map = CostMap::parse_text("1 3\n").unwrap();
+        let mut space = SE2StateSpace::new();
+        space.state_space_data_mut().longest_valid_segment = 0.01;
+        let space = Rc::new(space);
+        let s1 = space.alloc_arena_state_with_value(SE2State::new(0.5, 0.5, 1.0));
+        let s2 = space.alloc_arena_state_with_value(SE2State::new(1.5, 0.5, 1.0));
+        let outside = space.alloc_arena_state_with_value(SE2State::new(2.5, 0.5, 1.0));
+
+        let objective = CostMapOptimizationObjective::new(space.clone(), map);
+        assert_eq!(objective.get_position_indices(), &[0, 1]);
+        assert_eq!(objective.state_cost(&s2), Cost(3.0));
+        assert_eq!(objective.state_cost(&outside), objective.infinite_cost());
+        // half a cell of cost 1, then half a cell of cost 3
+        assert!((objective.motion_cost(&s1, &s2).value() - 2.0).abs() < 0.02);
+        assert_eq!(objective.motion_cost(&s1, &s1), Cost(0.0));
+        assert!(!objective.is_finite(objective.motion_cost(&s1, &outside)));
+    }
+}
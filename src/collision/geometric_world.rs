@@ -0,0 +1,621 @@
+use std::{fmt::Write as _, fs, path::Path, rc::Rc};
+
+use nalgebra::{DMatrix, DVector, Rotation2, Rotation3};
+
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+use crate::error::LoadError;
+
+/// An obstacle of a `GeometricWorld`. All obstacles are solid.
+#[derive(Debug, Clone)]
+pub enum Obstacle {
+    AxisAlignedBox {
+        min: DVector<f64>,
+        max: DVector<f64>,
+    },
+    /// A box centered at `center`, whose axes are the columns of `rotation`.
+    OrientedBox {
+        center: DVector<f64>,
+        half_extents: DVector<f64>,
+        rotation: DMatrix<f64>,
+    },
+    /// A disc in 2D, a sphere in 3D.
+    Sphere { center: DVector<f64>, radius: f64 },
+    /// A simple 2D polygon, given by its vertices in order.
+    Polygon { vertices: Vec<[f64; 2]> },
+}
+
+/// Closest point to `p` on the boundary of the box `[-h, h]`, and whether `p` is inside.
+fn box_closest_boundary_point(p: &DVector<f64>, h: &DVector<f64>) -> (DVector<f64>, bool) {
+    let inside = p.iter().zip(h.iter()).all(|(x, h)| x.abs() <= *h);
+    if !inside {
+        let clamped = DVector::from_iterator(
+            p.len(),
+            p.iter().zip(h.iter()).map(|(x, h)| x.clamp(-h, *h)),
+        );
+        return (clamped, false);
+    }
+
+    // push the point onto the nearest face
+    let (axis, _) = p
+        .iter()
+        .zip(h.iter())
+        .map(|(x, h)| h - x.abs())
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("the box must have at least one dimension");
+    let mut closest = p.clone();
+    closest[axis] = h[axis].copysign(p[axis]);
+    (closest, true)
+}
+
+/// Whether every value is non-negative (and not NaN).
+fn is_nonnegative(values: &[f64]) -> bool {
+    values.iter().all(|v| *v >= 0.0)
+}
+
+fn check_half_extents(half_extents: &[f64]) {
+    if !is_nonnegative(half_extents) {
+        panic!("The half extents of a box cannot be negative");
+    }
+}
+
+fn segment_closest_point(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> [f64; 2] {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let length_sq = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if length_sq > 0.0 {
+        (((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    [a[0] + t * ab[0], a[1] + t * ab[1]]
+}
+
+/// Even-odd rule point in polygon test.
+pub(crate) fn polygon_contains(vertices: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    let mut j = vertices.len() - 1;
+    for i in 0..vertices.len() {
+        let (a, b) = (vertices[i], vertices[j]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+impl Obstacle {
+    /// Create an oriented box in 2D, rotated by `angle` radians.
+    pub fn oriented_box_2d(center: [f64; 2], half_extents: [f64; 2], angle: f64) -> Self {
+        check_half_extents(&half_extents);
+        Obstacle::OrientedBox {
+            center: DVector::from_row_slice(&center),
+            half_extents: DVector::from_row_slice(&half_extents),
+            rotation: DMatrix::from_iterator(2, 2, Rotation2::new(angle).matrix().iter().copied()),
+        }
+    }
+
+    /// Create an oriented box in 3D, rotated by the given roll, pitch and yaw angles.
+    pub fn oriented_box_3d(center: [f64; 3], half_extents: [f64; 3], rpy: [f64; 3]) -> Self {
+        check_half_extents(&half_extents);
+        let rotation = Rotation3::from_euler_angles(rpy[0], rpy[1], rpy[2]);
+        Obstacle::OrientedBox {
+            center: DVector::from_row_slice(&center),
+            half_extents: DVector::from_row_slice(&half_extents),
+            rotation: DMatrix::from_iterator(3, 3, rotation.matrix().iter().copied()),
+        }
+    }
+
+    /// Why the obstacle is malformed, if it is: a box whose minimum exceeds its maximum, or a
+    /// negative half extent or radius.
+    fn malformed(&self) -> Option<&'static str> {
+        match self {
+            Obstacle::AxisAlignedBox { min, max } if !is_nonnegative((max - min).as_slice()) => {
+                Some("the minimum of a box cannot exceed its maximum")
+            }
+            Obstacle::OrientedBox { half_extents, .. }
+                if !is_nonnegative(half_extents.as_slice()) =>
+            {
+                Some("the half extents of a box cannot be negative")
+            }
+            Obstacle::Sphere { radius, .. } if !is_nonnegative(&[*radius]) => {
+                Some("the radius of a sphere cannot be negative")
+            }
+            _ => None,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        match self {
+            Obstacle::AxisAlignedBox { min, .. } => min.len(),
+            Obstacle::OrientedBox { center, .. } => center.len(),
+            Obstacle::Sphere { center, .. } => center.len(),
+            Obstacle::Polygon { .. } => 2,
+        }
+    }
+
+    /// The point of the boundary of the obstacle closest to `p`, and whether `p` lies inside
+    /// the obstacle.
+    pub fn closest_boundary_point(&self, p: &DVector<f64>) -> (DVector<f64>, bool) {
+        match self {
+            Obstacle::AxisAlignedBox { min, max } => {
+                let center = (min + max) / 2.0;
+                let half_extents = (max - min) / 2.0;
+                let (closest, inside) = box_closest_boundary_point(&(p - &center), &half_extents);
+                (closest + center, inside)
+            }
+            Obstacle::OrientedBox {
+                center,
+                half_extents,
+                rotation,
+            } => {
+                let local = rotation.transpose() * (p - center);
+                let (closest, inside) = box_closest_boundary_point(&local, half_extents);
+                (rotation * closest + center, inside)
+            }
+            Obstacle::Sphere { center, radius } => {
+                let offset = p - center;
+                let norm = offset.norm();
+                let direction = if norm > 0.0 {
+                    offset / norm
+                } else {
+                    let mut axis = DVector::zeros(p.len());
+                    axis[0] = 1.0;
+                    axis
+                };
+                (center + direction * *radius, norm <= *radius)
+            }
+            Obstacle::Polygon { vertices } => {
+                let q = [p[0], p[1]];
+                let closest = (0..vertices.len())
+                    .map(|i| {
+                        segment_closest_point(q, vertices[i], vertices[(i + 1) % vertices.len()])
+                    })
+                    .min_by(|a, b| {
+                        let da = (a[0] - q[0]).powi(2) + (a[1] - q[1]).powi(2);
+                        let db = (b[0] - q[0]).powi(2) + (b[1] - q[1]).powi(2);
+                        da.total_cmp(&db)
+                    })
+                    .expect("a polygon must have vertices");
+                (
+                    DVector::from_row_slice(&closest),
+                    polygon_contains(vertices, q),
+                )
+            }
+        }
+    }
+
+    /// Signed distance from `p` to the obstacle: positive outside, negative inside.
+    pub fn signed_distance(&self, p: &DVector<f64>) -> f64 {
+        let (closest, inside) = self.closest_boundary_point(p);
+        let distance = (p - closest).norm();
+        if inside {
+            -distance
+        } else {
+            distance
+        }
+    }
+}
+
+/// A set of geometric obstacles, in which a point robot (or a disc/sphere robot of radius
+/// `robot_radius`) moves.
+#[derive(Debug, Clone)]
+pub struct GeometricWorld {
+    dimension: usize,
+    robot_radius: f64,
+    obstacles: Vec<Obstacle>,
+}
+
+impl GeometricWorld {
+    pub fn new(dimension: usize) -> Self {
+        if !(2..=3).contains(&dimension) {
+            panic!("Geometric worlds are only supported in 2 or 3 dimensions");
+        }
+        Self {
+            dimension,
+            robot_radius: 0.0,
+            obstacles: Vec::new(),
+        }
+    }
+
+    pub fn get_dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn set_robot_radius(&mut self, robot_radius: f64) {
+        if robot_radius < 0.0 {
+            panic!("The robot radius cannot be negative");
+        }
+        self.robot_radius = robot_radius;
+    }
+
+    pub fn get_robot_radius(&self) -> f64 {
+        self.robot_radius
+    }
+
+    pub fn add_obstacle(&mut self, obstacle: Obstacle) {
+        assert_eq!(
+            obstacle.dimension(),
+            self.dimension,
+            "The obstacle does not have the dimension of the world"
+        );
+        if let Some(message) = obstacle.malformed() {
+            panic!("Malformed obstacle: {}", message);
+        }
+        self.obstacles.push(obstacle);
+    }
+
+    pub fn get_obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    /// The signed distance from the robot at `p` to the nearest obstacle, along with the index
+    /// of that obstacle. Infinite if the world has no obstacle.
+    fn nearest_obstacle(&self, p: &DVector<f64>) -> (f64, Option<usize>) {
+        self.obstacles
+            .iter()
+            .enumerate()
+            .map(|(i, o)| (o.signed_distance(p) - self.robot_radius, Some(i)))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or((f64::INFINITY, None))
+    }
+
+    /// Signed distance from the robot at `p` to the obstacles.
+    pub fn signed_distance(&self, p: &DVector<f64>) -> f64 {
+        self.nearest_obstacle(p).0
+    }
+
+    /// A collision-free position near `p`, found by repeatedly pushing the robot out of the
+    /// obstacle it penetrates the most, up to `margin` beyond its boundary.
+    pub fn nearest_free_point(&self, p: &DVector<f64>, margin: f64) -> Option<DVector<f64>> {
+        const MAX_ITERATIONS: usize = 16;
+        let mut p = p.clone();
+        for _ in 0..MAX_ITERATIONS {
+            let (distance, index) = self.nearest_obstacle(&p);
+            let Some(index) = index.filter(|_| distance <= 0.0) else {
+                return Some(p);
+            };
+
+            let (closest, inside) = self.obstacles[index].closest_boundary_point(&p);
+            let offset = &p - &closest;
+            let norm = offset.norm();
+            let outward = if norm > 0.0 {
+                if inside {
+                    -offset / norm
+                } else {
+                    offset / norm
+                }
+            } else {
+                // on the boundary itself: move away from the obstacle center
+                let probe = self.obstacles[index].closest_boundary_point(&closest).0;
+                let fallback = &closest - probe;
+                if fallback.norm() > 0.0 {
+                    fallback.normalize()
+                } else {
+                    return None;
+                }
+            };
+            p = closest + outward * (self.robot_radius + margin);
+        }
+        (self.signed_distance(&p) > 0.0).then_some(p)
+    }
+
+    /// Parse a scene description. Every line holds one directive; `#` starts a comment.
+    ///
+    /// ```text
+    /// dimension 2
+    /// robot_radius 0.1
+    /// box <min...> <max...>
+    /// oriented_box <center...> <half extents...> <angle | roll pitch yaw>
+    /// sphere <center...> <radius>
+    /// polygon <x1> <y1> <x2> <y2> ...
+    /// ```
+    ///
+    /// The `dimension` directive must come before any obstacle.
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        const FORMAT: &str = "scene";
+        let error = |line: usize, message: &str| LoadError::InvalidFormat {
+            format: FORMAT,
+            message: format!("line {}: {}", line + 1, message),
+        };
+
+        let mut world: Option<GeometricWorld> = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut words = line.split_whitespace();
+            let Some(directive) = words.next() else {
+                continue;
+            };
+            let values = words
+                .map(|w| w.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| error(n, "invalid number"))?;
+
+            if directive == "dimension" {
+                if world.is_some() {
+                    return Err(error(n, "the dimension is given more than once"));
+                }
+                match values.as_slice() {
+                    [d] if *d == 2.0 || *d == 3.0 => world = Some(GeometricWorld::new(*d as usize)),
+                    _ => return Err(error(n, "the dimension must be 2 or 3")),
+                }
+                continue;
+            }
+
+            let world = world
+                .as_mut()
+                .ok_or_else(|| error(n, "the dimension must be given first"))?;
+            let d = world.dimension;
+            // the oriented box constructors panic on negative half extents
+            if directive == "oriented_box"
+                && values.len() >= 2 * d
+                && !is_nonnegative(&values[d..2 * d])
+            {
+                return Err(error(n, "the half extents of a box cannot be negative"));
+            }
+            let obstacle = match (directive, values.len()) {
+                ("robot_radius", 1) if values[0] >= 0.0 => {
+                    world.robot_radius = values[0];
+                    continue;
+                }
+                ("box", l) if l == 2 * d => Obstacle::AxisAlignedBox {
+                    min: DVector::from_row_slice(&values[..d]),
+                    max: DVector::from_row_slice(&values[d..]),
+                },
+                ("oriented_box", 5) if d == 2 => Obstacle::oriented_box_2d(
+                    [values[0], values[1]],
+                    [values[2], values[3]],
+                    values[4],
+                ),
+                ("oriented_box", 9) if d == 3 => Obstacle::oriented_box_3d(
+                    [values[0], values[1], values[2]],
+                    [values[3], values[4], values[5]],
+                    [values[6], values[7], values[8]],
+                ),
+                ("sphere", l) if l == d + 1 => Obstacle::Sphere {
+                    center: DVector::from_row_slice(&values[..d]),
+                    radius: values[d],
+                },
+                ("polygon", l) if d == 2 && l >= 6 && l % 2 == 0 => Obstacle::Polygon {
+                    vertices: values.chunks(2).map(|v| [v[0], v[1]]).collect(),
+                },
+                _ => return Err(error(n, &format!("invalid '{}' directive", directive))),
+            };
+            if let Some(message) = obstacle.malformed() {
+                return Err(error(n, message));
+            }
+            world.obstacles.push(obstacle);
+        }
+
+        world.ok_or_else(|| error(0, "missing dimension"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Write the world in the format read by `parse`.
+    pub fn to_text(&self) -> String {
+        let join = |values: &mut dyn Iterator<Item = f64>| {
+            values.map(|v| v.to_string()).collect::<Vec<_>>().join(" ")
+        };
+
+        let mut out = String::new();
+        writeln!(out, "dimension {}", self.dimension).unwrap();
+        writeln!(out, "robot_radius {}", self.robot_radius).unwrap();
+        for obstacle in &self.obstacles {
+            match obstacle {
+                Obstacle::AxisAlignedBox { min, max } => {
+                    writeln!(
+                        out,
+                        "box {}",
+                        join(&mut min.iter().chain(max.iter()).copied())
+                    )
+                }
+                Obstacle::OrientedBox {
+                    center,
+                    half_extents,
+                    rotation,
+                } => {
+                    let angles = if self.dimension == 2 {
+                        vec![rotation[(1, 0)].atan2(rotation[(0, 0)])]
+                    } else {
+                        let m = rotation.fixed_view::<3, 3>(0, 0).into_owned();
+                        let (r, p, y) = Rotation3::from_matrix_unchecked(m).euler_angles();
+                        vec![r, p, y]
+                    };
+                    writeln!(
+                        out,
+                        "oriented_box {}",
+                        join(
+                            &mut center
+                                .iter()
+                                .chain(half_extents.iter())
+                                .copied()
+                                .chain(angles)
+                        )
+                    )
+                }
+                Obstacle::Sphere { center, radius } => writeln!(
+                    out,
+                    "sphere {}",
+                    join(&mut center.iter().copied().chain([*radius]))
+                ),
+                Obstacle::Polygon { vertices } => writeln!(
+                    out,
+                    "polygon {}",
+                    join(&mut vertices.iter().flat_map(|v| v.iter().copied()))
+                ),
+            }
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// A state validity checker for a point or disc robot in a `GeometricWorld`. The position of
+/// the robot is made of the first values given by `StateSpace::copy_to_reals`.
+///
+/// The clearance is the exact signed distance to the nearest obstacle, and states in
+/// collision can be pushed out to the nearest free position.
+pub struct GeometricWorldValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    world: GeometricWorld,
+    margin: f64,
+}
+
+impl GeometricWorldValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>, world: GeometricWorld) -> Self {
+        assert!(
+            state_space.get_dimension() as usize >= world.get_dimension(),
+            "The state space has fewer dimensions than the world"
+        );
+        Self {
+            state_space,
+            world,
+            margin: 1e-6,
+        }
+    }
+
+    pub fn get_world(&self) -> &GeometricWorld {
+        &self.world
+    }
+
+    /// Distance by which states in collision are pushed beyond the obstacle boundary.
+    pub fn set_margin(&mut self, margin: f64) {
+        self.margin = margin;
+    }
+
+    fn position(&self, state: &StateId) -> (Vec<f64>, DVector<f64>) {
+        let mut reals = Vec::new();
+        self.state_space.copy_to_reals(&mut reals, state);
+        let position = DVector::from_row_slice(&reals[..self.world.dimension]);
+        (reals, position)
+    }
+}
+
+impl StateValidityChecker for GeometricWorldValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.world.signed_distance(&self.position(state).1) > 0.0
+    }
+
+    fn has_valid_direction_computation(&self) -> bool {
+        true
+    }
+
+    fn is_valid_with_distance(&self, state: &StateId) -> (bool, Option<f64>) {
+        let distance = self.world.signed_distance(&self.position(state).1);
+        (distance > 0.0, Some(distance))
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        Some(self.world.signed_distance(&self.position(state).1))
+    }
+
+    fn clearance_with_state(
+        &self,
+        state: &StateId,
+        valid_state: &mut StateId,
+        valid_state_available: &mut bool,
+    ) -> Option<f64> {
+        let (mut reals, position) = self.position(state);
+        let distance = self.world.signed_distance(&position);
+
+        *valid_state_available = false;
+        if let Some(free) = self.world.nearest_free_point(&position, self.margin) {
+            reals[..self.world.dimension].copy_from_slice(free.as_slice());
+            self.state_space.copy_from_reals(valid_state, &reals);
+            *valid_state_available = true;
+        }
+        Some(distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+
+    const SCENE: &str = "
+        dimension 2
+        robot_radius 0.1 # a disc robot
+        box 0 0 1 1
+        sphere 3 0 0.5
+        oriented_box 0 3 1 0.5 0.7853981633974483
+        polygon 5 5 6 5 6 6
+    ";
+
+    #[test]
+    fn test_geometric_world() {
+        let world = GeometricWorld::parse(SCENE).unwrap();
+        assert_eq!(world.get_obstacles().len(), 4);
+
+        let reparsed = GeometricWorld::parse(&world.to_text()).unwrap();
+        assert_eq!(reparsed.get_obstacles().len(), 4);
+        assert_eq!(reparsed.get_robot_radius(), 0.1);
+
+        let p = |x: f64, y: f64| DVector::from_vec(vec![x, y]);
+        for w in [&world, &reparsed] {
+            assert!((w.signed_distance(&p(1.5, 0.5)) - 0.4).abs() < 1e-12);
+            assert!((w.signed_distance(&p(0.5, 0.6)) + 0.5).abs() < 1e-12);
+            assert!((w.signed_distance(&p(4.0, 0.0)) - 0.4).abs() < 1e-12);
+            assert!(w.signed_distance(&p(0.0, 3.0)) < -0.5);
+            assert!(w.signed_distance(&p(5.8, 5.5)) < 0.0);
+            assert!(w.signed_distance(&p(5.2, 5.8)) > 0.0);
+        }
+
+        assert!(GeometricWorld::parse("box 0 0 1 1").is_err());
+        assert!(GeometricWorld::parse("dimension 2\nsphere 0 0").is_err());
+        for malformed in [
+            "box 1 1 0 0",
+            "oriented_box 0 0 1 -1 0",
+            "sphere 0 0 -1",
+            "dimension 2",
+        ] {
+            let text = format!("dimension 2\nbox 0 0 1 1\n{}", malformed);
+            assert!(GeometricWorld::parse(&text).is_err());
+        }
+    }
+
+    #[test]
+    fn test_geometric_world_validity_checker() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, -10.0, 10.0);
+        space.add_dimension(None, -10.0, 10.0);
+        let space = Rc::new(space);
+
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        let checker = GeometricWorldValidityChecker::new(
+            space.clone(),
+            GeometricWorld::parse(SCENE).unwrap(),
+        );
+        assert!(checker.has_valid_direction_computation());
+        assert!(checker.is_valid(&state(2.0, 0.5)));
+        assert!(!checker.is_valid(&state(0.5, 0.2)));
+
+        let mut valid_state = space.alloc_state();
+        let mut available = false;
+        let (valid, clearance) = checker.is_valid_with_distance_and_state(
+            &state(0.5, 0.2),
+            &mut valid_state,
+            &mut available,
+        );
+        assert!(!valid);
+        assert!((clearance.unwrap() + 0.3).abs() < 1e-12);
+        assert!(available);
+        assert!(checker.is_valid(&valid_state));
+        let values = space.clone_state_inner_value(&valid_state).values;
+        assert!((values[0] - 0.5).abs() < 1e-9);
+        assert!((values[1] + 0.1).abs() < 1e-5);
+    }
+}
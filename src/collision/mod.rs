@@ -0,0 +1,8 @@
+pub mod cost_map;
+pub mod geometric_world;
+pub mod occupancy_grid;
+pub mod planar_arm;
+pub mod point_cloud;
+pub mod se2_polygon;
+pub mod signed_distance_field;
+pub mod triangle_mesh;
@@ -0,0 +1,708 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::base::{
+    spaces::{real_vector_bounds::RealVectorBounds, real_vector_state_space::RealVectorStateSpace},
+    state_allocator::StateId,
+    state_validity_checker::StateValidityChecker,
+    statespace::StateSpace,
+};
+use crate::error::LoadError;
+use crate::util::distance_transform::euclidean_distance_transform;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellState {
+    Free,
+    Occupied,
+    Unknown,
+}
+
+/// How the occupancy of an image pixel is decided, following the conventions of the ROS map
+/// server: a pixel of value `v` (out of `maxval`) has an occupancy probability of
+/// `(maxval - v) / maxval`, or `v / maxval` if `negate` is set.
+#[derive(Debug, Copy, Clone)]
+pub struct OccupancyThresholds {
+    /// Pixels with an occupancy probability above this are occupied.
+    pub occupied: f64,
+    /// Pixels with an occupancy probability below this are free.
+    pub free: f64,
+    pub negate: bool,
+}
+
+impl Default for OccupancyThresholds {
+    fn default() -> Self {
+        Self {
+            occupied: 0.65,
+            free: 0.196,
+            negate: false,
+        }
+    }
+}
+
+impl OccupancyThresholds {
+    /// The occupancy probability of a pixel.
+    pub(crate) fn probability(&self, value: u32, maxval: u32) -> f64 {
+        let p = (maxval - value.min(maxval)) as f64 / maxval as f64;
+        if self.negate {
+            1.0 - p
+        } else {
+            p
+        }
+    }
+
+    fn classify(&self, value: u32, maxval: u32) -> CellState {
+        let p = self.probability(value, maxval);
+        if p > self.occupied {
+            CellState::Occupied
+        } else if p < self.free {
+            CellState::Free
+        } else {
+            CellState::Unknown
+        }
+    }
+}
+
+/// A 2D occupancy grid. Cell `(0, 0)` is the bottom-left cell, whose lower-left corner is
+/// located at `origin` in the world frame; cells are squares of side `resolution`.
+#[derive(Debug, Clone)]
+pub struct OccupancyGrid {
+    width: usize,
+    height: usize,
+    resolution: f64,
+    origin: [f64; 2],
+    cells: Vec<CellState>,
+}
+
+pub(crate) fn invalid(format: &'static str, message: impl Into<String>) -> LoadError {
+    LoadError::InvalidFormat {
+        format,
+        message: message.into(),
+    }
+}
+
+/// Rejects the resolution of a map read from a file unless it is finite and strictly positive.
+pub(crate) fn check_resolution(format: &'static str, resolution: f64) -> Result<(), LoadError> {
+    if resolution.is_finite() && resolution > 0.0 {
+        Ok(())
+    } else {
+        Err(invalid(
+            format,
+            "resolution must be finite and strictly positive",
+        ))
+    }
+}
+
+impl OccupancyGrid {
+    /// Create a grid in which every cell is free.
+    pub fn new(width: usize, height: usize, resolution: f64, origin: [f64; 2]) -> Self {
+        if resolution <= 0.0 {
+            panic!("The resolution of the grid must be strictly positive");
+        }
+        Self {
+            width,
+            height,
+            resolution,
+            origin,
+            cells: vec![CellState::Free; width * height],
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get_resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    pub fn get_origin(&self) -> [f64; 2] {
+        self.origin
+    }
+
+    pub fn get_cell(&self, x: usize, y: usize) -> CellState {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set_cell(&mut self, x: usize, y: usize, state: CellState) {
+        self.cells[y * self.width + x] = state;
+    }
+
+    /// The cell containing a world position, if it lies within the grid.
+    pub fn world_to_cell(&self, position: [f64; 2]) -> Option<(usize, usize)> {
+        let x = ((position[0] - self.origin[0]) / self.resolution).floor();
+        let y = ((position[1] - self.origin[1]) / self.resolution).floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    /// The world position of the center of a cell.
+    pub fn cell_to_world(&self, x: usize, y: usize) -> [f64; 2] {
+        [
+            self.origin[0] + (x as f64 + 0.5) * self.resolution,
+            self.origin[1] + (y as f64 + 0.5) * self.resolution,
+        ]
+    }
+
+    /// The lower and upper corners of the area covered by the grid.
+    pub fn get_extent(&self) -> ([f64; 2], [f64; 2]) {
+        (
+            self.origin,
+            [
+                self.origin[0] + self.width as f64 * self.resolution,
+                self.origin[1] + self.height as f64 * self.resolution,
+            ],
+        )
+    }
+
+    /// Set the bounds of the first two dimensions of `space` to the extent of the map.
+    pub fn set_space_bounds(&self, space: &mut RealVectorStateSpace) {
+        assert!(
+            space.get_dimension() >= 2,
+            "The state space must have at least two dimensions"
+        );
+        let (low, high) = self.get_extent();
+        let mut bounds: RealVectorBounds = space.bounds.clone();
+        for i in 0..2 {
+            bounds.set_low_at(i, low[i]);
+            bounds.set_high_at(i, high[i]);
+        }
+        space.set_bounds(bounds);
+    }
+
+    /// Parse a binary (`P5`) or plain (`P2`) PGM image. The first row of the image is the top
+    /// of the map.
+    pub fn parse_pgm(
+        data: &[u8],
+        resolution: f64,
+        origin: [f64; 2],
+        thresholds: OccupancyThresholds,
+    ) -> Result<Self, LoadError> {
+        check_resolution("PGM", resolution)?;
+        let PgmImage {
+            width,
+            height,
+            maxval,
+            values,
+        } = read_pgm(data)?;
+        let mut grid = Self::new(width, height, resolution, origin);
+        for (i, value) in values.into_iter().enumerate() {
+            let (row, col) = (i / width, i % width);
+            grid.set_cell(col, height - 1 - row, thresholds.classify(value, maxval));
+        }
+        Ok(grid)
+    }
+
+    pub fn load_pgm(
+        path: impl AsRef<Path>,
+        resolution: f64,
+        origin: [f64; 2],
+        thresholds: OccupancyThresholds,
+    ) -> Result<Self, LoadError> {
+        Self::parse_pgm(&fs::read(path)?, resolution, origin, thresholds)
+    }
+
+    /// Load a map described by a ROS map server YAML file (`image`, `resolution`, `origin`,
+    /// `negate`, `occupied_thresh` and `free_thresh` keys). The image must be a PGM file; its
+    /// path is relative to the YAML file.
+    pub fn load_map_yaml(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let map = read_map_yaml(path.as_ref())?;
+        Self::load_pgm(map.image, map.resolution, map.origin, map.thresholds)
+    }
+
+    /// Parse a plain-text map. Lines `resolution <r>` and `origin <x> <y>` set the metadata
+    /// (defaults: 1 and the world origin), lines starting with `//` are comments, and every
+    /// other non-empty line is a row of the map, the first one being the top of the map.
+    /// In rows, `.` and `0` are free cells, `#`, `@` and `1` are occupied and `?` and `-`
+    /// are unknown.
+    pub fn parse_text(text: &str) -> Result<Self, LoadError> {
+        const FORMAT: &str = "text map";
+        let mut resolution = 1.0;
+        let mut origin = [0.0, 0.0];
+        let mut rows: Vec<Vec<CellState>> = Vec::new();
+
+        let parse_f64 = |value: Option<&str>| {
+            value
+                .and_then(|v| v.parse::<f64>().ok())
+                .ok_or_else(|| invalid(FORMAT, "invalid metadata value"))
+        };
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("resolution") => resolution = parse_f64(words.next())?,
+                Some("origin") => origin = [parse_f64(words.next())?, parse_f64(words.next())?],
+                _ => {
+                    let row = line
+                        .chars()
+                        .map(|c| match c {
+                            '.' | '0' => Ok(CellState::Free),
+                            '#' | '@' | '1' => Ok(CellState::Occupied),
+                            '?' | '-' => Ok(CellState::Unknown),
+                            _ => Err(invalid(FORMAT, format!("unexpected cell '{}'", c))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if rows.first().is_some_and(|r| r.len() != row.len()) {
+                        return Err(invalid(FORMAT, "rows must have the same length"));
+                    }
+                    rows.push(row);
+                }
+            }
+        }
+
+        check_resolution(FORMAT, resolution)?;
+        let height = rows.len();
+        let width = rows.first().map_or(0, |r| r.len());
+        let mut grid = Self::new(width, height, resolution, origin);
+        for (row, cells) in rows.into_iter().enumerate() {
+            for (col, state) in cells.into_iter().enumerate() {
+                grid.set_cell(col, height - 1 - row, state);
+            }
+        }
+        Ok(grid)
+    }
+
+    pub fn load_text(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        Self::parse_text(&fs::read_to_string(path)?)
+    }
+}
+
+/// The pixels of a PGM image, row by row from the top of the image.
+pub(crate) struct PgmImage {
+    pub width: usize,
+    pub height: usize,
+    pub maxval: u32,
+    pub values: Vec<u32>,
+}
+
+/// Read a binary (`P5`) or plain (`P2`) PGM image.
+pub(crate) fn read_pgm(data: &[u8]) -> Result<PgmImage, LoadError> {
+    const FORMAT: &str = "PGM";
+    let mut header = PgmHeader { data, pos: 0 };
+
+    let magic = header.next_token()?;
+    let binary = match magic.as_str() {
+        "P5" => true,
+        "P2" => false,
+        _ => return Err(invalid(FORMAT, format!("unsupported magic '{}'", magic))),
+    };
+    let width = header.next_number()? as usize;
+    let height = header.next_number()? as usize;
+    let maxval = header.next_number()?;
+    if maxval == 0 || maxval > 65535 {
+        return Err(invalid(FORMAT, format!("invalid maximum value {}", maxval)));
+    }
+
+    let too_large = || {
+        invalid(
+            FORMAT,
+            format!("image of {} x {} is too large", width, height),
+        )
+    };
+    let pixel_count = width.checked_mul(height).ok_or_else(too_large)?;
+
+    let mut values;
+    if binary {
+        // a single whitespace character separates the header from the pixels
+        let start = header.pos + 1;
+        let bytes_per_pixel = if maxval > 255 { 2 } else { 1 };
+        let end = pixel_count
+            .checked_mul(bytes_per_pixel)
+            .and_then(|n| n.checked_add(start))
+            .ok_or_else(too_large)?;
+        let pixels = data
+            .get(start..end)
+            .ok_or_else(|| invalid(FORMAT, "not enough pixel data"))?;
+        values = Vec::with_capacity(pixel_count);
+        values.extend(pixels.chunks(bytes_per_pixel).map(|c| match c {
+            [v] => *v as u32,
+            [hi, lo] => ((*hi as u32) << 8) | *lo as u32,
+            _ => unreachable!(),
+        }));
+    } else {
+        // every pixel takes at least two bytes, so the header cannot make this allocate more
+        // than the data
+        values = Vec::with_capacity(pixel_count.min(data.len() / 2));
+        for _ in 0..pixel_count {
+            values.push(header.next_number()?);
+        }
+    }
+    Ok(PgmImage {
+        width,
+        height,
+        maxval,
+        values,
+    })
+}
+
+/// The content of a ROS map server YAML file.
+pub(crate) struct MapYaml {
+    /// The path of the image, relative to the working directory.
+    pub image: PathBuf,
+    pub resolution: f64,
+    pub origin: [f64; 2],
+    pub thresholds: OccupancyThresholds,
+}
+
+pub(crate) fn read_map_yaml(path: &Path) -> Result<MapYaml, LoadError> {
+    const FORMAT: &str = "map YAML";
+    let content = fs::read_to_string(path)?;
+
+    let mut image = None;
+    let mut resolution = None;
+    let mut origin = [0.0, 0.0];
+    let mut thresholds = OccupancyThresholds::default();
+
+    let parse_f64 = |key: &str, value: &str| {
+        value
+            .parse::<f64>()
+            .map_err(|_| invalid(FORMAT, format!("invalid value '{}' for {}", value, key)))
+    };
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "image" => image = Some(value.trim_matches(|c| c == '"' || c == '\'').to_string()),
+            "resolution" => resolution = Some(parse_f64("resolution", value)?),
+            "origin" => {
+                let values = value
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .split(',')
+                    .map(|v| parse_f64("origin", v.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if values.len() < 2 {
+                    return Err(invalid(FORMAT, "origin must have at least two values"));
+                }
+                origin = [values[0], values[1]];
+            }
+            "negate" => thresholds.negate = parse_f64("negate", value)? != 0.0,
+            "occupied_thresh" => thresholds.occupied = parse_f64("occupied_thresh", value)?,
+            "free_thresh" => thresholds.free = parse_f64("free_thresh", value)?,
+            _ => {}
+        }
+    }
+
+    let image = image.ok_or_else(|| invalid(FORMAT, "missing 'image' key"))?;
+    let resolution = resolution.ok_or_else(|| invalid(FORMAT, "missing 'resolution' key"))?;
+    check_resolution(FORMAT, resolution)?;
+    Ok(MapYaml {
+        image: path.parent().unwrap_or(Path::new("")).join(image),
+        resolution,
+        origin,
+        thresholds,
+    })
+}
+
+/// Reads the whitespace separated tokens of a PGM file, skipping `#` comments.
+struct PgmHeader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl PgmHeader<'_> {
+    fn next_token(&mut self) -> Result<String, LoadError> {
+        let data = self.data;
+        loop {
+            while self.pos < data.len() && data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if self.pos < data.len() && data[self.pos] == b'#' {
+                while self.pos < data.len() && data[self.pos] != b'\n' {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = self.pos;
+        while self.pos < data.len() && !data[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(invalid("PGM", "unexpected end of data"));
+        }
+        Ok(String::from_utf8_lossy(&data[start..self.pos]).into_owned())
+    }
+
+    fn next_number(&mut self) -> Result<u32, LoadError> {
+        let token = self.next_token()?;
+        token
+            .parse()
+            .map_err(|_| invalid("PGM", format!("expected a number, found '{}'", token)))
+    }
+}
+
+/// How cells of unknown occupancy are treated.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum UnknownCellPolicy {
+    Free,
+    #[default]
+    Occupied,
+}
+
+/// A state validity checker for 2D positions in an occupancy grid.
+///
+/// The position is made of two of the values given by `StateSpace::copy_to_reals` (the first
+/// two by default), so the checker applies to a 2D `RealVectorStateSpace` as well as to the
+/// translation part of a compound space that starts with it. Positions outside of the map are
+/// invalid.
+///
+/// The clearance is a lower bound on the distance to the nearest blocked cell (or to the map
+/// border), which is what `ClearanceMotionValidator` needs. It is computed from a Euclidean
+/// distance transform of the grid corners, which gives the exact distance at every corner.
+/// Elsewhere, the largest lower bound implied by the four corners of the cell is reported, so
+/// the clearance is exact at the corners and never overestimates the true distance.
+pub struct OccupancyGridValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    grid: OccupancyGrid,
+    unknown_policy: UnknownCellPolicy,
+    position_indices: [usize; 2],
+    corner_clearance: Vec<f64>,
+}
+
+impl OccupancyGridValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>, grid: OccupancyGrid) -> Self {
+        let mut checker = Self {
+            state_space,
+            grid,
+            unknown_policy: UnknownCellPolicy::default(),
+            position_indices: [0, 1],
+            corner_clearance: Vec::new(),
+        };
+        checker.compute_clearance();
+        checker
+    }
+
+    pub fn get_grid(&self) -> &OccupancyGrid {
+        &self.grid
+    }
+
+    pub fn set_unknown_policy(&mut self, policy: UnknownCellPolicy) {
+        self.unknown_policy = policy;
+        self.compute_clearance();
+    }
+
+    pub fn get_unknown_policy(&self) -> UnknownCellPolicy {
+        self.unknown_policy
+    }
+
+    /// Select which of the real values of a state are its x and y position.
+    pub fn set_position_indices(&mut self, x: usize, y: usize) {
+        self.position_indices = [x, y];
+    }
+
+    fn is_blocked(&self, x: usize, y: usize) -> bool {
+        match self.grid.get_cell(x, y) {
+            CellState::Free => false,
+            CellState::Occupied => true,
+            CellState::Unknown => self.unknown_policy == UnknownCellPolicy::Occupied,
+        }
+    }
+
+    /// The distance from a lattice point to an axis-aligned square whose corners lie on the
+    /// lattice is always reached at a lattice point, so a distance transform over the corners
+    /// of the cells is exact at the corners.
+    fn compute_clearance(&mut self) {
+        let (w, h) = (self.grid.width, self.grid.height);
+        let mut sites = vec![false; (w + 1) * (h + 1)];
+        for cy in 0..=h {
+            for cx in 0..=w {
+                let border = cx == 0 || cy == 0 || cx == w || cy == h;
+                let touches_blocked = (cy.saturating_sub(1)..(cy + 1).min(h)).any(|y| {
+                    (cx.saturating_sub(1)..(cx + 1).min(w)).any(|x| self.is_blocked(x, y))
+                });
+                sites[cy * (w + 1) + cx] = border || touches_blocked;
+            }
+        }
+
+        self.corner_clearance = euclidean_distance_transform(&[h + 1, w + 1], &sites);
+        let resolution = self.grid.resolution;
+        self.corner_clearance
+            .iter_mut()
+            .for_each(|d| *d *= resolution);
+    }
+
+    fn position(&self, state: &StateId) -> [f64; 2] {
+        let mut reals = Vec::new();
+        self.state_space.copy_to_reals(&mut reals, state);
+        [
+            reals[self.position_indices[0]],
+            reals[self.position_indices[1]],
+        ]
+    }
+
+    /// Clearance of a world position, or `None` if it is outside of the map.
+    fn position_clearance(&self, position: [f64; 2]) -> Option<f64> {
+        let (x, y) = self.grid.world_to_cell(position)?;
+        if self.is_blocked(x, y) {
+            return Some(0.0);
+        }
+
+        let w = self.grid.width;
+        let resolution = self.grid.resolution;
+        let mut clearance: f64 = 0.0;
+        for (cx, cy) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+            let corner = [
+                self.grid.origin[0] + cx as f64 * resolution,
+                self.grid.origin[1] + cy as f64 * resolution,
+            ];
+            let offset =
+                ((position[0] - corner[0]).powi(2) + (position[1] - corner[1]).powi(2)).sqrt();
+            clearance = clearance.max(self.corner_clearance[cy * (w + 1) + cx] - offset);
+        }
+        Some(clearance)
+    }
+}
+
+impl StateValidityChecker for OccupancyGridValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        match self.grid.world_to_cell(self.position(state)) {
+            Some((x, y)) => !self.is_blocked(x, y),
+            None => false,
+        }
+    }
+
+    fn is_valid_with_distance(&self, state: &StateId) -> (bool, Option<f64>) {
+        let position = self.position(state);
+        match self.grid.world_to_cell(position) {
+            Some((x, y)) => (!self.is_blocked(x, y), self.position_clearance(position)),
+            None => (false, Some(0.0)),
+        }
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        Some(self.position_clearance(self.position(state)).unwrap_or(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::RealVectorState;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    const MAP: &str = "
+        // a 4 x 3 map with a wall on the right
+        resolution 0.5
+        origin -1 0
+        ...#
+        ..?#
+        ....
+    ";
+
+    #[test]
+    fn test_occupancy_grid_validity_checker() {
+        let grid = OccupancyGrid::parse_text(MAP).unwrap();
+        assert_eq!((grid.get_width(), grid.get_height()), (4, 3));
+        assert_eq!(grid.get_cell(3, 2), CellState::Occupied);
+        assert_eq!(grid.get_cell(2, 1), CellState::Unknown);
+        assert_eq!(grid.get_cell(3, 0), CellState::Free);
+
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        grid.set_space_bounds(&mut space);
+        assert_eq!(space.bounds.low.as_slice(), &[-1.0, 0.0]);
+        assert_eq!(space.bounds.high.as_slice(), &[1.0, 1.5]);
+        let space = Rc::new(space);
+
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        let mut checker = OccupancyGridValidityChecker::new(space.clone(), grid);
+        assert!(checker.is_valid(&state(-0.75, 1.25)));
+        assert!(!checker.is_valid(&state(0.75, 1.25)));
+        assert!(!checker.is_valid(&state(0.25, 0.75)));
+        assert!(!checker.is_valid(&state(1.25, 0.25)));
+
+        // the corner at (0, 0.5) touches the unknown cell, the border is 0.5 away
+        let clearance = checker.clearance(&state(-0.5, 0.5)).unwrap();
+        assert!((clearance - 0.5).abs() < 1e-12);
+
+        // the clearance is a lower bound of the exact distance to the blocked cells and the
+        // border, and is exact at the corners of the cells
+        let grid = checker.get_grid().clone();
+        let exact = |p: [f64; 2]| {
+            let (low, high) = grid.get_extent();
+            let mut d = (0..2)
+                .map(|i| f64::min(p[i] - low[i], high[i] - p[i]))
+                .fold(f64::INFINITY, f64::min);
+            for (x, y) in [(2, 1), (3, 1), (3, 2)] {
+                let c = grid.cell_to_world(x, y);
+                let dx = ((p[0] - c[0]).abs() - 0.25).max(0.0);
+                let dy = ((p[1] - c[1]).abs() - 0.25).max(0.0);
+                d = d.min((dx * dx + dy * dy).sqrt());
+            }
+            d
+        };
+        for i in 0..=16 {
+            for j in 0..=12 {
+                let p = [-1.0 + i as f64 / 8.0, j as f64 / 8.0];
+                let clearance = checker.clearance(&state(p[0], p[1])).unwrap();
+                if grid.world_to_cell(p).is_some() {
+                    assert!(clearance <= exact(p) + 1e-12);
+                }
+                if i % 4 == 0 && j % 4 == 0 && i < 16 && j < 12 {
+                    assert!((clearance - exact(p)).abs() < 1e-12);
+                }
+            }
+        }
+
+        checker.set_unknown_policy(UnknownCellPolicy::Free);
+        assert!(checker.is_valid(&state(0.25, 0.75)));
+        let clearance = checker.clearance(&state(-0.25, 0.75)).unwrap();
+        assert!(clearance > 0.0 && clearance <= 0.75);
+    }
+
+    #[test]
+    fn test_parse_pgm() {
+        let pgm = b"P2\n# occupancy\n3 2\n255\n255 0 205\n0 255 255\n";
+        let grid =
+            OccupancyGrid::parse_pgm(pgm, 0.1, [0.0, 0.0], OccupancyThresholds::default()).unwrap();
+        assert_eq!(grid.get_cell(0, 1), CellState::Free);
+        assert_eq!(grid.get_cell(1, 1), CellState::Occupied);
+        assert_eq!(grid.get_cell(2, 1), CellState::Unknown);
+        assert_eq!(grid.get_cell(0, 0), CellState::Occupied);
+
+        let mut binary = b"P5 3 2 255\n".to_vec();
+        binary.extend_from_slice(&[255, 0, 205, 0, 255, 255]);
+        let grid =
+            OccupancyGrid::parse_pgm(&binary, 0.1, [0.0, 0.0], OccupancyThresholds::default())
+                .unwrap();
+        assert_eq!(grid.get_cell(1, 1), CellState::Occupied);
+        assert_eq!(grid.get_cell(1, 0), CellState::Free);
+
+        // hostile headers are rejected instead of overflowing or allocating
+        let huge = b"P5 4294967295 4294967295 65535\n";
+        assert!(OccupancyGrid::parse_pgm(huge, 0.1, [0.0, 0.0], Default::default()).is_err());
+        let huge = b"P2 4294967295 4294967295 255\n0\n";
+        assert!(OccupancyGrid::parse_pgm(huge, 0.1, [0.0, 0.0], Default::default()).is_err());
+
+        // so are bad resolutions, whether given by the caller or by a YAML file
+        assert!(OccupancyGrid::parse_pgm(pgm, 0.0, [0.0, 0.0], Default::default()).is_err());
+        assert!(OccupancyGrid::parse_text("resolution nan\n.#\n").is_err());
+        let yaml = std::env::temp_dir().join(format!("sbmp-map-{}.yaml", std::process::id()));
+        fs::write(&yaml, "image: map.pgm\nresolution: 0\n").unwrap();
+        let loaded = OccupancyGrid::load_map_yaml(&yaml);
+        fs::remove_file(&yaml).unwrap();
+        assert!(matches!(loaded, Err(LoadError::InvalidFormat { .. })));
+    }
+}
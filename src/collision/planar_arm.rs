@@ -0,0 +1,270 @@
+use std::rc::Rc;
+
+use crate::base::{
+    spaces::real_vector_state_space::RealVectorStateSpace, state_allocator::StateId,
+    state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+
+use super::geometric_world::polygon_contains;
+
+type Point2 = [f64; 2];
+
+fn sub(a: Point2, b: Point2) -> Point2 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dot(a: Point2, b: Point2) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: Point2, b: Point2) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn point_segment_distance(p: Point2, a: Point2, b: Point2) -> f64 {
+    let (ab, ap) = (sub(b, a), sub(p, a));
+    let length = dot(ab, ab);
+    let t = if length <= f64::EPSILON {
+        0.0
+    } else {
+        (dot(ap, ab) / length).clamp(0.0, 1.0)
+    };
+    let closest = [a[0] + ab[0] * t, a[1] + ab[1] * t];
+    let d = sub(p, closest);
+    dot(d, d).sqrt()
+}
+
+/// Whether the segments `[a, b]` and `[c, d]` properly cross.
+fn segments_cross(a: Point2, b: Point2, c: Point2, d: Point2) -> bool {
+    let (ab, cd) = (sub(b, a), sub(d, c));
+    let (o1, o2) = (cross(ab, sub(c, a)), cross(ab, sub(d, a)));
+    let (o3, o4) = (cross(cd, sub(a, c)), cross(cd, sub(b, c)));
+    o1 * o2 < 0.0 && o3 * o4 < 0.0
+}
+
+/// Distance between the segments `[a, b]` and `[c, d]`.
+fn segment_distance(a: Point2, b: Point2, c: Point2, d: Point2) -> f64 {
+    if segments_cross(a, b, c, d) {
+        return 0.0;
+    }
+    point_segment_distance(a, c, d)
+        .min(point_segment_distance(b, c, d))
+        .min(point_segment_distance(c, a, b))
+        .min(point_segment_distance(d, a, b))
+}
+
+/// A planar serial chain of links connected by revolute joints.
+///
+/// Joint `i` rotates link `i` relative to link `i - 1` (or to the x axis for the first link),
+/// and every link is a capsule of radius `link_radius` around the segment between its joint
+/// and the next one.
+#[derive(Debug, Clone)]
+pub struct PlanarArm {
+    base: Point2,
+    link_lengths: Vec<f64>,
+    link_radius: f64,
+    obstacles: Vec<Vec<Point2>>,
+}
+
+impl PlanarArm {
+    pub fn new(base: Point2, link_lengths: Vec<f64>, link_radius: f64) -> Self {
+        assert!(!link_lengths.is_empty(), "The arm needs at least one link");
+        if link_radius < 0.0 {
+            panic!("The link radius cannot be negative");
+        }
+        Self {
+            base,
+            link_lengths,
+            link_radius,
+            obstacles: Vec::new(),
+        }
+    }
+
+    /// An arm with `n` links of equal length, adding up to a total length of 1.
+    pub fn uniform(n: usize, link_radius: f64) -> Self {
+        Self::new([0.0, 0.0], vec![1.0 / n as f64; n], link_radius)
+    }
+
+    pub fn get_link_count(&self) -> usize {
+        self.link_lengths.len()
+    }
+
+    pub fn get_link_lengths(&self) -> &[f64] {
+        &self.link_lengths
+    }
+
+    pub fn get_link_radius(&self) -> f64 {
+        self.link_radius
+    }
+
+    /// Add a polygonal obstacle; it does not need to be convex.
+    pub fn add_obstacle(&mut self, vertices: Vec<Point2>) {
+        assert!(
+            vertices.len() >= 3,
+            "A polygon needs at least three vertices"
+        );
+        self.obstacles.push(vertices);
+    }
+
+    pub fn get_obstacles(&self) -> &[Vec<Point2>] {
+        &self.obstacles
+    }
+
+    /// A joint space with one dimension per joint, each bounded by `[-limit, limit]`.
+    pub fn create_state_space(&self, limit: f64) -> RealVectorStateSpace {
+        let mut space = RealVectorStateSpace::new();
+        for i in 0..self.get_link_count() {
+            space.add_dimension(Some(format!("joint_{}", i)), -limit, limit);
+        }
+        space
+    }
+
+    /// Positions of the base, of every joint and of the end effector.
+    pub fn forward_kinematics(&self, joints: &[f64]) -> Vec<Point2> {
+        assert!(
+            joints.len() >= self.get_link_count(),
+            "Expected {} joint values",
+            self.get_link_count()
+        );
+        let mut positions = Vec::with_capacity(self.get_link_count() + 1);
+        let mut position = self.base;
+        let mut angle = 0.0;
+        positions.push(position);
+        for (length, joint) in self.link_lengths.iter().zip(joints) {
+            angle += joint;
+            position = [
+                position[0] + length * angle.cos(),
+                position[1] + length * angle.sin(),
+            ];
+            positions.push(position);
+        }
+        positions
+    }
+
+    pub fn end_effector(&self, joints: &[f64]) -> Point2 {
+        *self.forward_kinematics(joints).last().unwrap()
+    }
+
+    /// Smallest distance between the capsules of two links that are not adjacent, infinity
+    /// for arms of less than three links. Negative when the arm intersects itself.
+    pub fn self_clearance(&self, positions: &[Point2]) -> f64 {
+        let n = self.get_link_count();
+        let mut clearance = f64::INFINITY;
+        for i in 0..n {
+            for j in i + 2..n {
+                let d = segment_distance(
+                    positions[i],
+                    positions[i + 1],
+                    positions[j],
+                    positions[j + 1],
+                );
+                clearance = clearance.min(d - 2.0 * self.link_radius);
+            }
+        }
+        clearance
+    }
+
+    /// Smallest distance between a link capsule and an obstacle, infinity if there are no
+    /// obstacles. Negative when a link touches an obstacle.
+    pub fn environment_clearance(&self, positions: &[Point2]) -> f64 {
+        let mut clearance = f64::INFINITY;
+        for link in positions.windows(2) {
+            let (a, b) = (link[0], link[1]);
+            for obstacle in &self.obstacles {
+                let d = if polygon_contains(obstacle, a) || polygon_contains(obstacle, b) {
+                    0.0
+                } else {
+                    (0..obstacle.len())
+                        .map(|k| {
+                            segment_distance(a, b, obstacle[k], obstacle[(k + 1) % obstacle.len()])
+                        })
+                        .fold(f64::INFINITY, f64::min)
+                };
+                clearance = clearance.min(d - self.link_radius);
+            }
+        }
+        clearance
+    }
+
+    /// Clearance of the arm for the given joint values, accounting for both self collisions
+    /// and collisions with obstacles.
+    pub fn clearance(&self, joints: &[f64]) -> f64 {
+        let positions = self.forward_kinematics(joints);
+        self.self_clearance(&positions)
+            .min(self.environment_clearance(&positions))
+    }
+}
+
+/// A state validity checker for a `PlanarArm`. Joint values are the first values given by
+/// `StateSpace::copy_to_reals`, so the joints may come first in a compound state.
+pub struct PlanarArmValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    arm: PlanarArm,
+}
+
+impl PlanarArmValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>, arm: PlanarArm) -> Self {
+        assert!(
+            state_space.get_dimension() as usize >= arm.get_link_count(),
+            "The state space has fewer dimensions than the arm has joints"
+        );
+        Self { state_space, arm }
+    }
+
+    pub fn get_arm(&self) -> &PlanarArm {
+        &self.arm
+    }
+
+    fn joints(&self, state: &StateId) -> Vec<f64> {
+        let mut joints = Vec::new();
+        self.state_space.copy_to_reals(&mut joints, state);
+        joints
+    }
+}
+
+impl StateValidityChecker for PlanarArmValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.arm.clearance(&self.joints(state)) > 0.0
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        Some(self.arm.clearance(&self.joints(state)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::RealVectorState;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    #[test]
+    fn test_planar_arm() {
+        let mut arm = PlanarArm::uniform(4, 0.02);
+        let positions = arm.forward_kinematics(&[FRAC_PI_2, -FRAC_PI_2, 0.0, 0.0]);
+        assert!((positions[1][0]).abs() < 1e-12 && (positions[1][1] - 0.25).abs() < 1e-12);
+        let end = arm.end_effector(&[FRAC_PI_2, -FRAC_PI_2, 0.0, 0.0]);
+        assert!((end[0] - 0.75).abs() < 1e-12 && (end[1] - 0.25).abs() < 1e-12);
+
+        // a box above the straight arm
+        arm.add_obstacle(vec![[0.4, 0.2], [0.6, 0.2], [0.6, 0.4], [0.4, 0.4]]);
+        let space = Rc::new(arm.create_state_space(PI));
+        let checker = PlanarArmValidityChecker::new(space.clone(), arm);
+        let state = |joints: Vec<f64>| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(joints),
+            })
+        };
+
+        let straight = state(vec![0.0; 4]);
+        assert!(checker.is_valid(&straight));
+        assert!((checker.clearance(&straight).unwrap() - 0.18).abs() < 1e-12);
+
+        // folding the last links back onto the first one
+        assert!(!checker.is_valid(&state(vec![0.0, 0.0, PI - 0.05, 0.0])));
+        // raising the arm into the box
+        assert!(!checker.is_valid(&state(vec![0.5, 0.0, 0.0, 0.0])));
+    }
+}
@@ -0,0 +1,292 @@
+use std::{cell::RefCell, fs, path::Path, rc::Rc};
+
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+use crate::datastructure::nearest_neighbours_kd_tree::{EuclideanMetric, VpAvl};
+use crate::error::LoadError;
+
+/// A set of 3D points, as produced by a lidar or a depth camera.
+#[derive(Debug, Clone, Default)]
+pub struct PointCloud {
+    points: Vec<Vec<f64>>,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<[f64; 3]>) -> Self {
+        Self {
+            points: points.into_iter().map(|p| p.to_vec()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn get_points(&self) -> impl Iterator<Item = &[f64]> {
+        self.points.iter().map(|p| p.as_slice())
+    }
+
+    /// Parse a point cloud with one point per line, its first three columns (separated by
+    /// spaces, tabs or commas) being the coordinates. Other columns, empty lines and `#`
+    /// comments are ignored.
+    pub fn parse_xyz(text: &str) -> Result<Self, LoadError> {
+        let mut points = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let values = line
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|w| !w.is_empty())
+                .take(3)
+                .map(|w| w.parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_xyz(n, "invalid number"))?;
+            match values.len() {
+                0 => continue,
+                3 => points.push(values),
+                _ => return Err(invalid_xyz(n, "a point needs three coordinates")),
+            }
+        }
+        Ok(Self { points })
+    }
+
+    /// Parse the vertices of an ASCII PLY file, using their `x`, `y` and `z` properties.
+    pub fn parse_ply(text: &str) -> Result<Self, LoadError> {
+        let error = |message: &str| LoadError::InvalidFormat {
+            format: "PLY",
+            message: message.to_string(),
+        };
+
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some("ply") {
+            return Err(error("missing 'ply' magic number"));
+        }
+
+        // elements are laid out in the order of the header; only the vertices are read
+        let mut vertices_before = 0;
+        let mut vertex_count = None;
+        let mut properties: Vec<String> = Vec::new();
+        let mut current_is_vertex = false;
+        for line in lines.by_ref() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", ..] => {}
+                ["format", ..] => return Err(error("only ASCII PLY files are supported")),
+                ["element", name, count] => {
+                    let count: usize = count.parse().map_err(|_| error("invalid element count"))?;
+                    current_is_vertex = *name == "vertex";
+                    if current_is_vertex {
+                        vertex_count = Some(count);
+                    } else if vertex_count.is_none() {
+                        vertices_before += count;
+                    }
+                }
+                ["property", "list", ..] if current_is_vertex => {
+                    return Err(error("list properties on vertices are not supported"))
+                }
+                ["property", .., name] if current_is_vertex => properties.push(name.to_string()),
+                ["end_header"] => break,
+                _ => {}
+            }
+        }
+
+        let vertex_count = vertex_count.ok_or_else(|| error("missing vertex element"))?;
+        let column = |name: &str| {
+            properties
+                .iter()
+                .position(|p| p == name)
+                .ok_or_else(|| error(&format!("missing vertex property '{}'", name)))
+        };
+        let columns = [column("x")?, column("y")?, column("z")?];
+
+        let mut points = Vec::with_capacity(vertex_count);
+        for line in lines.skip(vertices_before).take(vertex_count) {
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if values.len() != properties.len() {
+                return Err(error("vertex does not match its properties"));
+            }
+            let point = columns
+                .iter()
+                .map(|&c| values[c].parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| error("invalid number"))?;
+            points.push(point);
+        }
+        if points.len() != vertex_count {
+            return Err(error("missing vertices"));
+        }
+        Ok(Self { points })
+    }
+
+    /// Load a `.ply` or `.xyz` point cloud.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ply") => Self::parse_ply(&fs::read_to_string(path)?),
+            Some("xyz") => Self::parse_xyz(&fs::read_to_string(path)?),
+            _ => Err(LoadError::InvalidFormat {
+                format: "point cloud",
+                message: format!("unsupported file extension for {}", path.display()),
+            }),
+        }
+    }
+}
+
+fn invalid_xyz(line: usize, message: &str) -> LoadError {
+    LoadError::InvalidFormat {
+        format: "XYZ",
+        message: format!("line {}: {}", line + 1, message),
+    }
+}
+
+/// A sphere of the robot model, in world coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: [f64; 3],
+    pub radius: f64,
+}
+
+/// Writes to its second argument the spheres covering the robot at the given state.
+pub type SphereModelFn = Box<dyn Fn(&StateId, &mut Vec<Sphere>)>;
+
+/// A sphere model for a robot made of a single sphere of the given radius, centered at the
+/// first three values given by `StateSpace::copy_to_reals`.
+pub fn single_sphere_model(state_space: Rc<dyn StateSpace>, radius: f64) -> SphereModelFn {
+    Box::new(move |state, spheres| {
+        let mut reals = Vec::new();
+        state_space.copy_to_reals(&mut reals, state);
+        spheres.clear();
+        spheres.push(Sphere {
+            center: [reals[0], reals[1], reals[2]],
+            radius,
+        });
+    })
+}
+
+/// A state validity checker for a robot modelled as a set of spheres, among obstacles given
+/// as a point cloud. The robot collides when a point lies within one of its spheres.
+///
+/// The points are indexed in a `VpAvl` tree, and the clearance is the smallest distance
+/// between a sphere and its nearest point.
+pub struct PointCloudValidityChecker {
+    tree: VpAvl<Vec<f64>, EuclideanMetric<Vec<f64>>>,
+    sphere_model: SphereModelFn,
+    spheres: RefCell<Vec<Sphere>>,
+}
+
+impl PointCloudValidityChecker {
+    pub fn new(cloud: PointCloud, sphere_model: SphereModelFn) -> Self {
+        let tree = if cloud.is_empty() {
+            VpAvl::new(EuclideanMetric::default())
+        } else {
+            VpAvl::bulk_insert(EuclideanMetric::default(), cloud.points)
+        };
+        Self {
+            tree,
+            sphere_model,
+            spheres: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn get_point_count(&self) -> usize {
+        self.tree.size()
+    }
+
+    /// Distance from `point` to the nearest point of the cloud, or infinity if it is empty.
+    pub fn nearest_distance(&self, point: &[f64; 3]) -> f64 {
+        let query = point.to_vec();
+        self.tree
+            .nn_dist_iter(&query)
+            .next()
+            .map_or(f64::INFINITY, |(_, d)| d)
+    }
+}
+
+impl StateValidityChecker for PointCloudValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        self.clearance(state).is_some_and(|c| c > 0.0)
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        let mut spheres = self.spheres.borrow_mut();
+        (self.sphere_model)(state, &mut spheres);
+        Some(
+            spheres
+                .iter()
+                .map(|s| self.nearest_distance(&s.center) - s.radius)
+                .fold(f64::INFINITY, f64::min),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_parse_point_clouds() {
+        let ply = "ply
+format ascii 1.0
+element vertex 2
+property float y
+property float x
+property float z
+property uchar red
+element face 0
+property list uchar int vertex_indices
+end_header
+1 2 3 255
+4 5 6 0
+";
+        let cloud = PointCloud::parse_ply(ply).unwrap();
+        let points: Vec<&[f64]> = cloud.get_points().collect();
+        assert_eq!(points, vec![&[2.0, 1.0, 3.0][..], &[5.0, 4.0, 6.0][..]]);
+        assert!(PointCloud::parse_ply("ply\nformat binary_little_endian 1.0\n").is_err());
+
+        let cloud = PointCloud::parse_xyz("# scan\n1,2,3\n\n4 5 6 0.5\n").unwrap();
+        assert_eq!(cloud.len(), 2);
+        assert!(PointCloud::parse_xyz("1 2").is_err());
+    }
+
+    #[test]
+    fn test_point_cloud_validity_checker() {
+        let mut space = RealVectorStateSpace::new();
+        for _ in 0..3 {
+            space.add_dimension(None, -10.0, 10.0);
+        }
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y, 0.0]),
+            })
+        };
+
+        // a wall of points along x = 1
+        let cloud = PointCloud::new((-10..=10).map(|i| [1.0, i as f64 * 0.1, 0.0]).collect());
+        let checker =
+            PointCloudValidityChecker::new(cloud, single_sphere_model(space.clone(), 0.25));
+        assert_eq!(checker.get_point_count(), 21);
+
+        assert!(checker.is_valid(&state(0.0, 0.0)));
+        assert!((checker.clearance(&state(0.0, 0.0)).unwrap() - 0.75).abs() < 1e-9);
+        assert!(!checker.is_valid(&state(0.8, 0.0)));
+        assert!(checker.clearance(&state(0.8, 0.0)).unwrap() < 0.0);
+
+        let empty = PointCloudValidityChecker::new(
+            PointCloud::default(),
+            single_sphere_model(space.clone(), 0.25),
+        );
+        assert!(empty.is_valid(&state(1.0, 0.0)));
+    }
+}
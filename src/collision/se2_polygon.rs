@@ -0,0 +1,578 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+
+use crate::base::{
+    motion_validator::{
+        discrete_motion_validator::DiscreteMotionValidator, MotionCheckStats, MotionValidator,
+    },
+    spaces::se2_state_space::angle_difference,
+    state_allocator::StateId,
+    state_validity_checker::StateValidityChecker,
+    statespace::StateSpace,
+};
+
+pub type Point2 = [f64; 2];
+
+/// A planar axis-aligned bounding box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb2 {
+    pub min: Point2,
+    pub max: Point2,
+}
+
+impl Aabb2 {
+    pub fn from_points(points: &[Point2]) -> Self {
+        let mut aabb = Aabb2 {
+            min: [f64::INFINITY; 2],
+            max: [f64::NEG_INFINITY; 2],
+        };
+        for p in points {
+            aabb.min = [aabb.min[0].min(p[0]), aabb.min[1].min(p[1])];
+            aabb.max = [aabb.max[0].max(p[0]), aabb.max[1].max(p[1])];
+        }
+        aabb
+    }
+
+    pub fn inflated(&self, margin: f64) -> Self {
+        Aabb2 {
+            min: [self.min[0] - margin, self.min[1] - margin],
+            max: [self.max[0] + margin, self.max[1] + margin],
+        }
+    }
+
+    pub fn intersects(&self, other: &Aabb2) -> bool {
+        (0..2).all(|k| self.min[k] <= other.max[k] && other.min[k] <= self.max[k])
+    }
+}
+
+fn cross(o: Point2, a: Point2, b: Point2) -> f64 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+/// Whether the polygon is convex; collinear vertices are allowed.
+pub fn is_convex(vertices: &[Point2]) -> bool {
+    let n = vertices.len();
+    let mut sign = 0.0;
+    for i in 0..n {
+        let c = cross(vertices[i], vertices[(i + 1) % n], vertices[(i + 2) % n]);
+        if c.abs() <= f64::EPSILON {
+            continue;
+        }
+        if sign * c < 0.0 {
+            return false;
+        }
+        sign = c;
+    }
+    true
+}
+
+/// Convex hull of a set of points, in counter-clockwise order (Andrew's monotone chain).
+pub fn convex_hull(points: &[Point2]) -> Vec<Point2> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull: Vec<Point2> = Vec::with_capacity(2 * points.len());
+    for pass in 0..2 {
+        let start = hull.len();
+        let iter: Box<dyn Iterator<Item = &Point2>> = if pass == 0 {
+            Box::new(points.iter())
+        } else {
+            Box::new(points.iter().rev())
+        };
+        for &p in iter {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], p) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // the last point of each chain is the first point of the other one
+        hull.pop();
+    }
+    hull
+}
+
+/// Projection of a polygon on an axis.
+fn project(vertices: &[Point2], axis: Point2) -> (f64, f64) {
+    vertices
+        .iter()
+        .map(|v| v[0] * axis[0] + v[1] * axis[1])
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), x| {
+            (lo.min(x), hi.max(x))
+        })
+}
+
+/// Separating axis test between two convex polygons. The polygons are considered to
+/// intersect if they are closer than `margin` along every edge normal, which makes the test
+/// conservative for polygons inflated by `margin`.
+pub fn sat_intersect(a: &[Point2], b: &[Point2], margin: f64) -> bool {
+    for polygon in [a, b] {
+        let n = polygon.len();
+        for i in 0..n {
+            let (p, q) = (polygon[i], polygon[(i + 1) % n]);
+            let normal = [p[1] - q[1], q[0] - p[0]];
+            let length = normal[0].hypot(normal[1]);
+            if length <= f64::EPSILON {
+                continue;
+            }
+            let axis = [normal[0] / length, normal[1] / length];
+            let (a_lo, a_hi) = project(a, axis);
+            let (b_lo, b_hi) = project(b, axis);
+            if b_lo > a_hi + margin || a_lo > b_hi + margin {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// A uniform grid over the plane, mapping each cell to the objects whose bounding box
+/// overlaps it. Used as the broad phase of collision checking.
+#[derive(Debug, Clone)]
+pub struct AabbGrid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl AabbGrid {
+    pub fn new(cell_size: f64) -> Self {
+        if cell_size <= 0.0 {
+            panic!("The cell size of the grid must be strictly positive");
+        }
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_range(&self, aabb: &Aabb2) -> ((i64, i64), (i64, i64)) {
+        let cell = |x: f64| (x / self.cell_size).floor() as i64;
+        (
+            (cell(aabb.min[0]), cell(aabb.max[0])),
+            (cell(aabb.min[1]), cell(aabb.max[1])),
+        )
+    }
+
+    pub fn insert(&mut self, index: usize, aabb: &Aabb2) {
+        let ((x0, x1), (y0, y1)) = self.cell_range(aabb);
+        for i in x0..=x1 {
+            for j in y0..=y1 {
+                self.cells.entry((i, j)).or_default().push(index);
+            }
+        }
+    }
+
+    /// Write to `result` the (sorted, unique) indices of the objects that may overlap `aabb`.
+    pub fn query(&self, aabb: &Aabb2, result: &mut Vec<usize>) {
+        result.clear();
+        let ((x0, x1), (y0, y1)) = self.cell_range(aabb);
+        for i in x0..=x1 {
+            for j in y0..=y1 {
+                if let Some(indices) = self.cells.get(&(i, j)) {
+                    result.extend(indices);
+                }
+            }
+        }
+        result.sort_unstable();
+        result.dedup();
+    }
+}
+
+/// A convex polygonal robot moving among convex polygonal obstacles. Non-convex obstacles
+/// must be decomposed into convex parts.
+#[derive(Debug, Clone)]
+pub struct PolygonWorld {
+    robot: Vec<Point2>,
+    robot_radius: f64,
+    obstacles: Vec<Vec<Point2>>,
+    obstacle_aabbs: Vec<Aabb2>,
+    grid: AabbGrid,
+}
+
+impl PolygonWorld {
+    /// Create a world for the given robot footprint, expressed in the robot frame. Obstacles
+    /// are bucketed in a grid of `cell_size` cells.
+    pub fn new(robot: Vec<Point2>, cell_size: f64) -> Self {
+        assert!(
+            robot.len() >= 3 && is_convex(&robot),
+            "The robot footprint must be a convex polygon"
+        );
+        let robot_radius = robot.iter().map(|v| v[0].hypot(v[1])).fold(0.0, f64::max);
+        Self {
+            robot,
+            robot_radius,
+            obstacles: Vec::new(),
+            obstacle_aabbs: Vec::new(),
+            grid: AabbGrid::new(cell_size),
+        }
+    }
+
+    pub fn add_obstacle(&mut self, vertices: Vec<Point2>) {
+        assert!(
+            vertices.len() >= 3 && is_convex(&vertices),
+            "Obstacles must be convex polygons"
+        );
+        let aabb = Aabb2::from_points(&vertices);
+        self.grid.insert(self.obstacles.len(), &aabb);
+        self.obstacles.push(vertices);
+        self.obstacle_aabbs.push(aabb);
+    }
+
+    pub fn get_robot(&self) -> &[Point2] {
+        &self.robot
+    }
+
+    /// Distance from the origin of the robot frame to the furthest vertex of the robot.
+    pub fn get_robot_radius(&self) -> f64 {
+        self.robot_radius
+    }
+
+    pub fn get_obstacles(&self) -> &[Vec<Point2>] {
+        &self.obstacles
+    }
+
+    /// Vertices of the robot placed at `pose` (x, y, yaw).
+    pub fn robot_at(&self, pose: [f64; 3]) -> Vec<Point2> {
+        let (sin, cos) = pose[2].sin_cos();
+        self.robot
+            .iter()
+            .map(|v| {
+                [
+                    pose[0] + cos * v[0] - sin * v[1],
+                    pose[1] + sin * v[0] + cos * v[1],
+                ]
+            })
+            .collect()
+    }
+
+    /// Whether the convex `polygon`, inflated by `margin`, may touch an obstacle.
+    pub fn collides(&self, polygon: &[Point2], margin: f64) -> bool {
+        let aabb = Aabb2::from_points(polygon).inflated(margin);
+        let mut candidates = Vec::new();
+        self.grid.query(&aabb, &mut candidates);
+        candidates.iter().any(|&i| {
+            self.obstacle_aabbs[i].intersects(&aabb)
+                && sat_intersect(polygon, &self.obstacles[i], margin)
+        })
+    }
+
+    pub fn pose_in_collision(&self, pose: [f64; 3]) -> bool {
+        self.collides(&self.robot_at(pose), 0.0)
+    }
+}
+
+/// Read the pose (x, y, yaw) of a state from the first three values given by
+/// `StateSpace::copy_to_reals`.
+fn pose_of(state_space: &dyn StateSpace, state: &StateId) -> [f64; 3] {
+    let mut reals = Vec::new();
+    state_space.copy_to_reals(&mut reals, state);
+    [reals[0], reals[1], reals[2]]
+}
+
+/// A state validity checker for a polygonal robot in SE(2), placing the robot footprint at
+/// the pose of the state and testing it against the obstacles of a `PolygonWorld`.
+pub struct SE2PolygonValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    world: Arc<PolygonWorld>,
+}
+
+impl SE2PolygonValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>, world: impl Into<Arc<PolygonWorld>>) -> Self {
+        assert!(
+            state_space.get_dimension() >= 3,
+            "The state space must contain a planar pose"
+        );
+        Self {
+            state_space,
+            world: world.into(),
+        }
+    }
+
+    /// The world of the checker, which can be shared with a `SweptPolygonMotionValidator`.
+    pub fn get_world(&self) -> &Arc<PolygonWorld> {
+        &self.world
+    }
+}
+
+impl StateValidityChecker for SE2PolygonValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        !self
+            .world
+            .pose_in_collision(pose_of(self.state_space.as_ref(), state))
+    }
+}
+
+/// A motion validator for `SE2PolygonValidityChecker`, checking the area swept by the robot
+/// between two states instead of a finite number of intermediate poses, so that no obstacle
+/// can be tunnelled through.
+///
+/// The motion is split into pieces rotating by at most `max_rotation_step`. The area swept
+/// over a piece is contained in the convex hull of the footprints at its two ends, inflated by
+/// the largest deviation of a vertex from its chord. Pieces in contact are bisected down to
+/// `time_resolution` to locate the first contact.
+///
+/// Positions are interpolated linearly and orientations along the shortest arc, as
+/// `SE2StateSpace` does.
+///
+/// The swept areas are checked against a `PolygonWorld`, given to
+/// `SweptPolygonMotionValidator::with_world`. A validator created by `MotionValidator::new`
+/// only has a validity checker, whose world it cannot reach: it falls back to checking poses
+/// along the motions as `DiscreteMotionValidator` does.
+pub struct SweptPolygonMotionValidator {
+    state_space: Rc<dyn StateSpace>,
+    target: SweepTarget,
+    stats: RefCell<MotionCheckStats>,
+    max_rotation_step: f64,
+    time_resolution: f64,
+}
+
+/// What the motions are checked against.
+enum SweepTarget {
+    World(Arc<PolygonWorld>),
+    Discrete(DiscreteMotionValidator),
+}
+
+impl SweptPolygonMotionValidator {
+    pub fn with_world(state_space: Rc<dyn StateSpace>, world: Arc<PolygonWorld>) -> Self {
+        Self::with_target(state_space, SweepTarget::World(world))
+    }
+
+    fn with_target(state_space: Rc<dyn StateSpace>, target: SweepTarget) -> Self {
+        assert!(
+            state_space.get_dimension() >= 3,
+            "The state space must contain a planar pose"
+        );
+        Self {
+            state_space,
+            target,
+            stats: RefCell::new(MotionCheckStats::default()),
+            max_rotation_step: 0.1,
+            time_resolution: 1e-3,
+        }
+    }
+
+    /// Set the largest rotation covered by a single swept hull.
+    pub fn set_max_rotation_step(&mut self, step: f64) {
+        if step <= 0.0 {
+            panic!("The rotation step must be strictly positive");
+        }
+        self.max_rotation_step = step;
+    }
+
+    /// Whether motions are checked by their swept area, i.e. the validator has a world.
+    pub fn is_swept(&self) -> bool {
+        matches!(self.target, SweepTarget::World(_))
+    }
+
+    pub fn get_max_rotation_step(&self) -> f64 {
+        self.max_rotation_step
+    }
+
+    /// Set the precision, in motion time, to which the first contact is located.
+    pub fn set_time_resolution(&mut self, resolution: f64) {
+        if resolution <= 0.0 {
+            panic!("The time resolution must be strictly positive");
+        }
+        self.time_resolution = resolution;
+    }
+
+    pub fn get_time_resolution(&self) -> f64 {
+        self.time_resolution
+    }
+
+    fn pose_at(from: &[f64; 3], to: &[f64; 3], t: f64) -> [f64; 3] {
+        [
+            from[0] + (to[0] - from[0]) * t,
+            from[1] + (to[1] - from[1]) * t,
+            from[2] + angle_difference(from[2], to[2]) * t,
+        ]
+    }
+
+    /// Whether the area swept between times `a` and `b` may touch an obstacle.
+    fn swept_collides(
+        world: &PolygonWorld,
+        from: &[f64; 3],
+        to: &[f64; 3],
+        a: f64,
+        b: f64,
+    ) -> bool {
+        let (pa, pb) = (Self::pose_at(from, to, a), Self::pose_at(from, to, b));
+
+        let mut vertices = world.robot_at(pa);
+        vertices.extend(world.robot_at(pb));
+        let hull = convex_hull(&vertices);
+
+        // sagitta of the arc followed by the furthest vertex around the robot origin
+        let rotation = (pb[2] - pa[2]).abs();
+        let deviation = world.get_robot_radius() * (1.0 - (rotation / 2.0).cos());
+        world.collides(&hull, deviation)
+    }
+
+    fn first_contact_in(
+        &self,
+        world: &PolygonWorld,
+        from: &[f64; 3],
+        to: &[f64; 3],
+        a: f64,
+        b: f64,
+    ) -> Option<f64> {
+        let collides = self
+            .stats
+            .borrow_mut()
+            .time_check(|| Self::swept_collides(world, from, to, a, b));
+        if !collides {
+            return None;
+        }
+        if b - a <= self.time_resolution {
+            return Some(a);
+        }
+        let m = (a + b) / 2.0;
+        self.first_contact_in(world, from, to, a, m)
+            .or_else(|| self.first_contact_in(world, from, to, m, b))
+    }
+
+    /// The time of the first contact along the motion from s1 to s2, if any. Every swept-area
+    /// test counts as a state check in the statistics.
+    fn first_contact(&self, world: &PolygonWorld, s1: &StateId, s2: &StateId) -> Option<f64> {
+        self.stats.borrow_mut().begin_motion();
+        let from = pose_of(self.state_space.as_ref(), s1);
+        let to = pose_of(self.state_space.as_ref(), s2);
+
+        let rotation = angle_difference(from[2], to[2]).abs();
+        let pieces = (rotation / self.max_rotation_step).ceil().max(1.0) as usize;
+        let contact = (0..pieces).find_map(|i| {
+            let a = i as f64 / pieces as f64;
+            let b = (i + 1) as f64 / pieces as f64;
+            self.first_contact_in(world, &from, &to, a, b)
+        });
+        self.stats
+            .borrow_mut()
+            .record_motion(contact.is_none(), pieces as u32, contact);
+        contact
+    }
+}
+
+impl MotionValidator for SweptPolygonMotionValidator {
+    /// A validator checking poses along the motions with `checker`, as the world of the
+    /// checker is not known: use `with_world` to check swept areas.
+    fn new(state_space: Rc<dyn StateSpace>, checker: Arc<dyn StateValidityChecker>) -> Self
+    where
+        Self: Sized,
+    {
+        let discrete = DiscreteMotionValidator::new(state_space.clone(), checker);
+        Self::with_target(state_space, SweepTarget::Discrete(discrete))
+    }
+
+    fn get_motion_check_stats(&self) -> &RefCell<MotionCheckStats> {
+        match &self.target {
+            SweepTarget::World(_) => &self.stats,
+            SweepTarget::Discrete(discrete) => discrete.get_motion_check_stats(),
+        }
+    }
+
+    fn check_motion(&self, s1: &StateId, s2: &StateId) -> bool {
+        match &self.target {
+            SweepTarget::World(world) => self.first_contact(world, s1, s2).is_none(),
+            SweepTarget::Discrete(discrete) => discrete.check_motion(s1, s2),
+        }
+    }
+
+    fn check_motion_with_last_valid(
+        &self,
+        s1: &StateId,
+        s2: &StateId,
+        last_valid: &mut (Option<StateId>, f64),
+    ) -> bool {
+        let world = match &self.target {
+            SweepTarget::World(world) => world,
+            SweepTarget::Discrete(discrete) => {
+                return discrete.check_motion_with_last_valid(s1, s2, last_valid)
+            }
+        };
+
+        // assume motion starts in a valid configuration
+        match self.first_contact(world, s1, s2) {
+            None => true,
+            Some(t) => {
+                last_valid.1 = t;
+                if let Some(s) = &mut last_valid.0 {
+                    self.state_space.interpolate(s1, s2, t, s);
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_bounds::RealVectorBounds;
+    use crate::base::spaces::se2_state_space::{SE2State, SE2StateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_se2_polygon_checking() {
+        let mut space = SE2StateSpace::new();
+        let mut bounds = RealVectorBounds::new(2);
+        bounds.set_low(-5.0);
+        bounds.set_high(5.0);
+        space.set_bounds(bounds);
+        space.setup();
+        let space = Rc::new(space);
+
+        // a 2 x 0.5 forklift, and a thin wall at x in [1, 1.1] and y in [0.5, 3]
+        let mut world = PolygonWorld::new(
+            vec![[-1.0, -0.25], [1.0, -0.25], [1.0, 0.25], [-1.0, 0.25]],
+            1.0,
+        );
+        world.add_obstacle(vec![[1.0, 0.5], [1.1, 0.5], [1.1, 3.0], [1.0, 3.0]]);
+
+        let checker = Arc::new(SE2PolygonValidityChecker::new(space.clone(), world));
+        let state =
+            |x: f64, y: f64, yaw: f64| space.alloc_arena_state_with_value(SE2State::new(x, y, yaw));
+        assert!(checker.is_valid(&state(0.0, 0.0, 0.0)));
+        assert!(!checker.is_valid(&state(1.0, 0.4, 0.0)));
+        assert!(!checker.is_valid(&state(1.0, 1.0, FRAC_PI_2)));
+        assert!(checker.is_valid(&state(3.0, 1.0, FRAC_PI_2)));
+
+        let validator =
+            SweptPolygonMotionValidator::with_world(space.clone(), checker.get_world().clone());
+
+        // both ends are valid, but the robot passes through the wall
+        let (s1, s2) = (state(0.0, 1.0, FRAC_PI_2), state(3.0, 1.0, FRAC_PI_2));
+        assert!(checker.is_valid(&s1) && checker.is_valid(&s2));
+        let mut last_valid = (Some(space.alloc_state()), 0.0);
+        assert!(!validator.check_motion_with_last_valid(&s1, &s2, &mut last_valid));
+        // the robot is 0.5 wide, so it touches the wall after moving by 0.75
+        assert!((last_valid.1 - 0.25).abs() < 2e-3);
+        let reached = space.clone_state_inner_value(last_valid.0.as_ref().unwrap());
+        assert!((reached.x - 0.75).abs() < 1e-2);
+
+        // turning in place below the wall is fine
+        assert!(validator.check_motion(&state(0.0, -1.0, 0.0), &state(0.0, -1.0, 1.0)));
+        let stats = validator.get_motion_check_stats().borrow();
+        assert_eq!(stats.invalid_motion_count(), 1);
+        assert_eq!(stats.valid_motion_count(), 1);
+
+        // without the world, poses along the motion are checked instead
+        let discrete =
+            <SweptPolygonMotionValidator as MotionValidator>::new(space.clone(), checker);
+        assert!(!discrete.is_swept());
+        assert!(!discrete.check_motion(&s1, &s2));
+        assert!(discrete.check_motion(&state(0.0, -1.0, 0.0), &state(0.0, -1.0, 1.0)));
+        assert_eq!(
+            discrete
+                .get_motion_check_stats()
+                .borrow()
+                .checked_motion_count(),
+            2
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use std::rc::Rc;
+
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+use crate::util::distance_transform::euclidean_distance_transform;
+
+use super::occupancy_grid::{CellState, OccupancyGrid, UnknownCellPolicy};
+
+/// A signed distance field sampled on a regular 2D or 3D voxel grid: positive in free space,
+/// negative inside obstacles.
+///
+/// Samples are located at voxel centers. Voxel `(i, j[, k])` is stored in row-major order
+/// with the first index varying fastest, and its lower corner is at
+/// `origin + (i, j[, k]) * resolution`.
+#[derive(Debug, Clone)]
+pub struct SignedDistanceField {
+    dims: Vec<usize>,
+    resolution: f64,
+    origin: Vec<f64>,
+    values: Vec<f64>,
+}
+
+impl SignedDistanceField {
+    /// Build the field from the occupancy of every voxel. The boundary between an occupied
+    /// and a free voxel lies halfway between their centers, and the value of a voxel is the
+    /// exact distance from its center to the nearest point of a voxel of the other kind.
+    pub fn from_occupancy(
+        dims: &[usize],
+        resolution: f64,
+        origin: &[f64],
+        occupied: &[bool],
+    ) -> Self {
+        assert!(
+            (2..=3).contains(&dims.len()),
+            "Signed distance fields are only supported in 2 or 3 dimensions"
+        );
+        assert_eq!(dims.len(), origin.len());
+        if dims.contains(&0) {
+            panic!("The field must have at least one voxel along each dimension");
+        }
+        assert_eq!(occupied.len(), dims.iter().product());
+        if resolution <= 0.0 {
+            panic!("The resolution of the field must be strictly positive");
+        }
+
+        // The nearest point of a voxel to the center of another one always has coordinates
+        // that are multiples of half a voxel, so the distance transform is computed on the
+        // lattice of the corners, edge and face midpoints and centers of the voxels, in which
+        // every voxel covers 3 x 3 (x 3) nodes.
+        let n = dims.len();
+        let lattice: Vec<usize> = dims.iter().map(|d| 2 * d + 1).collect();
+        let lattice_index = |node: &[usize]| {
+            node.iter()
+                .zip(&lattice)
+                .rev()
+                .fold(0, |acc, (i, d)| acc * d + i)
+        };
+        let node_count: usize = lattice.iter().product();
+        let mut occupied_nodes = vec![false; node_count];
+        let mut free_nodes = vec![false; node_count];
+        let mut voxel = vec![0usize; n];
+        let mut node = vec![0usize; n];
+        for (i, &is_occupied) in occupied.iter().enumerate() {
+            let mut rest = i;
+            for (v, d) in voxel.iter_mut().zip(dims) {
+                *v = rest % d;
+                rest /= d;
+            }
+            let nodes = if is_occupied {
+                &mut occupied_nodes
+            } else {
+                &mut free_nodes
+            };
+            for offset in 0..3usize.pow(n as u32) {
+                let mut rest = offset;
+                for (k, x) in node.iter_mut().enumerate() {
+                    *x = 2 * voxel[k] + rest % 3;
+                    rest /= 3;
+                }
+                nodes[lattice_index(&node)] = true;
+            }
+        }
+
+        // the distance transform expects the last index to vary fastest
+        let reversed: Vec<usize> = lattice.iter().rev().copied().collect();
+        let to_occupied = euclidean_distance_transform(&reversed, &occupied_nodes);
+        let to_free = euclidean_distance_transform(&reversed, &free_nodes);
+
+        // without voxels of the other kind the transform is infinite, so the values are kept
+        // within the diagonal of the field for interpolation to stay finite
+        let diagonal = resolution * dims.iter().map(|d| (d * d) as f64).sum::<f64>().sqrt();
+        let half = 0.5 * resolution;
+        let values = occupied
+            .iter()
+            .enumerate()
+            .map(|(i, &is_occupied)| {
+                let mut rest = i;
+                for (x, d) in node.iter_mut().zip(dims) {
+                    *x = 2 * (rest % d) + 1;
+                    rest /= d;
+                }
+                let center = lattice_index(&node);
+                let value = if is_occupied {
+                    -to_free[center] * half
+                } else {
+                    to_occupied[center] * half
+                };
+                value.clamp(-diagonal, diagonal)
+            })
+            .collect();
+
+        Self {
+            dims: dims.to_vec(),
+            resolution,
+            origin: origin.to_vec(),
+            values,
+        }
+    }
+
+    /// Build a 2D field from an occupancy grid.
+    pub fn from_occupancy_grid(grid: &OccupancyGrid, unknown_policy: UnknownCellPolicy) -> Self {
+        let (w, h) = (grid.get_width(), grid.get_height());
+        let occupied: Vec<bool> = (0..w * h)
+            .map(|i| match grid.get_cell(i % w, i / w) {
+                CellState::Free => false,
+                CellState::Occupied => true,
+                CellState::Unknown => unknown_policy == UnknownCellPolicy::Occupied,
+            })
+            .collect();
+        Self::from_occupancy(
+            &[w, h],
+            grid.get_resolution(),
+            &grid.get_origin(),
+            &occupied,
+        )
+    }
+
+    pub fn get_dimension(&self) -> usize {
+        self.dims.len()
+    }
+
+    pub fn get_dims(&self) -> &[usize] {
+        &self.dims
+    }
+
+    pub fn get_resolution(&self) -> f64 {
+        self.resolution
+    }
+
+    /// The value stored for a voxel.
+    pub fn get_voxel(&self, index: &[usize]) -> f64 {
+        self.values[self.flat_index(index)]
+    }
+
+    fn flat_index(&self, index: &[usize]) -> usize {
+        index
+            .iter()
+            .zip(&self.dims)
+            .rev()
+            .fold(0, |acc, (i, d)| acc * d + i)
+    }
+
+    /// Whether `p` lies within the extent of the field.
+    pub fn contains(&self, p: &[f64]) -> bool {
+        p.iter()
+            .zip(&self.origin)
+            .zip(&self.dims)
+            .all(|((x, o), d)| *x >= *o && *x <= o + *d as f64 * self.resolution)
+    }
+
+    /// Multilinear interpolation of the field at `p`, along with its gradient. Positions
+    /// outside of the field are clamped to its extent.
+    pub fn sample(&self, p: &[f64]) -> (f64, Vec<f64>) {
+        let n = self.dims.len();
+
+        // continuous voxel coordinates, relative to the voxel centers
+        let mut base = vec![0usize; n];
+        let mut frac = vec![0.0; n];
+        for k in 0..n {
+            let u = (p[k] - self.origin[k]) / self.resolution - 0.5;
+            let max = (self.dims[k] - 1) as f64;
+            let u = u.clamp(0.0, max);
+            let b = (u.floor() as usize).min(self.dims[k].saturating_sub(2));
+            base[k] = b;
+            frac[k] = if self.dims[k] > 1 { u - b as f64 } else { 0.0 };
+        }
+
+        let mut value = 0.0;
+        let mut gradient = vec![0.0; n];
+        let mut corner = vec![0usize; n];
+        for mask in 0..(1usize << n) {
+            let mut weight = 1.0;
+            for k in 0..n {
+                let upper = (mask >> k) & 1 == 1 && self.dims[k] > 1;
+                corner[k] = base[k] + upper as usize;
+                weight *= if upper { frac[k] } else { 1.0 - frac[k] };
+            }
+            let v = self.values[self.flat_index(&corner)];
+            value += weight * v;
+
+            for (k, g) in gradient.iter_mut().enumerate() {
+                if self.dims[k] < 2 {
+                    continue;
+                }
+                let upper = (mask >> k) & 1 == 1;
+                let mut w = if upper { 1.0 } else { -1.0 };
+                for j in (0..n).filter(|j| *j != k) {
+                    let upper = (mask >> j) & 1 == 1 && self.dims[j] > 1;
+                    w *= if upper { frac[j] } else { 1.0 - frac[j] };
+                }
+                *g += w * v / self.resolution;
+            }
+        }
+        (value, gradient)
+    }
+
+    /// Interpolated signed distance at `p`.
+    pub fn distance(&self, p: &[f64]) -> f64 {
+        self.sample(p).0
+    }
+
+    /// A lower bound of the signed distance at `p`, which the interpolated distance is not
+    /// near corners. The signed distance changes by at most the distance travelled, so the
+    /// value of every surrounding voxel minus the distance to its center is a lower bound.
+    /// Positions outside of the field are clamped to its extent.
+    pub fn distance_lower_bound(&self, p: &[f64]) -> f64 {
+        let n = self.dims.len();
+        let mut base = vec![0usize; n];
+        for k in 0..n {
+            let u = (p[k] - self.origin[k]) / self.resolution - 0.5;
+            let u = u.clamp(0.0, (self.dims[k] - 1) as f64);
+            base[k] = (u.floor() as usize).min(self.dims[k].saturating_sub(2));
+        }
+
+        let mut bound = f64::NEG_INFINITY;
+        let mut corner = vec![0usize; n];
+        for mask in 0..(1usize << n) {
+            for k in 0..n {
+                let upper = (mask >> k) & 1 == 1 && self.dims[k] > 1;
+                corner[k] = base[k] + upper as usize;
+            }
+            let offset = (0..n)
+                .map(|k| {
+                    let center = self.origin[k] + (corner[k] as f64 + 0.5) * self.resolution;
+                    (p[k] - center).powi(2)
+                })
+                .sum::<f64>()
+                .sqrt();
+            bound = bound.max(self.values[self.flat_index(&corner)] - offset);
+        }
+        bound
+    }
+}
+
+/// A state validity checker for a point (or sphere of radius `robot_radius`) robot in a
+/// signed distance field. The position of the robot is made of the first values given by
+/// `StateSpace::copy_to_reals`; positions outside of the field are invalid.
+///
+/// States in collision can be repaired by following the gradient of the field until the
+/// robot is out of collision, which is useful for invalid start states caused by noisy
+/// localization.
+///
+/// Validity is decided by the interpolated distance, while the reported clearance is a lower
+/// bound of the distance to the obstacles (see `SignedDistanceField::distance_lower_bound`),
+/// as `ClearanceMotionValidator` requires. Close to obstacles, the clearance of a valid state
+/// may thus be zero or negative, which motion validators treat as a contact.
+pub struct SdfValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    field: SignedDistanceField,
+    robot_radius: f64,
+    margin: f64,
+    max_repair_steps: usize,
+}
+
+impl SdfValidityChecker {
+    pub fn new(state_space: Rc<dyn StateSpace>, field: SignedDistanceField) -> Self {
+        assert!(
+            state_space.get_dimension() as usize >= field.get_dimension(),
+            "The state space has fewer dimensions than the field"
+        );
+        Self {
+            state_space,
+            field,
+            robot_radius: 0.0,
+            margin: 1e-3,
+            max_repair_steps: 50,
+        }
+    }
+
+    pub fn get_field(&self) -> &SignedDistanceField {
+        &self.field
+    }
+
+    pub fn set_robot_radius(&mut self, robot_radius: f64) {
+        if robot_radius < 0.0 {
+            panic!("The robot radius cannot be negative");
+        }
+        self.robot_radius = robot_radius;
+    }
+
+    /// Clearance that repaired states must reach.
+    pub fn set_margin(&mut self, margin: f64) {
+        self.margin = margin;
+    }
+
+    /// Maximum number of gradient steps taken to repair a state.
+    pub fn set_max_repair_steps(&mut self, steps: usize) {
+        self.max_repair_steps = steps;
+    }
+
+    fn reals(&self, state: &StateId) -> Vec<f64> {
+        let mut reals = Vec::new();
+        self.state_space.copy_to_reals(&mut reals, state);
+        reals
+    }
+
+    fn position_is_valid(&self, p: &[f64]) -> bool {
+        self.field.contains(p) && self.field.distance(p) - self.robot_radius > 0.0
+    }
+
+    fn position_clearance(&self, p: &[f64]) -> f64 {
+        if self.field.contains(p) {
+            self.field.distance_lower_bound(p) - self.robot_radius
+        } else {
+            0.0
+        }
+    }
+
+    /// Follow the gradient of the field from `p` until the clearance reaches the margin.
+    fn repair(&self, p: &mut [f64]) -> bool {
+        for _ in 0..self.max_repair_steps {
+            let (distance, gradient) = self.field.sample(p);
+            let clearance = distance - self.robot_radius;
+            if clearance >= self.margin && self.field.contains(p) {
+                return true;
+            }
+
+            let norm = gradient.iter().map(|g| g * g).sum::<f64>().sqrt();
+            if norm < f64::EPSILON {
+                return false;
+            }
+            // the field is close to a distance function, so its value is the distance to go
+            let step = (self.margin - clearance).max(self.field.resolution * 0.1);
+            p.iter_mut()
+                .zip(&gradient)
+                .for_each(|(x, g)| *x += step * g / norm);
+        }
+        false
+    }
+}
+
+impl StateValidityChecker for SdfValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        let reals = self.reals(state);
+        self.position_is_valid(&reals[..self.field.get_dimension()])
+    }
+
+    fn has_valid_direction_computation(&self) -> bool {
+        true
+    }
+
+    fn is_valid_with_distance(&self, state: &StateId) -> (bool, Option<f64>) {
+        let reals = self.reals(state);
+        let p = &reals[..self.field.get_dimension()];
+        (self.position_is_valid(p), Some(self.position_clearance(p)))
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        let reals = self.reals(state);
+        Some(self.position_clearance(&reals[..self.field.get_dimension()]))
+    }
+
+    fn clearance_with_state(
+        &self,
+        state: &StateId,
+        valid_state: &mut StateId,
+        valid_state_available: &mut bool,
+    ) -> Option<f64> {
+        let mut reals = self.reals(state);
+        let dimension = self.field.get_dimension();
+        let clearance = self.position_clearance(&reals[..dimension]);
+
+        *valid_state_available = self.repair(&mut reals[..dimension]);
+        if *valid_state_available {
+            self.state_space.copy_from_reals(valid_state, &reals);
+        }
+        Some(clearance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+
+    #[test]
+    fn test_sdf_validity_checker() {
+        // a 10 x 10 field of 0.1 cells, with an obstacle covering [0.3, 0.7] x [0.3, 0.7]
+        let occupied: Vec<bool> = (0..100)
+            .map(|i| (3..7).contains(&(i % 10)) && (3..7).contains(&(i / 10)))
+            .collect();
+        let field = SignedDistanceField::from_occupancy(&[10, 10], 0.1, &[0.0, 0.0], &occupied);
+
+        assert!((field.get_voxel(&[2, 5]) - 0.05).abs() < 1e-12);
+        assert!((field.get_voxel(&[3, 5]) + 0.05).abs() < 1e-12);
+        assert!((field.get_voxel(&[5, 5]) + 0.15).abs() < 1e-12);
+        assert!(field.distance(&[0.3, 0.5]).abs() < 1e-12);
+        let (value, gradient) = field.sample(&[0.2, 0.5]);
+        assert!((value - 0.1).abs() < 1e-12);
+        assert!(gradient[0] < 0.0 && gradient[1].abs() < 1e-12);
+
+        // the diagonal neighbour of the obstacle is sqrt(0.5) voxels away from its corner, and
+        // the bound never exceeds the distance to the obstacle square
+        assert!((field.get_voxel(&[7, 7]) - 0.1 * 0.5f64.sqrt()).abs() < 1e-12);
+        assert!((field.get_voxel(&[8, 7]) - 0.1 * 2.5f64.sqrt()).abs() < 1e-12);
+        for i in 0..=20 {
+            for j in 0..=20 {
+                let p = [i as f64 / 20.0, j as f64 / 20.0];
+                let dx = ((p[0] - 0.5).abs() - 0.2).max(0.0);
+                let dy = ((p[1] - 0.5).abs() - 0.2).max(0.0);
+                let exact = (dx * dx + dy * dy).sqrt();
+                assert!(field.distance_lower_bound(&p) <= exact + 1e-12);
+            }
+        }
+
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        let checker = SdfValidityChecker::new(space.clone(), field);
+        assert!(checker.is_valid(&state(0.1, 0.1)));
+        assert!(!checker.is_valid(&state(0.4, 0.5)));
+        assert!(!checker.is_valid(&state(1.5, 0.5)));
+
+        let mut repaired = space.alloc_state();
+        let mut available = false;
+        let (valid, clearance) = checker.is_valid_with_distance_and_state(
+            &state(0.35, 0.5),
+            &mut repaired,
+            &mut available,
+        );
+        assert!(!valid);
+        assert!(clearance.unwrap() < 0.0);
+        assert!(available);
+        assert!(checker.is_valid(&repaired));
+        let values = space.clone_state_inner_value(&repaired).values;
+        assert!(values[0] < 0.3 && values[0] > 0.2);
+
+        // without obstacles the field stays finite, at the centers and on the borders
+        let empty = SignedDistanceField::from_occupancy(&[4, 4], 0.1, &[0.0, 0.0], &[false; 16]);
+        for p in [[0.05, 0.05], [0.12, 0.27], [0.0, 0.4]] {
+            let (value, gradient) = empty.sample(&p);
+            assert!(value > 0.0 && value.is_finite());
+            assert!(gradient.iter().all(|g| g.is_finite()));
+        }
+        let checker = SdfValidityChecker::new(space.clone(), empty);
+        assert!(checker.is_valid(&state(0.05, 0.05)));
+        assert!(checker.is_valid(&state(0.4, 0.0)));
+    }
+}
@@ -0,0 +1,826 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use nalgebra::{Isometry3, Point3, Quaternion, Translation3, UnitQuaternion, Vector3};
+
+use crate::base::{
+    state_allocator::StateId, state_validity_checker::StateValidityChecker, statespace::StateSpace,
+};
+use crate::error::LoadError;
+
+type Triangle = [Vector3<f64>; 3];
+
+fn transform(pose: &Isometry3<f64>, p: &Vector3<f64>) -> Vector3<f64> {
+    pose.transform_point(&Point3::from(*p)).coords
+}
+
+/// An axis-aligned bounding box in 3D.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb3 {
+    pub min: Vector3<f64>,
+    pub max: Vector3<f64>,
+}
+
+impl Aabb3 {
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f64::INFINITY),
+            max: Vector3::repeat(f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Vector3<f64>>) -> Self {
+        let mut aabb = Self::empty();
+        for p in points {
+            aabb.min = aabb.min.inf(p);
+            aabb.max = aabb.max.sup(p);
+        }
+        aabb
+    }
+
+    /// Bounding box of this box once transformed by `pose`.
+    pub fn transformed(&self, pose: &Isometry3<f64>) -> Self {
+        let corners = (0..8).map(|i| {
+            let corner = Vector3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            transform(pose, &corner)
+        });
+        let mut aabb = Self::empty();
+        for p in corners {
+            aabb.min = aabb.min.inf(&p);
+            aabb.max = aabb.max.sup(&p);
+        }
+        aabb
+    }
+
+    pub fn intersects(&self, other: &Aabb3) -> bool {
+        (0..3).all(|k| self.min[k] <= other.max[k] && other.min[k] <= self.max[k])
+    }
+
+    /// Euclidean distance between the closest points of the two boxes.
+    pub fn distance(&self, other: &Aabb3) -> f64 {
+        (0..3)
+            .map(|k| {
+                (other.min[k] - self.max[k])
+                    .max(self.min[k] - other.max[k])
+                    .max(0.0)
+            })
+            .map(|gap| gap * gap)
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn extent(&self) -> f64 {
+        (self.max - self.min).norm()
+    }
+}
+
+/// The parameter `s` at which the line `origin + s * direction` crosses the triangle `t`
+/// (Möller–Trumbore), if it does. Lines parallel to the triangle are not reported.
+fn line_triangle_intersection(
+    origin: &Vector3<f64>,
+    direction: &Vector3<f64>,
+    t: &Triangle,
+) -> Option<f64> {
+    let (e1, e2) = (t[1] - t[0], t[2] - t[0]);
+    let h = direction.cross(&e2);
+    let det = e1.dot(&h);
+    if det.abs() <= f64::EPSILON {
+        return None;
+    }
+
+    let inv = 1.0 / det;
+    let s = origin - t[0];
+    let u = inv * s.dot(&h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let k = s.cross(&e1);
+    let v = inv * direction.dot(&k);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    Some(inv * e2.dot(&k))
+}
+
+/// Whether the segment `[p, q]` crosses the triangle `t`. Segments lying in the plane of the
+/// triangle are not reported.
+fn segment_crosses_triangle(p: &Vector3<f64>, q: &Vector3<f64>, t: &Triangle) -> bool {
+    line_triangle_intersection(p, &(q - p), t).is_some_and(|s| (0.0..=1.0).contains(&s))
+}
+
+/// Closest point to `p` on the triangle `t` (Ericson, "Real-Time Collision Detection").
+fn closest_point_on_triangle(p: &Vector3<f64>, t: &Triangle) -> Vector3<f64> {
+    let [a, b, c] = t;
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (ab.dot(&ap), ac.dot(&ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(&bp), ac.dot(&bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(&cp), ac.dot(&cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+/// Distance between the segments `[p1, q1]` and `[p2, q2]` (Ericson, "Real-Time Collision
+/// Detection").
+fn segment_distance(
+    p1: &Vector3<f64>,
+    q1: &Vector3<f64>,
+    p2: &Vector3<f64>,
+    q2: &Vector3<f64>,
+) -> f64 {
+    let (d1, d2, r) = (q1 - p1, q2 - p2, p1 - p2);
+    let (a, e, f) = (d1.dot(&d1), d2.dot(&d2), d2.dot(&r));
+    let eps = f64::EPSILON;
+
+    let (s, t) = if a <= eps && e <= eps {
+        (0.0, 0.0)
+    } else if a <= eps {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(&r);
+        if e <= eps {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = d1.dot(&d2);
+            let denom = a * e - b * b;
+            let s = if denom > eps {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let t = (b * s + f) / e;
+            if t < 0.0 {
+                ((-c / a).clamp(0.0, 1.0), 0.0)
+            } else if t > 1.0 {
+                (((b - c) / a).clamp(0.0, 1.0), 1.0)
+            } else {
+                (s, t)
+            }
+        }
+    };
+
+    ((p1 + d1 * s) - (p2 + d2 * t)).norm()
+}
+
+/// Distance between two triangles, zero if they intersect.
+fn triangle_distance(t1: &Triangle, t2: &Triangle) -> f64 {
+    let edges = [(0, 1), (1, 2), (2, 0)];
+    for (i, j) in edges {
+        if segment_crosses_triangle(&t1[i], &t1[j], t2)
+            || segment_crosses_triangle(&t2[i], &t2[j], t1)
+        {
+            return 0.0;
+        }
+    }
+
+    // otherwise the closest points are on the boundary of one of the triangles
+    let mut distance = f64::INFINITY;
+    for k in 0..3 {
+        distance = distance
+            .min((closest_point_on_triangle(&t1[k], t2) - t1[k]).norm())
+            .min((closest_point_on_triangle(&t2[k], t1) - t2[k]).norm());
+    }
+    for (i, j) in edges {
+        for (k, l) in edges {
+            distance = distance.min(segment_distance(&t1[i], &t1[j], &t2[k], &t2[l]));
+        }
+    }
+    distance
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    aabb: Aabb3,
+    /// Index of the first child if `count` is zero, of the first triangle otherwise.
+    first: usize,
+    count: usize,
+}
+
+/// A bounding volume hierarchy of axis-aligned boxes over the triangles of a mesh.
+#[derive(Debug, Clone)]
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangle indices, ordered such that every leaf refers to a contiguous range.
+    order: Vec<usize>,
+}
+
+const BVH_LEAF_SIZE: usize = 4;
+
+impl Bvh {
+    fn build(vertices: &[Vector3<f64>], triangles: &[[usize; 3]]) -> Self {
+        let boxes: Vec<Aabb3> = triangles
+            .iter()
+            .map(|t| Aabb3::from_points(t.iter().map(|&i| &vertices[i])))
+            .collect();
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            order: (0..triangles.len()).collect(),
+        };
+        if !triangles.is_empty() {
+            bvh.nodes.push(BvhNode {
+                aabb: Aabb3::empty(),
+                first: 0,
+                count: 0,
+            });
+            bvh.build_node(0, 0, triangles.len(), &boxes);
+        }
+        bvh
+    }
+
+    fn build_node(&mut self, node: usize, start: usize, end: usize, boxes: &[Aabb3]) {
+        let mut aabb = Aabb3::empty();
+        for &i in &self.order[start..end] {
+            aabb.min = aabb.min.inf(&boxes[i].min);
+            aabb.max = aabb.max.sup(&boxes[i].max);
+        }
+        self.nodes[node].aabb = aabb;
+
+        if end - start <= BVH_LEAF_SIZE {
+            self.nodes[node].first = start;
+            self.nodes[node].count = end - start;
+            return;
+        }
+
+        // split at the median of the centroids along the longest axis
+        let axis = (aabb.max - aabb.min).imax();
+        let centroid = |i: &usize| boxes[*i].min[axis] + boxes[*i].max[axis];
+        let mid = (start + end) / 2;
+        self.order[start..end]
+            .select_nth_unstable_by(mid - start, |a, b| centroid(a).total_cmp(&centroid(b)));
+
+        let left = self.nodes.len();
+        for _ in 0..2 {
+            self.nodes.push(BvhNode {
+                aabb: Aabb3::empty(),
+                first: 0,
+                count: 0,
+            });
+        }
+        self.nodes[node].first = left;
+        self.build_node(left, start, mid, boxes);
+        self.build_node(left + 1, mid, end, boxes);
+    }
+
+    fn is_leaf(&self, node: usize) -> bool {
+        self.nodes[node].count > 0
+    }
+
+    fn children(&self, node: usize) -> [usize; 2] {
+        let first = self.nodes[node].first;
+        [first, first + 1]
+    }
+
+    fn triangles(&self, node: usize) -> &[usize] {
+        let BvhNode { first, count, .. } = self.nodes[node];
+        &self.order[first..first + count]
+    }
+}
+
+/// A triangle mesh, with a bounding volume hierarchy over its triangles.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    vertices: Vec<Vector3<f64>>,
+    triangles: Vec<[usize; 3]>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(vertices: Vec<Vector3<f64>>, triangles: Vec<[usize; 3]>) -> Self {
+        assert!(
+            triangles.iter().flatten().all(|&i| i < vertices.len()),
+            "Triangle refers to a missing vertex"
+        );
+        let bvh = Bvh::build(&vertices, &triangles);
+        Self {
+            vertices,
+            triangles,
+            bvh,
+        }
+    }
+
+    pub fn get_vertices(&self) -> &[Vector3<f64>] {
+        &self.vertices
+    }
+
+    pub fn get_triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    /// Bounding box of the mesh, or `None` if it has no triangles.
+    pub fn get_aabb(&self) -> Option<Aabb3> {
+        self.bvh.nodes.first().map(|n| n.aabb)
+    }
+
+    fn triangle(&self, index: usize) -> Triangle {
+        self.triangles[index].map(|i| self.vertices[i])
+    }
+
+    /// Whether `p` is inside the mesh, from the parity of the number of triangles crossed by a
+    /// ray cast from `p`. The mesh must be closed.
+    pub fn contains_point(&self, p: &Vector3<f64>) -> bool {
+        match self.get_aabb() {
+            Some(aabb) if (0..3).all(|k| aabb.min[k] <= p[k] && p[k] <= aabb.max[k]) => {}
+            _ => return false,
+        }
+        // a direction unlikely to graze the edges of axis-aligned meshes
+        let direction = Vector3::new(0.5773, 0.5779, 0.5767);
+        let crossings = (0..self.triangles.len())
+            .filter(|&i| {
+                line_triangle_intersection(p, &direction, &self.triangle(i))
+                    .is_some_and(|s| s > 0.0)
+            })
+            .count();
+        crossings % 2 == 1
+    }
+
+    /// Parse a Wavefront OBJ mesh. Only vertices and faces are read; polygonal faces are
+    /// triangulated as fans.
+    pub fn parse_obj(text: &str) -> Result<Self, LoadError> {
+        let error = |line: usize, message: &str| LoadError::InvalidFormat {
+            format: "OBJ",
+            message: format!("line {}: {}", line + 1, message),
+        };
+
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let mut words = line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let values = words
+                        .take(3)
+                        .map(|w| w.parse::<f64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|_| error(n, "invalid vertex"))?;
+                    if values.len() != 3 {
+                        return Err(error(n, "a vertex needs three coordinates"));
+                    }
+                    vertices.push(Vector3::new(values[0], values[1], values[2]));
+                }
+                Some("f") => {
+                    // faces refer to 1-based vertex indices, or to relative ones if negative
+                    let face = words
+                        .map(|w| {
+                            let index: i64 =
+                                w.split('/').next().unwrap_or_default().parse().ok()?;
+                            match index {
+                                i if i > 0 && i as usize <= vertices.len() => Some(i as usize - 1),
+                                i if i < 0 && (-i) as usize <= vertices.len() => {
+                                    Some(vertices.len() - (-i) as usize)
+                                }
+                                _ => None,
+                            }
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| error(n, "invalid face"))?;
+                    if face.len() < 3 {
+                        return Err(error(n, "a face needs at least three vertices"));
+                    }
+                    for k in 1..face.len() - 1 {
+                        triangles.push([face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self::new(vertices, triangles))
+    }
+
+    /// Parse an STL mesh, in either the binary or the ASCII variant.
+    pub fn parse_stl(data: &[u8]) -> Result<Self, LoadError> {
+        let error = |message: &str| LoadError::InvalidFormat {
+            format: "STL",
+            message: message.to_string(),
+        };
+
+        let mut vertices = Vec::new();
+        if data.len() >= 84 {
+            let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+            if data.len() == 84 + 50 * count {
+                // every facet is a normal and three vertices as little-endian f32, and an
+                // attribute byte count
+                for facet in data[84..].chunks_exact(50) {
+                    let value = |i: usize| {
+                        let b = &facet[4 * i..4 * i + 4];
+                        f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+                    };
+                    for v in 1..4 {
+                        vertices.push(Vector3::new(
+                            value(3 * v),
+                            value(3 * v + 1),
+                            value(3 * v + 2),
+                        ));
+                    }
+                }
+                return Ok(Self::from_triangle_soup(vertices));
+            }
+        }
+
+        let text = std::str::from_utf8(data).map_err(|_| error("not a valid binary STL file"))?;
+        if !text.trim_start().starts_with("solid") {
+            return Err(error("not a valid binary STL file"));
+        }
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            if words.next() == Some("vertex") {
+                let values = words
+                    .map(|w| w.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| error("invalid vertex"))?;
+                if values.len() != 3 {
+                    return Err(error("a vertex needs three coordinates"));
+                }
+                vertices.push(Vector3::new(values[0], values[1], values[2]));
+            }
+        }
+        if vertices.len() % 3 != 0 {
+            return Err(error("facets must have three vertices"));
+        }
+        Ok(Self::from_triangle_soup(vertices))
+    }
+
+    fn from_triangle_soup(vertices: Vec<Vector3<f64>>) -> Self {
+        let triangles = (0..vertices.len() / 3)
+            .map(|i| [3 * i, 3 * i + 1, 3 * i + 2])
+            .collect();
+        Self::new(vertices, triangles)
+    }
+
+    /// Load an OBJ or STL mesh, depending on the extension of the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("obj") => Self::parse_obj(&fs::read_to_string(path)?),
+            Some("stl") => Self::parse_stl(&fs::read(path)?),
+            _ => Err(LoadError::InvalidFormat {
+                format: "mesh",
+                message: format!("unsupported file extension for {}", path.display()),
+            }),
+        }
+    }
+}
+
+/// Counters of the work done by mesh queries.
+#[derive(Clone, Debug, Default)]
+pub struct MeshQueryStats {
+    pub queries: u64,
+    pub bounding_volume_tests: u64,
+    pub triangle_tests: u64,
+    /// Triangle tests done by the most recent query.
+    pub last_triangle_tests: u64,
+}
+
+impl MeshQueryStats {
+    pub fn triangle_tests_per_query(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.triangle_tests as f64 / self.queries as f64
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// A state validity checker for a rigid body described by a triangle mesh, moving in an
+/// environment also described by a triangle mesh.
+///
+/// The pose of the robot is read from `StateSpace::copy_to_reals` as a position followed by a
+/// unit quaternion in `(x, y, z, w)` order. The clearance is the minimum distance between the
+/// meshes, and is zero for states in collision.
+///
+/// Meshes are treated as surfaces: a robot lying entirely inside the environment mesh (or the
+/// converse) does not touch it. When both meshes are closed solids, containment can be
+/// detected as well with `set_check_containment`.
+pub struct MeshValidityChecker {
+    state_space: Rc<dyn StateSpace>,
+    robot: TriangleMesh,
+    environment: TriangleMesh,
+    check_containment: bool,
+    stats: RefCell<MeshQueryStats>,
+}
+
+impl MeshValidityChecker {
+    pub fn new(
+        state_space: Rc<dyn StateSpace>,
+        robot: TriangleMesh,
+        environment: TriangleMesh,
+    ) -> Self {
+        let state = state_space.alloc_state();
+        let mut reals = Vec::new();
+        state_space.copy_to_reals(&mut reals, &state);
+        state_space.free_state(&state);
+        assert!(
+            reals.len() >= 7,
+            "The state space must contain a position and a quaternion"
+        );
+        Self {
+            state_space,
+            robot,
+            environment,
+            check_containment: false,
+            stats: RefCell::new(MeshQueryStats::default()),
+        }
+    }
+
+    /// Also report a collision when one mesh lies entirely inside the other. Both meshes must
+    /// then be closed.
+    pub fn set_check_containment(&mut self, check_containment: bool) {
+        self.check_containment = check_containment;
+    }
+
+    pub fn get_check_containment(&self) -> bool {
+        self.check_containment
+    }
+
+    pub fn get_robot(&self) -> &TriangleMesh {
+        &self.robot
+    }
+
+    pub fn get_environment(&self) -> &TriangleMesh {
+        &self.environment
+    }
+
+    pub fn get_stats(&self) -> &RefCell<MeshQueryStats> {
+        &self.stats
+    }
+
+    fn pose_of(&self, state: &StateId) -> Isometry3<f64> {
+        let mut reals = Vec::new();
+        self.state_space.copy_to_reals(&mut reals, state);
+        let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+            reals[6], reals[3], reals[4], reals[5],
+        ));
+        Isometry3::from_parts(Translation3::new(reals[0], reals[1], reals[2]), rotation)
+    }
+
+    /// Traverse pairs of nodes of the two hierarchies, calling `leaves` on pairs of robot and
+    /// environment triangles. Pairs of nodes for which `prune` (given the distance between
+    /// their boxes) returns true are skipped, and the traversal ends when `leaves` returns
+    /// true.
+    fn traverse(
+        &self,
+        pose: &Isometry3<f64>,
+        mut prune: impl FnMut(f64) -> bool,
+        mut leaves: impl FnMut(&Triangle, &Triangle) -> bool,
+    ) {
+        let (robot, environment) = (&self.robot.bvh, &self.environment.bvh);
+        if robot.nodes.is_empty() || environment.nodes.is_empty() {
+            return;
+        }
+
+        let world: Vec<Vector3<f64>> = self
+            .robot
+            .vertices
+            .iter()
+            .map(|v| transform(pose, v))
+            .collect();
+        let mut stats = self.stats.borrow_mut();
+        stats.queries += 1;
+        stats.last_triangle_tests = 0;
+
+        let mut stack = vec![(0, 0)];
+        while let Some((r, e)) = stack.pop() {
+            let robot_box = robot.nodes[r].aabb.transformed(pose);
+            let environment_box = &environment.nodes[e].aabb;
+            stats.bounding_volume_tests += 1;
+            if prune(robot_box.distance(environment_box)) {
+                continue;
+            }
+
+            match (robot.is_leaf(r), environment.is_leaf(e)) {
+                (true, true) => {
+                    for &i in robot.triangles(r) {
+                        let t1 = self.robot.triangles[i].map(|v| world[v]);
+                        for &j in environment.triangles(e) {
+                            stats.triangle_tests += 1;
+                            stats.last_triangle_tests += 1;
+                            if leaves(&t1, &self.environment.triangle(j)) {
+                                return;
+                            }
+                        }
+                    }
+                }
+                // descend into the larger of the two volumes
+                (false, true) => stack.extend(robot.children(r).map(|c| (c, e))),
+                (true, false) => stack.extend(environment.children(e).map(|c| (r, c))),
+                (false, false) => {
+                    if robot_box.extent() > environment_box.extent() {
+                        stack.extend(robot.children(r).map(|c| (c, e)));
+                    } else {
+                        stack.extend(environment.children(e).map(|c| (r, c)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether, the surfaces of the meshes being apart, one mesh is inside the other. A single
+    /// vertex of each mesh then needs to be tested.
+    fn contained_at(&self, pose: &Isometry3<f64>) -> bool {
+        let robot_vertex = self.robot.vertices.first().map(|v| transform(pose, v));
+        let environment_vertex = self
+            .environment
+            .vertices
+            .first()
+            .map(|v| transform(&pose.inverse(), v));
+        robot_vertex.is_some_and(|v| self.environment.contains_point(&v))
+            || environment_vertex.is_some_and(|v| self.robot.contains_point(&v))
+    }
+
+    /// Whether the robot at `pose` touches the environment.
+    pub fn collides_at(&self, pose: &Isometry3<f64>) -> bool {
+        let mut collision = false;
+        self.traverse(
+            pose,
+            |distance| distance > 0.0,
+            |t1, t2| {
+                collision = triangle_distance(t1, t2) <= f64::EPSILON;
+                collision
+            },
+        );
+        collision || (self.check_containment && self.contained_at(pose))
+    }
+
+    /// Minimum distance between the robot at `pose` and the environment, zero if they touch.
+    pub fn distance_at(&self, pose: &Isometry3<f64>) -> f64 {
+        let best = Cell::new(f64::INFINITY);
+        self.traverse(
+            pose,
+            |distance| distance >= best.get(),
+            |t1, t2| {
+                best.set(best.get().min(triangle_distance(t1, t2)));
+                best.get() <= f64::EPSILON
+            },
+        );
+        if self.check_containment && best.get() > f64::EPSILON && self.contained_at(pose) {
+            return 0.0;
+        }
+        best.get()
+    }
+}
+
+impl StateValidityChecker for MeshValidityChecker {
+    fn is_valid(&self, state: &StateId) -> bool {
+        !self.collides_at(&self.pose_of(state))
+    }
+
+    fn is_valid_with_distance(&self, state: &StateId) -> (bool, Option<f64>) {
+        let distance = self.distance_at(&self.pose_of(state));
+        (distance > f64::EPSILON, Some(distance))
+    }
+
+    fn clearance(&self, state: &StateId) -> Option<f64> {
+        Some(self.distance_at(&self.pose_of(state)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::f64::consts::FRAC_PI_4;
+
+    const UNIT_CUBE_OBJ: &str = "
+        # unit cube centered at the origin
+        v -0.5 -0.5 -0.5
+        v  0.5 -0.5 -0.5
+        v  0.5  0.5 -0.5
+        v -0.5  0.5 -0.5
+        v -0.5 -0.5  0.5
+        v  0.5 -0.5  0.5
+        v  0.5  0.5  0.5
+        v -0.5  0.5  0.5
+        f 1 4 3 2
+        f 5 6 7 8
+        f 1 2 6 5
+        f 2 3 7 6
+        f 3 4 8 7
+        f 4/1 1/1 5/1 8/1
+    ";
+
+    #[test]
+    fn test_parse_meshes() {
+        let cube = TriangleMesh::parse_obj(UNIT_CUBE_OBJ).unwrap();
+        assert_eq!(cube.get_vertices().len(), 8);
+        assert_eq!(cube.get_triangles().len(), 12);
+        assert!(TriangleMesh::parse_obj("f 1 2 3").is_err());
+
+        let stl = "solid tri
+            facet normal 0 0 1
+              outer loop
+                vertex 0 0 0
+                vertex 1 0 0
+                vertex 0 1 0
+              endloop
+            endfacet
+            endsolid tri";
+        let triangle = TriangleMesh::parse_stl(stl.as_bytes()).unwrap();
+        assert_eq!(triangle.get_triangles().len(), 1);
+
+        let mut binary = vec![0u8; 80];
+        binary.extend(1u32.to_le_bytes());
+        for v in [
+            0.0f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend(v.to_le_bytes());
+        }
+        binary.extend([0, 0]);
+        let triangle = TriangleMesh::parse_stl(&binary).unwrap();
+        assert_eq!(triangle.get_vertices()[1], Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_mesh_validity_checker() {
+        let mut space = RealVectorStateSpace::new();
+        for _ in 0..7 {
+            space.add_dimension(None, -10.0, 10.0);
+        }
+        let space = Rc::new(space);
+        let state = |x: f64, yaw: f64| {
+            let (s, c) = (yaw / 2.0).sin_cos();
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, 0.0, 0.0, 0.0, 0.0, s, c]),
+            })
+        };
+
+        let cube = TriangleMesh::parse_obj(UNIT_CUBE_OBJ).unwrap();
+        let checker = MeshValidityChecker::new(space.clone(), cube.clone(), cube.clone());
+
+        assert!(!checker.is_valid(&state(0.5, 0.0)));
+        assert!(checker.is_valid(&state(1.5, 0.0)));
+        assert!((checker.clearance(&state(1.5, 0.0)).unwrap() - 0.5).abs() < 1e-9);
+
+        // rotated by 45 degrees, the cube reaches out to sqrt(2) / 2 along x
+        let rotated = checker.clearance(&state(1.5, FRAC_PI_4)).unwrap();
+        assert!((rotated - (1.0 - 0.5f64.sqrt())).abs() < 1e-9);
+        assert!(!checker.is_valid(&state(1.2, FRAC_PI_4)));
+
+        let stats = checker.get_stats().borrow();
+        assert_eq!(stats.queries, 5);
+        assert!(stats.triangle_tests > 0);
+        assert!(stats.last_triangle_tests <= stats.triangle_tests);
+
+        // a small cube inside the unit cube only collides when containment is checked
+        let small = TriangleMesh::new(
+            cube.get_vertices().iter().map(|v| v * 0.2).collect(),
+            cube.get_triangles().to_vec(),
+        );
+        assert!(cube.contains_point(&Vector3::new(0.1, 0.2, -0.3)));
+        assert!(!cube.contains_point(&Vector3::new(0.1, 0.7, -0.3)));
+        let mut checker = MeshValidityChecker::new(space.clone(), small, cube);
+        assert!(checker.is_valid(&state(0.1, 0.3)));
+        checker.set_check_containment(true);
+        assert!(!checker.is_valid(&state(0.1, 0.3)));
+        assert_eq!(checker.clearance(&state(0.1, 0.3)), Some(0.0));
+        assert!(checker.is_valid(&state(1.5, 0.3)));
+    }
+}
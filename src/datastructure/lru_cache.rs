@@ -0,0 +1,104 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A bounded key-value cache that evicts the least recently used entry once full.
+///
+/// Recency is tracked with a monotonically increasing stamp per access, so lookups,
+/// insertions and evictions are all `O(log n)`.
+#[derive(Debug, Clone)]
+pub struct LruCache<K, V> {
+    capacity: usize,
+    stamp: u64,
+    entries: HashMap<K, (V, u64)>,
+    recency: BTreeMap<u64, K>,
+}
+
+impl<K, V> LruCache<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Create a cache holding at most `capacity` entries. The capacity must be non-zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "The capacity of the cache must be non-zero");
+        Self {
+            capacity,
+            stamp: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity, evicting the least recently used entries if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        assert!(capacity > 0, "The capacity of the cache must be non-zero");
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a key, marking it as the most recently used entry.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    /// Look up a key mutably, marking it as the most recently used entry.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let (value, stamp) = self.entries.get_mut(key)?;
+
+        let key = self
+            .recency
+            .remove(stamp)
+            .expect("recency index out of sync");
+        self.stamp += 1;
+        *stamp = self.stamp;
+        self.recency.insert(self.stamp, key);
+
+        Some(value)
+    }
+
+    /// Insert a value. Returns `true` if an entry had to be evicted to make room.
+    pub fn insert(&mut self, key: K, value: V) -> bool {
+        self.stamp += 1;
+        if let Some((old_value, stamp)) = self.entries.get_mut(&key) {
+            *old_value = value;
+            self.recency.remove(stamp);
+            *stamp = self.stamp;
+            self.recency.insert(self.stamp, key);
+            return false;
+        }
+
+        let evicted = if self.entries.len() >= self.capacity {
+            self.evict();
+            true
+        } else {
+            false
+        };
+        self.recency.insert(self.stamp, key.clone());
+        self.entries.insert(key, (value, self.stamp));
+        evicted
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn evict(&mut self) {
+        if let Some((_, key)) = self.recency.pop_first() {
+            self.entries.remove(&key);
+        }
+    }
+}
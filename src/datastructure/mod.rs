@@ -1,4 +1,10 @@
 pub mod arena;
+pub mod lru_cache;
 pub mod nearest_neighbours;
 pub mod nearest_neighbours_GNANT_no_therad_safety;
+pub mod nearest_neighbours_asymmetric;
+pub mod nearest_neighbours_concurrent;
+pub mod nearest_neighbours_hnsw;
 pub mod nearest_neighbours_kd_tree;
+pub mod nearest_neighbours_linear;
+pub mod nearest_neighbours_real_vector_kd_tree;
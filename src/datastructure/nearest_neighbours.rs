@@ -1,15 +1,115 @@
-use std::f64;
+use std::{f64, rc::Rc};
 
+use crate::base::{state_allocator::StateKey, statespace::StateSpace};
+
+use super::nearest_neighbours_GNANT_no_therad_safety::NearestNeighborsGNATNoThreadSafety;
+use super::nearest_neighbours_linear::NearestNeighborsSqrtApprox;
+
+/// A distance function that can be shared between threads.
 pub type DistanceFunction<T> = Box<dyn Fn(&T, &T) -> f64 + Send + Sync>;
 
+/// A distance function that is not required to be `Send`, so that it can call into a
+/// `StateSpace`. This is what `NearestNeighbors::set_distance_function` takes, and a
+/// `DistanceFunction` coerces to it.
+pub type LocalDistanceFunction<T> = Box<dyn Fn(&T, &T) -> f64>;
+
+/// A borrowed distance function, as returned by `NearestNeighbors::get_distance_function`.
+pub type DistanceFunctionRef<'a, T> = &'a dyn Fn(&T, &T) -> f64;
+
+/// A distance function computing the distance between states of the given space, identified
+/// by their keys. A state is at distance 0 from itself, which the data structures rely on when
+/// removing states.
+pub fn state_space_distance_function(space: Rc<dyn StateSpace>) -> LocalDistanceFunction<StateKey> {
+    Box::new(move |s1, s2| {
+        if s1 == s2 {
+            // spaces cannot borrow the same state twice
+            0.0
+        } else {
+            s1.with_id(|s1| s2.with_id(|s2| space.distance(s1, s2)))
+        }
+    })
+}
+
+/// Select a default nearest neighbors data structure for the given space. Its distance
+/// function still needs to be set with `NearestNeighbors::set_distance_function`.
+///
+/// - If the space is a metric space (see `StateSpace::is_metric_space`), the default is
+///   `NearestNeighborsGNATNoThreadSafety`.
+/// - Otherwise, the default is `NearestNeighborsSqrtApprox`, which does not rely on the
+///   triangle inequality.
+///
+/// Neither ranks neighbors correctly when the distance is not symmetric (see
+/// `StateSpace::has_symmetric_distance`); such spaces need `NearestNeighborsAsymmetric`, which
+/// requires a symmetric lower bound on the distance.
+pub fn get_default_nearest_neighbors<T>(space: &dyn StateSpace) -> Box<dyn NearestNeighbors<T>>
+where
+    T: Clone + PartialEq + 'static,
+{
+    if space.is_metric_space() {
+        Box::new(NearestNeighborsGNATNoThreadSafety::new())
+    } else {
+        Box::new(NearestNeighborsSqrtApprox::new())
+    }
+}
+
+/// The fraction of the exact `k` nearest neighbors (according to `exact`) that `approximate`
+/// finds, averaged over the queries.
+pub fn measure_recall<T: PartialEq>(
+    approximate: &dyn NearestNeighbors<T>,
+    exact: &dyn NearestNeighbors<T>,
+    queries: &[T],
+    k: usize,
+) -> f64 {
+    let mut expected = 0;
+    let mut found = 0;
+    for query in queries {
+        let exact = exact.nearest_k(query, k);
+        let approximate = approximate.nearest_k(query, k);
+        expected += exact.len();
+        found += exact.iter().filter(|n| approximate.contains(n)).count();
+    }
+    if expected == 0 {
+        1.0
+    } else {
+        found as f64 / expected as f64
+    }
+}
+
+/// Write the neighbors of `data` to `out`, paired with their distance to `data` and sorted by
+/// distance.
+fn pair_with_distances<T>(
+    dist_fn: Option<DistanceFunctionRef<T>>,
+    data: &T,
+    neighbors: Vec<T>,
+    out: &mut Vec<(T, f64)>,
+) {
+    let dist_fn =
+        dist_fn.expect("The distance function must be set before using the nearest neighbors");
+    out.clear();
+    out.extend(neighbors.into_iter().map(|n| {
+        let dist = dist_fn(data, &n);
+        (n, dist)
+    }));
+    out.sort_by(|a, b| a.1.total_cmp(&b.1));
+}
+
 /// A trait for nearest neighbors search algorithms.
-pub trait NearestNeighbors<T> {
+///
+/// `D` is the type of the distance function. Data structures generic over it can also take a
+/// `DistanceFunction`, which lets them be shared between threads (see `ConcurrentBackend`).
+pub trait NearestNeighbors<T, D = LocalDistanceFunction<T>> {
     /// Sets the distance function to be used for nearest neighbors search.
     ///
     /// # Arguments
     ///
     /// * `dist_fn` - A function that calculates the distance between two data points.
-    fn set_distance_function(&mut self, dist_fn: DistanceFunction<T>);
+    fn set_distance_function(&mut self, dist_fn: D);
+
+    /// Returns the distance function, or `None` if it has not been set or if the data
+    /// structure computes distances on its own. Data structures returning `None` once their
+    /// distance function is set must override `nearest_k_into` and `nearest_r_into`, whose
+    /// default implementations need it.
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>>;
 
     /// Adds a data point to the data structure.
     ///
@@ -71,6 +171,92 @@ pub trait NearestNeighbors<T> {
     /// A vector containing all neighbors within the given radius.
     fn nearest_r(&self, data: &T, radius: f64) -> Vec<T>;
 
+    /// Finds the `k` nearest neighbors to a given data point, with their distances.
+    ///
+    /// By default, this is `nearest_k` with the distances computed again by the distance
+    /// function, and sorted; data structures override it to keep the distances found by the
+    /// search. The default panics if `get_distance_function` returns `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data point to find the nearest neighbors for.
+    /// * `k` - The number of nearest neighbors to find.
+    /// * `out` - The buffer receiving the neighbors, sorted by distance. It is cleared first, so
+    ///   that the same buffer can be reused across queries.
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        let neighbors = self.nearest_k(data, k);
+        pair_with_distances(self.get_distance_function(), data, neighbors, out);
+    }
+
+    /// Finds all neighbors within a given radius of a data point, with their distances.
+    ///
+    /// By default, this is `nearest_r` with the distances computed again by the distance
+    /// function, and sorted; data structures override it to keep the distances found by the
+    /// search. The default panics if `get_distance_function` returns `None`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data point to find the neighbors for.
+    /// * `radius` - The radius within which to find neighbors.
+    /// * `out` - The buffer receiving the neighbors, sorted by distance. It is cleared first.
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        let neighbors = self.nearest_r(data, radius);
+        pair_with_distances(self.get_distance_function(), data, neighbors, out);
+    }
+
+    /// Finds the `k` nearest neighbors to a given data point among those within a given radius,
+    /// with their distances.
+    ///
+    /// By default, this is `nearest_k_into` without the neighbors further than `radius`; data
+    /// structures override it to prune the search with the radius.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data point to find the nearest neighbors for.
+    /// * `k` - The maximum number of nearest neighbors to find.
+    /// * `radius` - The radius within which to find neighbors.
+    /// * `out` - The buffer receiving the neighbors, sorted by distance. It is cleared first.
+    fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        self.nearest_k_into(data, k, out);
+        let within = out.partition_point(|(_, dist)| *dist <= radius);
+        out.truncate(within);
+    }
+
+    /// Finds the `k` nearest neighbors to a given data point, with their distances.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing the `k` nearest neighbors and their distances, sorted by distance.
+    fn nearest_k_with_distances(&self, data: &T, k: usize) -> Vec<(T, f64)> {
+        let mut out = Vec::new();
+        self.nearest_k_into(data, k, &mut out);
+        out
+    }
+
+    /// Finds all neighbors within a given radius of a data point, with their distances.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing all neighbors within the given radius and their distances, sorted by
+    /// distance.
+    fn nearest_r_with_distances(&self, data: &T, radius: f64) -> Vec<(T, f64)> {
+        let mut out = Vec::new();
+        self.nearest_r_into(data, radius, &mut out);
+        out
+    }
+
+    /// Finds the `k` nearest neighbors to a given data point among those within a given radius,
+    /// with their distances. This is the neighborhood used by the rewiring step of RRT*.
+    ///
+    /// # Returns
+    ///
+    /// A vector containing at most `k` neighbors and their distances, sorted by distance.
+    fn nearest_k_within(&self, data: &T, k: usize, radius: f64) -> Vec<(T, f64)> {
+        let mut out = Vec::new();
+        self.nearest_k_within_into(data, k, radius, &mut out);
+        out
+    }
+
     /// Clears all data points from the data structure.
     fn clear(&mut self);
 
@@ -81,3 +267,166 @@ pub trait NearestNeighbors<T> {
     /// The number of data points in the data structure.
     fn size(&self) -> usize;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::datastructure::nearest_neighbours_GNANT_no_therad_safety::NearestNeighborsGNATNoThreadSafety;
+    use crate::datastructure::nearest_neighbours_kd_tree::{DistanceFunctionMetric, VpAvl};
+    use crate::datastructure::nearest_neighbours_linear::NearestNeighborsLinear;
+    use crate::datastructure::nearest_neighbours_real_vector_kd_tree::NearestNeighborsKdTree;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn assert_same_neighbors(found: &[(StateKey, f64)], expected: &[(StateKey, f64)]) {
+        assert_eq!(found.len(), expected.len());
+        for ((s1, d1), (s2, d2)) in found.iter().zip(expected) {
+            assert_eq!(s1, s2);
+            assert!((d1 - d2).abs() < 1e-9);
+        }
+    }
+
+    /// Check a nearest neighbors data structure, made for the given space, against `NearestNeighborsLinear` on random
+    /// states. `nearest` is only required to be exact if `exact_nearest` is set.
+    fn check_conformance(
+        make: impl FnOnce(Rc<RealVectorStateSpace>) -> Box<dyn NearestNeighbors<StateKey>>,
+        exact_nearest: bool,
+    ) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut space = RealVectorStateSpace::new();
+        for _ in 0..4 {
+            space.add_dimension(None, -1.0, 1.0);
+        }
+        let space = Rc::new(space);
+        let mut nn = make(space.clone());
+        let mut random_state = || {
+            let values = (0..4).map(|_| rng.gen_range(-1.0..1.0)).collect();
+            space
+                .alloc_arena_state_with_value(RealVectorState {
+                    values: DVector::from_vec(values),
+                })
+                .key()
+        };
+
+        let mut linear = NearestNeighborsLinear::new();
+        linear.set_distance_function(state_space_distance_function(space.clone()));
+        nn.set_distance_function(state_space_distance_function(space.clone()));
+
+        let states: Vec<StateKey> = (0..400).map(|_| random_state()).collect();
+        let queries: Vec<StateKey> = (0..30).map(|_| random_state()).collect();
+        linear.add_multiple(states.clone());
+        nn.add_multiple(states[..200].to_vec());
+        for s in &states[200..] {
+            nn.add(*s);
+        }
+
+        let mut out = Vec::new();
+        let mut check = |nn: &dyn NearestNeighbors<StateKey>,
+                         linear: &NearestNeighborsLinear<_>| {
+            assert_eq!(nn.size(), linear.size());
+            for query in &queries {
+                let nearest = nn.nearest(query).unwrap();
+                if exact_nearest {
+                    assert_eq!(Some(nearest), linear.nearest(query));
+                } else {
+                    assert!(linear.get_data().contains(&nearest));
+                }
+                assert_eq!(nn.nearest_k(query, 10), linear.nearest_k(query, 10));
+                assert_eq!(nn.nearest_r(query, 0.6), linear.nearest_r(query, 0.6));
+
+                let expected = linear.nearest_k_with_distances(query, 10);
+                nn.nearest_k_into(query, 10, &mut out);
+                assert_same_neighbors(&out, &expected);
+                let expected = linear.nearest_r_with_distances(query, 0.6);
+                nn.nearest_r_into(query, 0.6, &mut out);
+                assert_same_neighbors(&out, &expected);
+                let expected = linear.nearest_k_within(query, 5, 0.5);
+                nn.nearest_k_within_into(query, 5, 0.5, &mut out);
+                assert_same_neighbors(&out, &expected);
+                assert!(out.len() <= 5 && out.iter().all(|(_, d)| *d <= 0.5));
+            }
+        };
+        check(nn.as_ref(), &linear);
+
+        for s in states.iter().step_by(3) {
+            assert!(nn.remove(s));
+            assert!(linear.remove(s));
+        }
+        assert!(!nn.remove(&states[0]));
+        check(nn.as_ref(), &linear);
+
+        nn.clear();
+        assert_eq!(nn.size(), 0);
+        assert!(nn.nearest(&queries[0]).is_none());
+    }
+
+    /// A data structure implementing only the required methods, to check the default ones.
+    struct RequiredOnly(NearestNeighborsLinear<StateKey>);
+
+    impl NearestNeighbors<StateKey> for RequiredOnly {
+        fn set_distance_function(&mut self, dist_fn: LocalDistanceFunction<StateKey>) {
+            NearestNeighbors::set_distance_function(&mut self.0, dist_fn);
+        }
+
+        fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, StateKey>> {
+            self.0.get_distance_function()
+        }
+
+        fn add(&mut self, data: StateKey) {
+            self.0.add(data);
+        }
+
+        fn add_multiple(&mut self, data: Vec<StateKey>) {
+            NearestNeighbors::add_multiple(&mut self.0, data);
+        }
+
+        fn remove(&mut self, data: &StateKey) -> bool {
+            NearestNeighbors::remove(&mut self.0, data)
+        }
+
+        fn nearest(&self, data: &StateKey) -> Option<StateKey> {
+            self.0.nearest(data)
+        }
+
+        fn nearest_k(&self, data: &StateKey, k: usize) -> Vec<StateKey> {
+            self.0.nearest_k(data, k)
+        }
+
+        fn nearest_r(&self, data: &StateKey, radius: f64) -> Vec<StateKey> {
+            self.0.nearest_r(data, radius)
+        }
+
+        fn clear(&mut self) {
+            NearestNeighbors::clear(&mut self.0);
+        }
+
+        fn size(&self) -> usize {
+            NearestNeighbors::size(&self.0)
+        }
+    }
+
+    #[test]
+    fn test_nearest_neighbors_conformance() {
+        check_conformance(|_| Box::new(NearestNeighborsLinear::new()), true);
+        check_conformance(
+            |_| Box::new(RequiredOnly(NearestNeighborsLinear::new())),
+            true,
+        );
+        check_conformance(|_| Box::new(NearestNeighborsSqrtApprox::new()), false);
+        check_conformance(
+            |_| Box::new(VpAvl::new(DistanceFunctionMetric::default())),
+            true,
+        );
+        check_conformance(
+            |_| {
+                let mut gnat = NearestNeighborsGNATNoThreadSafety::new();
+                gnat.set_max_num_pts_per_leaf(8);
+                Box::new(gnat)
+            },
+            true,
+        );
+        check_conformance(|space| Box::new(NearestNeighborsKdTree::new(space)), true);
+    }
+}
@@ -1,370 +1,880 @@
-// use std::cmp::Ordering;
-// use std::collections::{BinaryHeap, HashSet, VecDeque};
-// use std::f64;
-// use std::sync::Arc;
-
-// use super::nearest_neighbours::{DistanceFunction, NearestNeighbors};
-
-// pub struct GNAT<'a, T: Eq + std::hash::Hash> {
-//     degree: usize,
-//     min_degree: usize,
-//     max_degree: usize,
-//     max_num_pts_per_leaf: usize,
-//     removed_cache_size: usize,
-//     rebuild_size: usize,
-//     size: usize,
-//     tree: Option<Node<T>>,
-//     removed: HashSet<T>,
-//     dist_fn: DistanceFunction<T>,
-
-//     near_queue: NearQueue<'a, T>,
-//     /// \brief Nodes yet to be processed for possible nearest neighbors
-//     node_queue: NearQueue<'a, T>,
-// }
-
-// struct Node<T> {
-//     // scratch space to store the distance to the pivot
-//     dist_to_pivot: f64,
-
-//     degree: usize,
-//     pivot: T,
-//     min_radius: f64,
-//     max_radius: f64,
-//     min_range: Vec<f64>,
-//     max_range: Vec<f64>,
-//     data: Vec<T>,
-//     children: Vec<Node<T>>,
-// }
-
-// struct Neighbour<'a, T> {
-//     dist: f64,
-//     key: &'a T,
-// }
-// type NearQueue<'a, T> =  BinaryHeap<Neighbour<'a, T>>;
-
-// impl <'a, T>
-// PartialEq for Neighbour<'a, T> {
-//     fn eq(&self, other: &Self) -> bool {
-//         self.dist == other.dist
-//     }
-// }
-
-// impl <'a, T>
-// PartialOrd for Neighbour<'a, T> {
-//     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-//         self.dist.partial_cmp(&other.dist)
-//     }
-// }
-
-// impl <'a, T>
-// Eq for Neighbour<'a, T> {}
-
-// impl <'a, T>
-// Ord for Neighbour<'a, T> {
-//     fn cmp(&self, other: &Self) -> Ordering {
-//         self.dist.partial_cmp(&other.dist).unwrap()
-//     }
-// }
-
-// // impl<T> PartialEq for Node<T> {
-// //     fn eq(&self, other: &Self) -> bool {
-// //         self.pivot == other.pivot
-// //     }
-// // }
-
-// impl<T> Node<T>
-// where
-//     T: Eq + std::hash::Hash,
-// {
-//     fn new(degree: usize, pivot: T) -> Self {
-//         Self {
-//             dist_to_pivot: f64::INFINITY,
-//             degree,
-//             pivot,
-//             min_radius: f64::INFINITY,
-//             max_radius: f64::NEG_INFINITY,
-//             min_range: vec![f64::INFINITY; degree],
-//             max_range: vec![f64::NEG_INFINITY; degree],
-//             data: Vec::new(),
-//             children: Vec::new(),
-//         }
-//     }
-
-//     fn update_radius(&mut self, dist: f64) {
-//         if self.min_radius > dist {
-//             self.min_radius = dist;
-//         }
-//         if self.max_radius < dist {
-//             self.max_radius = dist;
-//         }
-//     }
-
-//     fn update_range(&mut self, i: usize, dist: f64) {
-//         if self.min_range[i] > dist {
-//             self.min_range[i] = dist;
-//         }
-//         if self.max_range[i] < dist {
-//             self.max_range[i] = dist;
-//         }
-//     }
-
-//     // fn add(&mut self, gnat: &mut GNAT<T>, data: T) {
-//     //     if self.children.is_empty() {
-//     //         self.data.push(data);
-//     //         gnat.size += 1;
-//     //         if self.data.len() > gnat.max_num_pts_per_leaf && self.data.len() > self.degree {
-//     //             self.split(gnat);
-//     //         }
-//     //     } else {
-//     //         let mut min_dist = (gnat.dist_fn)(&data, &self.children[0].pivot);
-//     //         let mut min_ind = 0;
-//     //         for (i, child) in self.children.iter_mut().enumerate().skip(1) {
-//     //             let dist = (gnat.dist_fn)(&data, &child.pivot);
-//     //             if dist < min_dist {
-//     //                 min_dist = dist;
-//     //                 min_ind = i;
-//     //             }
-//     //             child.update_range(min_ind, dist);
-//     //         }
-//     //         self.children[min_ind].update_radius(min_dist);
-//     //         self.children[min_ind].add(gnat, data);
-//     //     }
-//     // }
-
-//     fn need_to_split(&self, gnat: &GNAT<T>) -> bool {
-//         self.data.len() > gnat.max_num_pts_per_leaf && self.data.len() > self.degree
-//     }
-
-//     fn split(&mut self, gnat: &mut GNAT<T>) {
-//         // ...existing code for splitting the node...
-//     }
-
-//     fn nearest_k(&self, gnat: &GNAT<T>, data: &T, k: usize, nbh: &mut VecDeque<(f64, Arc<T>)>) {
-//         // ...existing code for finding k nearest neighbors...
-//     }
-
-//     fn nearest_r(&self, gnat: &GNAT<T>, data: &T, radius: f64, nbh: &mut VecDeque<(f64, Arc<T>)>) {
-//         // ...existing code for finding neighbors within radius...
-//     }
-// }
-
-//     pub fn insert_neighbour_k<'a, T>(
-//         // &self
-//         nbh: &mut NearQueue<'a, T>,
-//         k: usize,
-//         data: &'a T,
-//         key: &'a T,
-//         dist: f64,
-//     ) -> bool
-//         where
-//             T: Eq + std::hash::Hash
-//     {
-//         if nbh.len() < k {
-//             nbh.push(Neighbour { dist, key });
-//             return true;
-//         }
-
-//         match nbh.peek() {
-//             Some(neighbour) => {
-//                 if dist < neighbour.dist || dist < f64::EPSILON
-//                 && data == key
-//                 {
-//                     nbh.pop();
-//                     nbh.push(Neighbour { dist, key });
-//                     return true;
-//                 }
-//             }
-//             None => {
-//                 return false;
-//             }
-//         }
-
-//         return false;
-//     }
-
-// impl<'a, T> GNAT<'a, T>
-// where
-//     T: Eq + std::hash::Hash,
-// {
-//     pub fn new(
-//         degree: usize,
-//         min_degree: usize,
-//         max_degree: usize,
-//         max_num_pts_per_leaf: usize,
-//         removed_cache_size: usize,
-//         dist_fn: DistanceFunction<T>,
-//     ) -> Self {
-//         Self {
-//             node_queue: BinaryHeap::new(),
-//             near_queue: BinaryHeap::new(),
-
-//             degree,
-//             min_degree,
-//             max_degree,
-//             max_num_pts_per_leaf,
-//             removed_cache_size,
-//             rebuild_size: max_num_pts_per_leaf * degree,
-//             size: 0,
-//             tree: None,
-//             removed: HashSet::new(),
-//             dist_fn,
-//         }
-//     }
-
-//     fn rebuild_data_structure(&mut self) {
-//         // ...existing code for rebuilding the data structure...
-//     }
-
-//     fn add_to_node(&mut self, node: &mut Node<T>, data: T) {
-//         // TODO ifdef GNAT_SAMPLER
-//         if node.children.is_empty() {
-//             node.data.push(data);
-//             self.size += 1;
-//             if node.need_to_split(self) {
-//                 if !self.removed.is_empty() {
-//                     self.rebuild_data_structure();
-//                 } else if self.size >= self.rebuild_size {
-//                     let rebuild_size = self.rebuild_size << 1;
-//                     self.rebuild_data_structure();
-//                     self.rebuild_size = rebuild_size;
-//                 } else {
-//                     node.split(self);
-//                 }
-//             }
-//         } else {
-//             let mut min_dist = (self.dist_fn)(&data, &node.children[0].pivot);
-//             node.children[0].dist_to_pivot = min_dist;
-
-//             let mut min_ind = 0;
-//             for (i, child) in node.children.iter_mut().enumerate().skip(1) {
-//                 child.dist_to_pivot = (self.dist_fn)(&data, &child.pivot);
-//                 if child.dist_to_pivot < min_dist {
-//                     min_dist = child.dist_to_pivot;
-//                     min_ind = i;
-//                 }
-//             }
-
-//             for child in node.children.iter_mut() {
-//                 child.update_range(min_ind, child.dist_to_pivot);
-//             }
-
-//             node.children[min_ind].update_radius(min_dist);
-//             self.add_to_node(&mut node.children[min_ind], data);
-//         }
-//     }
-
-//     fn nearest_k_internal(&self, data: &T, k: usize) -> bool {
-
-//         let tree = if let Some(tree) = self.tree { tree
-//         } else {
-//             return false;
-//         };
-
-//         let mut is_pivot = false;
-
-//         tree.dist_to_pivot = (self.dist_fn)(data, &tree.pivot);
-//         is_pivot = insert_neighbour_k(
-//             &mut self.near_queue,
-//             k,
-//             &tree.pivot,
-//             data,
-//             tree.dist_to_pivot,
-//         );
-
-//         if self.size == 0 {
-//             return None;
-//         }
-
-//         let mut nbh = VecDeque::with_capacity(k);
-//         if let Some(tree) = &self.tree {
-//             tree.nearest_k(self, data, k, &mut nbh);
-//         }
-
-//         is_pivot
-//     }
-// }
-
-// impl<T> NearestNeighbors<T> for GNAT<T>
-// where
-//     T: Eq + std::hash::Hash,
-// {
-//     fn set_distance_function(&mut self, dist_fn: DistanceFunction<T>) {
-//         self.dist_fn = dist_fn;
-//         if self.tree.is_some() {
-//             self.rebuild_data_structure();
-//         }
-//     }
-
-//     fn add(&mut self, data: T) {
-//         // tempoarily remove the data from the tree (to avoid ownership)
-//         let mut tree = self.tree.take();
-
-//         if let Some(tree) = &mut tree {
-//             if self.removed.contains(&data) {
-//                 self.rebuild_data_structure();
-//             }
-
-//             self.add_to_node(tree, data);
-//         } else {
-//             tree = Some(Node::new(self.degree, data));
-//             self.size = 1;
-//         }
-
-//         // insert the data back into the tree
-//         self.tree = tree;
-//     }
-
-//     fn add_multiple(&mut self, mut data: Vec<T>) {
-//         if self.tree.is_none() {
-//             if data.is_empty() {
-//                 return;
-//             }
-//             // data has length 1, rest has the rest of the data
-//             let rest = data.split_off(1);
-//             let mut new_root = Node::new(self.degree, data.remove(0));
-//             // TODO: GNAT_SAMPLER tree_->subtreeSize_ = data.size();
-
-//             new_root.data.extend(rest);
-//             self.size = 1 + data.len();
-//             if new_root.need_to_split(self) {
-//                 new_root.split(self);
-//             }
-
-//             self.tree = Some(new_root);
-//         }
-//         for d in data {
-//             self.add(d);
-//         }
-//     }
-
-//     fn remove(&mut self, data: &T) -> bool {
-//         if self.size == 0 {
-//             return false;
-//         }
-//         // find data in tree
-//         let is_pivor = self.nearest_k_internal(data, k)
-//     }
-
-//     fn nearest(&self, data: &T) -> Option<T> {
-//         // ...existing code for finding the nearest neighbor...
-//     }
-
-//     fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
-//         // ...existing code for finding k nearest neighbors...
-//     }
-
-//     fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
-//         // ...existing code for finding neighbors within radius...
-//     }
-
-//     fn clear(&mut self) {
-//         self.tree = None;
-//         self.size = 0;
-//         self.removed.clear();
-//         // TODO: rebuildSize_
-//     }
-
-//     fn size(&self) -> usize {
-//         self.size
-//     }
-// }
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::f64;
+
+use super::nearest_neighbours::{
+    DistanceFunction, DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors,
+};
+
+/// Geometric Near-neighbor Access Tree (GNAT), a data structure for nearest neighbor search in
+/// metric spaces, after Brin, "Near neighbor search in large metric spaces", VLDB 1995.
+///
+/// Every node has a pivot and up to `degree` children, each child holding the elements closer
+/// to its pivot than to the pivots of its siblings. Nodes record the range of distances
+/// between their pivot and the elements of every sibling, which prunes whole subtrees during
+/// queries using the triangle inequality.
+///
+/// Removed elements are only marked as removed, and the tree is rebuilt when a pivot is
+/// removed or when `removed_cache_size` elements have been removed.
+///
+/// Use `NearestNeighborsGNATNoThreadSafety` or `NearestNeighborsGNAT` rather than this type
+/// directly.
+pub struct Gnat<T, D> {
+    degree: usize,
+    min_degree: usize,
+    max_degree: usize,
+    max_num_pts_per_leaf: usize,
+    removed_cache_size: usize,
+    rebuild_size: usize,
+    size: usize,
+    items: Vec<T>,
+    removed: HashSet<usize>,
+    tree: Option<Node>,
+    dist_fn: Option<D>,
+}
+
+/// A GNAT using a `LocalDistanceFunction`, which may capture non thread-safe values such as an
+/// `Rc<dyn StateSpace>`. This is the variant to use through the `NearestNeighbors` trait.
+pub type NearestNeighborsGNATNoThreadSafety<T> = Gnat<T, LocalDistanceFunction<T>>;
+
+/// A GNAT using a `DistanceFunction`: it is `Send + Sync` whenever `T` is, so queries,
+/// which only need a shared reference, can run concurrently from several threads.
+pub type NearestNeighborsGNAT<T> = Gnat<T, DistanceFunction<T>>;
+
+struct Node {
+    degree: usize,
+    pivot: usize,
+    // range of distances between the pivot and the elements of the subtree
+    min_radius: f64,
+    max_radius: f64,
+    // range of distances between the pivot and the elements of the subtree of every sibling
+    min_range: Vec<f64>,
+    max_range: Vec<f64>,
+    data: Vec<usize>,
+    children: Vec<Node>,
+}
+
+/// What nodes need to know about the GNAT they belong to.
+struct Context<'a, T, D> {
+    items: &'a [T],
+    dist_fn: &'a D,
+    min_degree: usize,
+    max_degree: usize,
+    max_num_pts_per_leaf: usize,
+}
+
+impl<T, D: Fn(&T, &T) -> f64> Context<'_, T, D> {
+    fn distance(&self, a: usize, b: usize) -> f64 {
+        (self.dist_fn)(&self.items[a], &self.items[b])
+    }
+}
+
+impl Node {
+    fn new(degree: usize, siblings: usize, pivot: usize) -> Self {
+        Self {
+            degree,
+            pivot,
+            min_radius: f64::INFINITY,
+            max_radius: f64::NEG_INFINITY,
+            min_range: vec![f64::INFINITY; siblings],
+            max_range: vec![f64::NEG_INFINITY; siblings],
+            data: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn update_radius(&mut self, dist: f64) {
+        self.min_radius = self.min_radius.min(dist);
+        self.max_radius = self.max_radius.max(dist);
+    }
+
+    fn update_range(&mut self, i: usize, dist: f64) {
+        self.min_range[i] = self.min_range[i].min(dist);
+        self.max_range[i] = self.max_range[i].max(dist);
+    }
+
+    fn need_to_split<T, D>(&self, ctx: &Context<T, D>) -> bool {
+        self.data.len() > ctx.max_num_pts_per_leaf && self.data.len() > self.degree
+    }
+
+    /// Add the element `index` to the subtree. Leaves that become too large are split if
+    /// `allow_split` is set; otherwise this returns true and the GNAT must be rebuilt.
+    fn add<T, D: Fn(&T, &T) -> f64>(
+        &mut self,
+        ctx: &Context<T, D>,
+        index: usize,
+        allow_split: bool,
+    ) -> bool {
+        if self.children.is_empty() {
+            self.data.push(index);
+            if !self.need_to_split(ctx) {
+                return false;
+            }
+            if allow_split {
+                self.split(ctx);
+            }
+            return !allow_split;
+        }
+
+        let dists: Vec<f64> = self
+            .children
+            .iter()
+            .map(|c| ctx.distance(index, c.pivot))
+            .collect();
+        let min_ind = (1..dists.len()).fold(0, |m, i| if dists[i] < dists[m] { i } else { m });
+        for (child, &dist) in self.children.iter_mut().zip(&dists) {
+            child.update_range(min_ind, dist);
+        }
+        self.children[min_ind].update_radius(dists[min_ind]);
+        self.children[min_ind].add(ctx, index, allow_split)
+    }
+
+    /// Turn a leaf into an inner node, using its elements as `degree` children.
+    fn split<T, D: Fn(&T, &T) -> f64>(&mut self, ctx: &Context<T, D>) {
+        let (centers, dists) = greedy_k_centers(ctx, &self.data, self.degree);
+        let degree = centers.len();
+        self.children = centers
+            .iter()
+            .map(|&c| Node::new(self.degree, degree, self.data[c]))
+            .collect();
+        self.degree = degree;
+
+        for (j, &index) in self.data.iter().enumerate() {
+            let k = (1..degree).fold(0, |m, i| if dists[j][i] < dists[j][m] { i } else { m });
+            if j != centers[k] {
+                self.children[k].data.push(index);
+                self.children[k].update_radius(dists[j][k]);
+            }
+            for (i, child) in self.children.iter_mut().enumerate() {
+                child.update_range(k, dists[j][i]);
+            }
+        }
+
+        let total = self.data.len();
+        for child in &mut self.children {
+            // keep the degree of every child proportional to its size
+            child.degree =
+                (degree * child.data.len() / total).clamp(ctx.min_degree, ctx.max_degree);
+            if child.min_radius == f64::INFINITY {
+                // only the pivot
+                child.min_radius = 0.0;
+                child.max_radius = 0.0;
+            }
+        }
+        self.data = Vec::new();
+
+        for child in &mut self.children {
+            if child.need_to_split(ctx) {
+                child.split(ctx);
+            }
+        }
+    }
+}
+
+/// Choose up to `k` centers among `data` by repeatedly picking the element furthest from the
+/// centers chosen so far. Returns the positions of the centers in `data`, and for every
+/// element its distance to every center.
+fn greedy_k_centers<T, D: Fn(&T, &T) -> f64>(
+    ctx: &Context<T, D>,
+    data: &[usize],
+    k: usize,
+) -> (Vec<usize>, Vec<Vec<f64>>) {
+    let mut centers = vec![0];
+    let mut dists = vec![Vec::with_capacity(k); data.len()];
+    let mut min_dist = vec![f64::INFINITY; data.len()];
+    loop {
+        let center = data[*centers.last().unwrap()];
+        let mut furthest = 0;
+        for (j, &index) in data.iter().enumerate() {
+            let dist = ctx.distance(index, center);
+            dists[j].push(dist);
+            min_dist[j] = min_dist[j].min(dist);
+            if min_dist[j] > min_dist[furthest] {
+                furthest = j;
+            }
+        }
+        // stop early when all the remaining elements coincide with a center
+        if centers.len() == k || min_dist[furthest] < f64::EPSILON {
+            break;
+        }
+        centers.push(furthest);
+    }
+    (centers, dists)
+}
+
+/// A candidate neighbor, the furthest one being on top of the heap.
+struct Neighbor {
+    dist: f64,
+    index: usize,
+    is_pivot: bool,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist)
+    }
+}
+
+/// A node that may contain neighbors, the one with the smallest lower bound on the distance
+/// to its elements being on top of the heap.
+struct Prospect<'a> {
+    node: &'a Node,
+    dist_to_pivot: f64,
+}
+
+impl Prospect<'_> {
+    fn lower_bound(&self) -> f64 {
+        self.dist_to_pivot - self.node.max_radius
+    }
+}
+
+impl PartialEq for Prospect<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Prospect<'_> {}
+
+impl PartialOrd for Prospect<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prospect<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.lower_bound().total_cmp(&self.lower_bound())
+    }
+}
+
+impl<T, D> Default for Gnat<T, D> {
+    fn default() -> Self {
+        Self {
+            degree: 8,
+            min_degree: 4,
+            max_degree: 12,
+            max_num_pts_per_leaf: 50,
+            removed_cache_size: 500,
+            rebuild_size: 8 * 50,
+            size: 0,
+            items: Vec::new(),
+            removed: HashSet::new(),
+            tree: None,
+            dist_fn: None,
+        }
+    }
+}
+
+impl<T, D> Gnat<T, D>
+where
+    T: Clone + PartialEq,
+    D: Fn(&T, &T) -> f64,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the distance function, rebuilding the tree if it is not empty.
+    pub fn set_distance_function(&mut self, dist_fn: D) {
+        self.dist_fn = Some(dist_fn);
+        if self.tree.is_some() {
+            self.rebuild_data_structure();
+        }
+    }
+
+    /// Set the desired number of children of inner nodes, which must lie between the minimum
+    /// and the maximum degree.
+    pub fn set_degree(&mut self, degree: usize) {
+        if degree < self.min_degree || degree > self.max_degree {
+            panic!("The degree must lie between the minimum and the maximum degree");
+        }
+        self.degree = degree;
+        self.rebuild_size = self.max_num_pts_per_leaf * degree;
+        self.rebuild_if_built();
+    }
+
+    pub fn get_degree(&self) -> usize {
+        self.degree
+    }
+
+    /// Set the minimum and maximum number of children of inner nodes. The current degree is
+    /// clamped to the new range.
+    pub fn set_degree_range(&mut self, min_degree: usize, max_degree: usize) {
+        if min_degree < 2 || min_degree > max_degree {
+            panic!("The degree range must be non-empty and start at 2 or more");
+        }
+        self.min_degree = min_degree;
+        self.max_degree = max_degree;
+        self.degree = self.degree.clamp(min_degree, max_degree);
+        self.rebuild_size = self.max_num_pts_per_leaf * self.degree;
+        self.rebuild_if_built();
+    }
+
+    pub fn get_min_degree(&self) -> usize {
+        self.min_degree
+    }
+
+    pub fn get_max_degree(&self) -> usize {
+        self.max_degree
+    }
+
+    /// Set the number of elements above which leaves are split.
+    pub fn set_max_num_pts_per_leaf(&mut self, max_num_pts_per_leaf: usize) {
+        if max_num_pts_per_leaf == 0 {
+            panic!("Leaves must be able to hold at least one element");
+        }
+        self.max_num_pts_per_leaf = max_num_pts_per_leaf;
+        self.rebuild_size = max_num_pts_per_leaf * self.degree;
+        self.rebuild_if_built();
+    }
+
+    pub fn get_max_num_pts_per_leaf(&self) -> usize {
+        self.max_num_pts_per_leaf
+    }
+
+    /// Set the number of removed elements that are kept in the tree before it is rebuilt.
+    pub fn set_removed_cache_size(&mut self, removed_cache_size: usize) {
+        self.removed_cache_size = removed_cache_size;
+        if self.removed.len() >= removed_cache_size {
+            self.rebuild_if_built();
+        }
+    }
+
+    pub fn get_removed_cache_size(&self) -> usize {
+        self.removed_cache_size
+    }
+
+    /// All the elements, in no particular order.
+    pub fn list(&self) -> Vec<T> {
+        (0..self.items.len())
+            .filter(|i| !self.removed.contains(i))
+            .map(|i| self.items[i].clone())
+            .collect()
+    }
+
+    pub fn add(&mut self, data: T) {
+        self.items.push(data);
+        let index = self.items.len() - 1;
+        let Some(mut tree) = self.tree.take() else {
+            self.tree = Some(Node::new(self.degree, 0, index));
+            self.size = 1;
+            return;
+        };
+
+        self.size += 1;
+        let allow_split = self.removed.is_empty() && self.size < self.rebuild_size;
+        let must_rebuild = tree.add(&self.context(), index, allow_split);
+        self.tree = Some(tree);
+
+        if must_rebuild {
+            if self.removed.is_empty() {
+                // rebuilding balances the tree, which gets rarer as it grows
+                let rebuild_size = self.rebuild_size << 1;
+                self.rebuild_data_structure();
+                self.rebuild_size = rebuild_size;
+            } else {
+                self.rebuild_data_structure();
+            }
+        }
+    }
+
+    pub fn add_multiple(&mut self, data: Vec<T>) {
+        if self.tree.is_some() {
+            for d in data {
+                self.add(d);
+            }
+            return;
+        }
+        if data.is_empty() {
+            return;
+        }
+
+        let first = self.items.len();
+        self.size += data.len();
+        self.items.extend(data);
+        let mut tree = Node::new(self.degree, 0, first);
+        tree.data.extend(first + 1..self.items.len());
+        let ctx = self.context();
+        if tree.need_to_split(&ctx) {
+            tree.split(&ctx);
+        }
+        self.tree = Some(tree);
+    }
+
+    /// Remove an element equal to `data`. Returns false if there is none.
+    pub fn remove(&mut self, data: &T) -> bool {
+        let Some(found) = self.nearest_k_internal(data, 1, f64::INFINITY).pop() else {
+            return false;
+        };
+        if self.items[found.index] != *data {
+            return false;
+        }
+
+        self.removed.insert(found.index);
+        self.size -= 1;
+        // a removed pivot still shapes the tree, so the tree is rebuilt without it
+        if found.is_pivot || self.removed.len() >= self.removed_cache_size {
+            self.rebuild_data_structure();
+        }
+        true
+    }
+
+    pub fn nearest(&self, data: &T) -> Option<T> {
+        self.nearest_k(data, 1).pop()
+    }
+
+    /// The `k` nearest elements, from the nearest to the furthest.
+    pub fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        self.nearest_k_internal(data, k, f64::INFINITY)
+            .into_iter()
+            .map(|n| self.items[n.index].clone())
+            .collect()
+    }
+
+    /// The elements within distance `radius`, from the nearest to the furthest.
+    pub fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.nearest_r_internal(data, radius)
+            .into_iter()
+            .map(|n| self.items[n.index].clone())
+            .collect()
+    }
+
+    /// Write the `k` nearest elements and their distances to `out`, from the nearest to the
+    /// furthest.
+    pub fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        self.nearest_k_within_into(data, k, f64::INFINITY, out);
+    }
+
+    /// Write the elements within distance `radius` and their distances to `out`, from the
+    /// nearest to the furthest.
+    pub fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        let near = self.nearest_r_internal(data, radius);
+        self.write_neighbors(near, out);
+    }
+
+    /// Write the `k` nearest elements within distance `radius` and their distances to `out`,
+    /// from the nearest to the furthest. The radius prunes the search from the start.
+    pub fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        let near = self.nearest_k_internal(data, k, radius);
+        self.write_neighbors(near, out);
+    }
+
+    pub fn clear(&mut self) {
+        self.tree = None;
+        self.size = 0;
+        self.items.clear();
+        self.removed.clear();
+        self.rebuild_size = self.max_num_pts_per_leaf * self.degree;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn context(&self) -> Context<'_, T, D> {
+        Context {
+            items: &self.items,
+            dist_fn: self
+                .dist_fn
+                .as_ref()
+                .expect("The distance function must be set before using the nearest neighbors"),
+            min_degree: self.min_degree,
+            max_degree: self.max_degree,
+            max_num_pts_per_leaf: self.max_num_pts_per_leaf,
+        }
+    }
+
+    fn rebuild_data_structure(&mut self) {
+        let data = self.list();
+        self.clear();
+        self.add_multiple(data);
+    }
+
+    fn rebuild_if_built(&mut self) {
+        if self.tree.is_some() {
+            self.rebuild_data_structure();
+        }
+    }
+
+    fn is_removed(&self, index: usize) -> bool {
+        self.removed.contains(&index)
+    }
+
+    fn write_neighbors(&self, near: Vec<Neighbor>, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        out.extend(
+            near.into_iter()
+                .map(|n| (self.items[n.index].clone(), n.dist)),
+        );
+    }
+
+    /// Offer the element `index` at distance `dist` as one of the `k` nearest neighbors of
+    /// `data` within `radius`. Among elements at distance zero, one equal to `data` is
+    /// preferred, so that it can be found for removal.
+    fn insert_neighbor_k(
+        &self,
+        near: &mut BinaryHeap<Neighbor>,
+        k: usize,
+        radius: f64,
+        data: &T,
+        neighbor: Neighbor,
+    ) {
+        if neighbor.dist > radius {
+            return;
+        }
+        if near.len() < k {
+            near.push(neighbor);
+        } else if neighbor.dist < near.peek().unwrap().dist
+            || (neighbor.dist < f64::EPSILON && self.items[neighbor.index] == *data)
+        {
+            near.pop();
+            near.push(neighbor);
+        }
+    }
+
+    /// The `k` nearest neighbors of `data` within `radius`, sorted by distance.
+    fn nearest_k_internal(&self, data: &T, k: usize, radius: f64) -> Vec<Neighbor> {
+        let Some(tree) = &self.tree else {
+            return Vec::new();
+        };
+        if k == 0 || self.size == 0 {
+            return Vec::new();
+        }
+        let ctx = self.context();
+        let mut near = BinaryHeap::with_capacity(k.min(self.size) + 1);
+        let mut nodes = BinaryHeap::new();
+
+        let dist = (ctx.dist_fn)(data, &self.items[tree.pivot]);
+        if !self.is_removed(tree.pivot) {
+            let root = Neighbor {
+                dist,
+                index: tree.pivot,
+                is_pivot: true,
+            };
+            self.insert_neighbor_k(&mut near, k, radius, data, root);
+        }
+        self.nearest_k_node(&ctx, tree, data, k, radius, &mut near, &mut nodes);
+
+        while let Some(prospect) = nodes.pop() {
+            let node = prospect.node;
+            let worst = search_bound(&near, k, radius);
+            if worst.is_finite()
+                && (prospect.dist_to_pivot > node.max_radius + worst
+                    || prospect.dist_to_pivot < node.min_radius - worst)
+            {
+                continue;
+            }
+            self.nearest_k_node(&ctx, node, data, k, radius, &mut near, &mut nodes);
+        }
+
+        near.into_sorted_vec()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn nearest_k_node<'a>(
+        &self,
+        ctx: &Context<T, D>,
+        node: &'a Node,
+        data: &T,
+        k: usize,
+        radius: f64,
+        near: &mut BinaryHeap<Neighbor>,
+        nodes: &mut BinaryHeap<Prospect<'a>>,
+    ) {
+        for &index in &node.data {
+            if !self.is_removed(index) {
+                let dist = (ctx.dist_fn)(data, &self.items[index]);
+                let neighbor = Neighbor {
+                    dist,
+                    index,
+                    is_pivot: false,
+                };
+                self.insert_neighbor_k(near, k, radius, data, neighbor);
+            }
+        }
+        if node.children.is_empty() {
+            return;
+        }
+
+        let mut dists = vec![f64::INFINITY; node.children.len()];
+        let mut pruned = vec![false; node.children.len()];
+        for (i, child) in node.children.iter().enumerate() {
+            if pruned[i] {
+                continue;
+            }
+            dists[i] = (ctx.dist_fn)(data, &self.items[child.pivot]);
+            if !self.is_removed(child.pivot) {
+                let neighbor = Neighbor {
+                    dist: dists[i],
+                    index: child.pivot,
+                    is_pivot: true,
+                };
+                self.insert_neighbor_k(near, k, radius, data, neighbor);
+            }
+            let worst = search_bound(near, k, radius);
+            if worst.is_finite() {
+                prune_siblings(child, i, dists[i], worst, &mut pruned);
+            }
+        }
+
+        let worst = search_bound(near, k, radius);
+        for (i, child) in node.children.iter().enumerate() {
+            if !pruned[i]
+                && (worst.is_infinite()
+                    || (dists[i] - worst <= child.max_radius
+                        && dists[i] + worst >= child.min_radius))
+            {
+                nodes.push(Prospect {
+                    node: child,
+                    dist_to_pivot: dists[i],
+                });
+            }
+        }
+    }
+
+    /// The neighbors of `data` within distance `radius`, sorted by distance.
+    fn nearest_r_internal(&self, data: &T, radius: f64) -> Vec<Neighbor> {
+        let Some(tree) = &self.tree else {
+            return Vec::new();
+        };
+        let ctx = self.context();
+        let mut near = Vec::new();
+        let mut nodes = vec![tree];
+
+        let dist = (ctx.dist_fn)(data, &self.items[tree.pivot]);
+        if dist <= radius && !self.is_removed(tree.pivot) {
+            near.push(Neighbor {
+                dist,
+                index: tree.pivot,
+                is_pivot: true,
+            });
+        }
+
+        while let Some(node) = nodes.pop() {
+            for &index in &node.data {
+                if !self.is_removed(index) {
+                    let dist = (ctx.dist_fn)(data, &self.items[index]);
+                    if dist <= radius {
+                        near.push(Neighbor {
+                            dist,
+                            index,
+                            is_pivot: false,
+                        });
+                    }
+                }
+            }
+
+            let mut dists = vec![f64::INFINITY; node.children.len()];
+            let mut pruned = vec![false; node.children.len()];
+            for (i, child) in node.children.iter().enumerate() {
+                if pruned[i] {
+                    continue;
+                }
+                dists[i] = (ctx.dist_fn)(data, &self.items[child.pivot]);
+                if dists[i] <= radius && !self.is_removed(child.pivot) {
+                    near.push(Neighbor {
+                        dist: dists[i],
+                        index: child.pivot,
+                        is_pivot: true,
+                    });
+                }
+                prune_siblings(child, i, dists[i], radius, &mut pruned);
+            }
+
+            for (i, child) in node.children.iter().enumerate() {
+                if !pruned[i]
+                    && dists[i] - radius <= child.max_radius
+                    && dists[i] + radius >= child.min_radius
+                {
+                    nodes.push(child);
+                }
+            }
+        }
+
+        near.sort();
+        near
+    }
+}
+
+/// The distance beyond which elements cannot be among the `k` nearest neighbors within
+/// `radius`, given the neighbors found so far.
+fn search_bound(near: &BinaryHeap<Neighbor>, k: usize, radius: f64) -> f64 {
+    if near.len() < k {
+        radius
+    } else {
+        near.peek().unwrap().dist.min(radius)
+    }
+}
+
+/// Prune the siblings of the `i`-th child whose elements are all further than `radius` from
+/// the query, the query being at distance `dist` from the pivot of the child.
+fn prune_siblings(child: &Node, i: usize, dist: f64, radius: f64, pruned: &mut [bool]) {
+    for (j, pruned) in pruned.iter_mut().enumerate() {
+        if i != j && (dist - radius > child.max_range[j] || dist + radius < child.min_range[j]) {
+            *pruned = true;
+        }
+    }
+}
+
+impl<T, D> NearestNeighbors<T, D> for Gnat<T, D>
+where
+    T: Clone + PartialEq,
+    D: Fn(&T, &T) -> f64,
+{
+    fn set_distance_function(&mut self, dist_fn: D) {
+        Gnat::set_distance_function(self, dist_fn);
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.dist_fn.as_ref().map(|f| f as DistanceFunctionRef<T>)
+    }
+
+    fn add(&mut self, data: T) {
+        Gnat::add(self, data);
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        Gnat::add_multiple(self, data);
+    }
+
+    fn remove(&mut self, data: &T) -> bool {
+        Gnat::remove(self, data)
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        Gnat::nearest(self, data)
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        Gnat::nearest_k(self, data, k)
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        Gnat::nearest_r(self, data, radius)
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        Gnat::nearest_k_into(self, data, k, out);
+    }
+
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        Gnat::nearest_r_into(self, data, radius, out);
+    }
+
+    fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        Gnat::nearest_k_within_into(self, data, k, radius, out);
+    }
+
+    fn clear(&mut self) {
+        Gnat::clear(self);
+    }
+
+    fn size(&self) -> usize {
+        Gnat::size(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[allow(clippy::ptr_arg)]
+    fn euclidean(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn brute_force_k(points: &[Vec<f64>], query: &Vec<f64>, k: usize) -> Vec<f64> {
+        let mut dists: Vec<f64> = points.iter().map(|p| euclidean(p, query)).collect();
+        dists.sort_by(f64::total_cmp);
+        dists.truncate(k);
+        dists
+    }
+
+    #[test]
+    fn test_gnat_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut random_point = || {
+            (0..3)
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect::<Vec<f64>>()
+        };
+        let mut points: Vec<Vec<f64>> = (0..600).map(|_| random_point()).collect();
+
+        let mut gnat = NearestNeighborsGNATNoThreadSafety::new();
+        gnat.set_max_num_pts_per_leaf(10);
+        NearestNeighbors::set_distance_function(&mut gnat, Box::new(euclidean));
+        gnat.add_multiple(points[..300].to_vec());
+        for p in &points[300..] {
+            gnat.add(p.clone());
+        }
+        assert_eq!(gnat.size(), 600);
+
+        // remove some of the points, including pivots
+        for p in points.drain(..150).step_by(2) {
+            assert!(gnat.remove(&p));
+            assert!(!gnat.remove(&p));
+        }
+        assert_eq!(gnat.size(), 525);
+
+        let remaining: Vec<Vec<f64>> = gnat.list();
+        for _ in 0..50 {
+            let query = random_point();
+            let found: Vec<f64> = gnat
+                .nearest_k(&query, 7)
+                .iter()
+                .map(|p| euclidean(p, &query))
+                .collect();
+            assert_eq!(found, brute_force_k(&remaining, &query, 7));
+
+            let within: Vec<f64> = gnat
+                .nearest_r(&query, 0.4)
+                .iter()
+                .map(|p| euclidean(p, &query))
+                .collect();
+            let expected: Vec<f64> = brute_force_k(&remaining, &query, remaining.len())
+                .into_iter()
+                .filter(|&d| d <= 0.4)
+                .collect();
+            assert_eq!(within, expected);
+        }
+
+        // the thread-safe variant can be shared between threads
+        let mut shared = NearestNeighborsGNAT::new();
+        shared.set_distance_function(Box::new(euclidean));
+        shared.add_multiple(remaining.clone());
+        let query = vec![0.0; 3];
+        let nearest = std::thread::scope(|scope| {
+            let shared = &shared;
+            let query = &query;
+            let handles: Vec<_> = (0..2)
+                .map(|_| scope.spawn(move || shared.nearest(query)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap().map(|p| euclidean(&p, query)))
+                .collect::<Vec<_>>()
+        });
+        let expected = brute_force_k(&remaining, &query, 1).pop();
+        assert_eq!(nearest, vec![expected, expected]);
+    }
+}
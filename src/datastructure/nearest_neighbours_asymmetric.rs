@@ -0,0 +1,237 @@
+use super::nearest_neighbours::{DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors};
+use super::nearest_neighbours_kd_tree::{DistanceFunctionMetric, VpAvl, VpTreeObject};
+
+/// The direction in which the distance between the query and the elements is measured, which
+/// matters when the distance is not symmetric (see `StateSpace::has_symmetric_distance`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueryDirection {
+    /// Elements are ranked by the distance from them to the query, e.g. the states of a tree
+    /// that is extended towards a sample.
+    #[default]
+    ToQuery,
+    /// Elements are ranked by the distance from the query to them, e.g. the states a new state
+    /// can be connected to, or the states of a tree grown backwards from the goal.
+    FromQuery,
+}
+
+/// Nearest neighbors for asymmetric (quasi-metric) distances, such as the length of the
+/// shortest path of a car that cannot turn on the spot.
+///
+/// The elements are indexed by a symmetric metric that is a lower bound on the distance in both
+/// directions (e.g. the Euclidean distance between the positions of a car). Queries visit the
+/// elements by increasing lower bound, rank them by the true distance measured in the query
+/// direction, and stop as soon as the lower bound exceeds the distance of the worst neighbor
+/// kept. Queries are exact as long as the lower bound holds.
+pub struct NearestNeighborsAsymmetric<T> {
+    tree: VpAvl<T, DistanceFunctionMetric<T>>,
+    dist_fn: Option<LocalDistanceFunction<T>>,
+    direction: QueryDirection,
+}
+
+impl<T> NearestNeighborsAsymmetric<T>
+where
+    T: VpTreeObject<PointType = T> + Clone + PartialEq,
+{
+    /// Create the data structure for the given lower bound. The (asymmetric) distance function
+    /// still needs to be set with `NearestNeighbors::set_distance_function`.
+    pub fn new(lower_bound: LocalDistanceFunction<T>) -> Self {
+        Self {
+            tree: VpAvl::new(DistanceFunctionMetric::new(lower_bound)),
+            dist_fn: None,
+            direction: QueryDirection::default(),
+        }
+    }
+
+    /// Set the symmetric lower bound on the distance, re-indexing the elements.
+    pub fn set_lower_bound(&mut self, lower_bound: LocalDistanceFunction<T>) {
+        self.tree.set_distance_function(lower_bound);
+    }
+
+    /// Set the direction used by the `NearestNeighbors` queries.
+    pub fn set_query_direction(&mut self, direction: QueryDirection) {
+        self.direction = direction;
+    }
+
+    pub fn get_query_direction(&self) -> QueryDirection {
+        self.direction
+    }
+
+    /// Write the `k` nearest elements within distance `radius` of `data`, measured in the given
+    /// direction, and their distances to `out`, from the nearest to the furthest.
+    pub fn nearest_k_within_directed_into(
+        &self,
+        data: &T,
+        k: usize,
+        radius: f64,
+        direction: QueryDirection,
+        out: &mut Vec<(T, f64)>,
+    ) {
+        out.clear();
+        if k == 0 {
+            return;
+        }
+        for (element, lower_bound) in self.tree.nn_dist_iter(data) {
+            let bound = if out.len() == k {
+                out[k - 1].1.min(radius)
+            } else {
+                radius
+            };
+            if lower_bound > bound {
+                break;
+            }
+            let dist = match direction {
+                QueryDirection::ToQuery => self.distance(element, data),
+                QueryDirection::FromQuery => self.distance(data, element),
+            };
+            if dist <= bound && (out.len() < k || dist < out[k - 1].1) {
+                let position = out.partition_point(|(_, d)| *d <= dist);
+                out.insert(position, (element.clone(), dist));
+                out.truncate(k);
+            }
+        }
+    }
+
+    /// The `k` nearest elements of `data`, measured in the given direction, from the nearest
+    /// to the furthest.
+    pub fn nearest_k_directed(&self, data: &T, k: usize, direction: QueryDirection) -> Vec<T> {
+        let mut out = Vec::new();
+        self.nearest_k_within_directed_into(data, k, f64::INFINITY, direction, &mut out);
+        out.into_iter().map(|(d, _)| d).collect()
+    }
+
+    fn distance(&self, a: &T, b: &T) -> f64 {
+        let dist_fn = self
+            .dist_fn
+            .as_ref()
+            .expect("The distance function must be set before using the nearest neighbors");
+        dist_fn(a, b)
+    }
+}
+
+impl<T> NearestNeighbors<T> for NearestNeighborsAsymmetric<T>
+where
+    T: VpTreeObject<PointType = T> + Clone + PartialEq,
+{
+    /// Set the true, possibly asymmetric, distance used to rank the neighbors.
+    fn set_distance_function(&mut self, dist_fn: LocalDistanceFunction<T>) {
+        self.dist_fn = Some(dist_fn);
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.dist_fn.as_deref()
+    }
+
+    fn add(&mut self, data: T) {
+        self.tree.add(data);
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        self.tree.add_multiple(data);
+    }
+
+    fn remove(&mut self, data: &T) -> bool {
+        self.tree.remove(data).is_some()
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        self.nearest_k(data, 1).pop()
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        self.nearest_k_directed(data, k, self.direction)
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.nearest_r_with_distances(data, radius)
+            .into_iter()
+            .map(|(d, _)| d)
+            .collect()
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        self.nearest_k_within_directed_into(data, k, f64::INFINITY, self.direction, out);
+    }
+
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        self.nearest_k_within_directed_into(data, usize::MAX, radius, self.direction, out);
+    }
+
+    fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        self.nearest_k_within_directed_into(data, k, radius, self.direction, out);
+    }
+
+    fn clear(&mut self) {
+        self.tree.clear();
+    }
+
+    fn size(&self) -> usize {
+        self.tree.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastructure::nearest_neighbours_linear::NearestNeighborsLinear;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[allow(clippy::ptr_arg)]
+    fn euclidean(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Moving towards negative x costs three times as much.
+    #[allow(clippy::ptr_arg)]
+    fn one_way(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
+        euclidean(a, b) + 2.0 * (a[0] - b[0]).max(0.0)
+    }
+
+    #[test]
+    fn test_asymmetric_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let points: Vec<Vec<f64>> = (0..300)
+            .map(|_| vec![rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)])
+            .collect();
+
+        let mut nn = NearestNeighborsAsymmetric::new(Box::new(euclidean));
+        nn.set_distance_function(Box::new(one_way));
+        nn.add_multiple(points.clone());
+        for p in points.iter().step_by(4) {
+            assert!(nn.remove(p));
+        }
+
+        // brute force in each direction, `NearestNeighborsLinear` measuring from the query
+        let kept: Vec<Vec<f64>> = (0..points.len())
+            .filter(|i| i % 4 != 0)
+            .map(|i| points[i].clone())
+            .collect();
+        let mut to_query = NearestNeighborsLinear::new();
+        to_query.set_distance_function(Box::new(|q: &Vec<f64>, e: &Vec<f64>| one_way(e, q)));
+        to_query.add_multiple(kept.clone());
+        let mut from_query = NearestNeighborsLinear::new();
+        from_query.set_distance_function(Box::new(one_way));
+        from_query.add_multiple(kept);
+
+        let mut out = Vec::new();
+        for _ in 0..30 {
+            let query = vec![rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)];
+            for (direction, linear) in [
+                (QueryDirection::ToQuery, &to_query),
+                (QueryDirection::FromQuery, &from_query),
+            ] {
+                nn.set_query_direction(direction);
+                assert_eq!(nn.nearest_k(&query, 8), linear.nearest_k(&query, 8));
+                nn.nearest_r_into(&query, 0.4, &mut out);
+                assert_eq!(out, linear.nearest_r_with_distances(&query, 0.4));
+                nn.nearest_k_within_into(&query, 3, 0.3, &mut out);
+                assert_eq!(out, linear.nearest_k_within(&query, 3, 0.3));
+            }
+        }
+        assert_eq!(nn.get_query_direction(), QueryDirection::FromQuery);
+        assert_eq!(nn.size(), 225);
+    }
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, RwLock};
+
+use super::nearest_neighbours::{DistanceFunction, NearestNeighbors};
+
+/// A nearest neighbors data structure that `ConcurrentNearestNeighbors` can share between
+/// threads: any data structure taking a `DistanceFunction` that is `Send + Sync`, such as
+/// `NearestNeighborsLinear`, `VpAvl` or `NearestNeighborsGNAT` given a `DistanceFunction`.
+pub trait ConcurrentBackend<T>: NearestNeighbors<T, DistanceFunction<T>> + Send + Sync {}
+
+impl<T, B> ConcurrentBackend<T> for B where B: NearestNeighbors<T, DistanceFunction<T>> + Send + Sync
+{}
+
+type SharedDistanceFunction<T> = Arc<dyn Fn(&T, &T) -> f64 + Send + Sync>;
+
+/// Nearest neighbors that several threads can insert into and query at the same time, through
+/// a shared reference (e.g. an `Arc<ConcurrentNearestNeighbors<..>>`).
+///
+/// The elements are indexed by a backend behind a read-write lock, so that queries run in
+/// parallel. Single insertions are buffered, and the buffer is moved into the backend, under
+/// the write lock, once it holds `batch_size` elements; queries check the buffer by brute
+/// force, so buffered elements are found right away.
+///
+/// Everything here is `Send + Sync`, which rules out distance functions calling into an
+/// `Rc<dyn StateSpace>`: the elements are rather copies of the coordinates of the states (see
+/// `StateSpace::copy_to_reals`), or ids whose distance does not go through the space. This
+/// suits the parallel planners, whose worker threads grow one shared tree or roadmap (parallel
+/// RRT, PRM with parallel roadmap construction). Single-threaded planners are better served by
+/// `get_default_nearest_neighbors`, which avoids the locking.
+pub struct ConcurrentNearestNeighbors<T, B> {
+    index: RwLock<B>,
+    pending: RwLock<Vec<T>>,
+    dist_fn: Option<SharedDistanceFunction<T>>,
+    batch_size: usize,
+}
+
+impl<T, B> ConcurrentNearestNeighbors<T, B>
+where
+    T: Clone + PartialEq + Send + Sync + 'static,
+    B: ConcurrentBackend<T>,
+{
+    /// Wrap the given (empty) backend. The distance function still needs to be set with
+    /// `set_distance_function`.
+    pub fn new(backend: B) -> Self {
+        Self {
+            index: RwLock::new(backend),
+            pending: RwLock::new(Vec::new()),
+            dist_fn: None,
+            batch_size: 64,
+        }
+    }
+
+    /// Set the distance function, used both by the backend and for the buffered elements.
+    pub fn set_distance_function(&mut self, dist_fn: DistanceFunction<T>) {
+        let dist_fn: SharedDistanceFunction<T> = Arc::from(dist_fn);
+        let backend_dist_fn = dist_fn.clone();
+        self.index
+            .get_mut()
+            .unwrap()
+            .set_distance_function(Box::new(move |a, b| backend_dist_fn(a, b)));
+        self.dist_fn = Some(dist_fn);
+    }
+
+    /// Set the number of buffered insertions that triggers moving the buffer into the
+    /// backend. Larger batches take the write lock less often but make queries scan more
+    /// elements by brute force.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        if batch_size == 0 {
+            panic!("The batch size must be at least 1");
+        }
+        self.batch_size = batch_size;
+    }
+
+    pub fn get_batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Add an element, buffered until the batch is full.
+    pub fn add(&self, data: T) {
+        let mut pending = self.pending.write().unwrap();
+        pending.push(data);
+        if pending.len() >= self.batch_size {
+            let batch = std::mem::take(&mut *pending);
+            self.index.write().unwrap().add_multiple(batch);
+        }
+    }
+
+    /// Add elements to the backend directly, together with the buffered ones.
+    pub fn add_multiple(&self, data: Vec<T>) {
+        let mut pending = self.pending.write().unwrap();
+        let mut batch = std::mem::take(&mut *pending);
+        batch.extend(data);
+        self.index.write().unwrap().add_multiple(batch);
+    }
+
+    /// Move the buffered elements into the backend.
+    pub fn flush(&self) {
+        self.add_multiple(Vec::new());
+    }
+
+    pub fn remove(&self, data: &T) -> bool {
+        let mut pending = self.pending.write().unwrap();
+        if let Some(i) = pending.iter().position(|d| d == data) {
+            pending.swap_remove(i);
+            return true;
+        }
+        self.index.write().unwrap().remove(data)
+    }
+
+    pub fn nearest(&self, data: &T) -> Option<T> {
+        self.nearest_k(data, 1).pop()
+    }
+
+    /// The `k` nearest elements, from the nearest to the furthest.
+    pub fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        let mut out = Vec::new();
+        self.nearest_k_within_into(data, k, f64::INFINITY, &mut out);
+        out.into_iter().map(|(d, _)| d).collect()
+    }
+
+    /// The elements within distance `radius`, from the nearest to the furthest.
+    pub fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        let mut out = Vec::new();
+        self.nearest_k_within_into(data, usize::MAX, radius, &mut out);
+        out.into_iter().map(|(d, _)| d).collect()
+    }
+
+    /// Write the `k` nearest elements within distance `radius` and their distances to `out`,
+    /// from the nearest to the furthest.
+    pub fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        let dist_fn = self
+            .dist_fn
+            .as_ref()
+            .expect("The distance function must be set before using the nearest neighbors");
+        // the buffer is locked first, like when it is moved into the backend, so that no
+        // element is missed in between
+        let pending = self.pending.read().unwrap();
+        self.index
+            .read()
+            .unwrap()
+            .nearest_k_within_into(data, k, radius, out);
+        let buffered = pending
+            .iter()
+            .map(|d| (d.clone(), dist_fn(data, d)))
+            .filter(|(_, dist)| *dist <= radius);
+        out.extend(buffered);
+        out.sort_by(|a, b| a.1.total_cmp(&b.1));
+        out.truncate(k);
+    }
+
+    pub fn clear(&self) {
+        let mut pending = self.pending.write().unwrap();
+        pending.clear();
+        self.index.write().unwrap().clear();
+    }
+
+    pub fn size(&self) -> usize {
+        let pending = self.pending.read().unwrap();
+        pending.len() + self.index.read().unwrap().size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastructure::nearest_neighbours::NearestNeighbors;
+    use crate::datastructure::nearest_neighbours_GNANT_no_therad_safety::NearestNeighborsGNAT;
+    use crate::datastructure::nearest_neighbours_kd_tree::{DistanceFunctionMetric, VpAvl};
+    use crate::datastructure::nearest_neighbours_linear::NearestNeighborsLinear;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::thread;
+
+    #[allow(clippy::ptr_arg)]
+    fn euclidean(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn check_concurrent<B: ConcurrentBackend<Vec<f64>> + 'static>(backend: B) {
+        let mut nn = ConcurrentNearestNeighbors::new(backend);
+        nn.set_distance_function(Box::new(euclidean));
+        nn.set_batch_size(16);
+        let nn = Arc::new(nn);
+
+        // each thread inserts its points and queries the ones inserted so far
+        let workers: Vec<_> = (0..4)
+            .map(|t| {
+                let nn = nn.clone();
+                thread::spawn(move || {
+                    let mut rng = StdRng::seed_from_u64(t);
+                    let mut points = Vec::new();
+                    for _ in 0..100 {
+                        let p: Vec<f64> = (0..3).map(|_| rng.gen_range(-1.0..1.0)).collect();
+                        nn.add(p.clone());
+                        assert_eq!(nn.nearest(&p), Some(p.clone()));
+                        points.push(p);
+                    }
+                    points
+                })
+            })
+            .collect();
+        let points: Vec<Vec<f64>> = workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect();
+        assert_eq!(nn.size(), 400);
+
+        let mut linear = NearestNeighborsLinear::new();
+        NearestNeighbors::set_distance_function(&mut linear, Box::new(euclidean));
+        NearestNeighbors::add_multiple(&mut linear, points.clone());
+        for p in points.iter().step_by(5) {
+            assert!(nn.remove(p));
+            assert!(NearestNeighbors::remove(&mut linear, p));
+        }
+        let mut out = Vec::new();
+        for query in points.iter().take(20) {
+            assert_eq!(nn.nearest_k(query, 6), linear.nearest_k(query, 6));
+            assert_eq!(nn.nearest_r(query, 0.3), linear.nearest_r(query, 0.3));
+            nn.nearest_k_within_into(query, 4, 0.4, &mut out);
+            assert_eq!(out, linear.nearest_k_within(query, 4, 0.4));
+        }
+        nn.flush();
+        assert_eq!(nn.size(), 320);
+        nn.clear();
+        assert_eq!(nn.size(), 0);
+    }
+
+    #[test]
+    fn test_concurrent_nearest_neighbors() {
+        check_concurrent(NearestNeighborsLinear::<Vec<f64>, DistanceFunction<_>>::default());
+        check_concurrent(VpAvl::new(DistanceFunctionMetric::<
+            Vec<f64>,
+            DistanceFunction<_>,
+        >::default()));
+        check_concurrent(NearestNeighborsGNAT::<Vec<f64>>::default());
+    }
+}
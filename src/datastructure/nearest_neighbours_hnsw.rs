@@ -0,0 +1,481 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::nearest_neighbours::{DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors};
+
+/// Approximate nearest neighbors with a Hierarchical Navigable Small World graph, after Malkov
+/// and Yashunin, "Efficient and robust approximate nearest neighbor search using Hierarchical
+/// Navigable Small World graphs", TPAMI 2018.
+///
+/// Every element is linked to about `m` of its neighbors on each of a random number of layers,
+/// higher layers holding exponentially fewer elements. Queries greedily descend the layers and
+/// explore the bottom one with a beam of width `ef`, so they only need the distance function
+/// and scale to high dimensional spaces. `nearest`, `nearest_k` and `nearest_r` may miss some
+/// neighbors; larger values of `ef` and `m` trade speed for recall (see `measure_recall`).
+///
+/// Removed elements are kept in the graph to route queries, but never returned, until they make
+/// up half of the graph and it is rebuilt.
+pub struct NearestNeighborsHNSW<T> {
+    m: usize,
+    ef_construction: usize,
+    ef: usize,
+    items: Vec<T>,
+    // links of every element on every layer it belongs to
+    links: Vec<Vec<Vec<usize>>>,
+    removed: Vec<bool>,
+    size: usize,
+    entry_point: Option<usize>,
+    rng: StdRng,
+    dist_fn: Option<LocalDistanceFunction<T>>,
+}
+
+/// An element at some distance from a query.
+#[derive(Clone, Copy)]
+struct Neighbor {
+    dist: f64,
+    index: usize,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist)
+    }
+}
+
+impl<T> Default for NearestNeighborsHNSW<T> {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef: 50,
+            items: Vec::new(),
+            links: Vec::new(),
+            removed: Vec::new(),
+            size: 0,
+            entry_point: None,
+            rng: StdRng::seed_from_u64(0),
+            dist_fn: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> NearestNeighborsHNSW<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the number of links of every element on the upper layers; elements have twice as
+    /// many links on the bottom layer. Only affects elements added afterwards.
+    pub fn set_m(&mut self, m: usize) {
+        if m < 2 {
+            panic!("Elements need at least two links");
+        }
+        self.m = m;
+    }
+
+    pub fn get_m(&self) -> usize {
+        self.m
+    }
+
+    /// Set the width of the beam used to find the neighbors of added elements.
+    pub fn set_ef_construction(&mut self, ef_construction: usize) {
+        if ef_construction == 0 {
+            panic!("The beam width must be strictly positive");
+        }
+        self.ef_construction = ef_construction;
+    }
+
+    pub fn get_ef_construction(&self) -> usize {
+        self.ef_construction
+    }
+
+    /// Set the width of the beam used by queries. `nearest_k` uses at least `k`.
+    pub fn set_ef(&mut self, ef: usize) {
+        if ef == 0 {
+            panic!("The beam width must be strictly positive");
+        }
+        self.ef = ef;
+    }
+
+    pub fn get_ef(&self) -> usize {
+        self.ef
+    }
+
+    /// Seed the generator drawing the layers of added elements.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn distance(&self, a: &T, b: &T) -> f64 {
+        let dist_fn = self
+            .dist_fn
+            .as_ref()
+            .expect("The distance function must be set before using the nearest neighbors");
+        dist_fn(a, b)
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            2 * self.m
+        } else {
+            self.m
+        }
+    }
+
+    fn random_level(&mut self) -> usize {
+        // levels follow a geometric distribution, each layer holding 1/m of the one below
+        let u: f64 = 1.0 - self.rng.gen::<f64>();
+        (-u.ln() / (self.m as f64).ln()).floor() as usize
+    }
+
+    /// Explore `layer` from the entry points, keeping the `ef` elements nearest to `query`.
+    /// Returns them sorted by distance.
+    fn search_layer(
+        &self,
+        query: &T,
+        entry: &[Neighbor],
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Neighbor> {
+        let mut visited: HashSet<usize> = entry.iter().map(|n| n.index).collect();
+        let mut candidates: BinaryHeap<Reverse<Neighbor>> =
+            entry.iter().map(|&n| Reverse(n)).collect();
+        let mut found: BinaryHeap<Neighbor> = entry.iter().copied().collect();
+        while found.len() > ef {
+            found.pop();
+        }
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            if found.len() >= ef && candidate.dist > found.peek().unwrap().dist {
+                break;
+            }
+            for &e in &self.links[candidate.index][layer] {
+                if !visited.insert(e) {
+                    continue;
+                }
+                let dist = self.distance(query, &self.items[e]);
+                if found.len() < ef || dist < found.peek().unwrap().dist {
+                    let neighbor = Neighbor { dist, index: e };
+                    candidates.push(Reverse(neighbor));
+                    found.push(neighbor);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    /// Descend greedily through the layers above `down_to`, returning the entry point of layer
+    /// `down_to`.
+    fn descend(&self, query: &T, down_to: usize) -> Vec<Neighbor> {
+        let Some(entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+        let mut entry = vec![Neighbor {
+            dist: self.distance(query, &self.items[entry_point]),
+            index: entry_point,
+        }];
+        for layer in (down_to + 1..self.links[entry_point].len()).rev() {
+            entry = self.search_layer(query, &entry, 1, layer);
+        }
+        entry
+    }
+
+    /// Choose up to `m` of the candidates (sorted by distance to the element they would be
+    /// linked to), preferring candidates closer to that element than to the ones already
+    /// chosen, so that links point in diverse directions.
+    fn select_neighbors(&self, candidates: &[Neighbor], m: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(m);
+        let mut pruned = Vec::new();
+        for c in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            let diverse = selected
+                .iter()
+                .all(|&s| self.distance(&self.items[c.index], &self.items[s]) > c.dist);
+            if diverse {
+                selected.push(c.index);
+            } else {
+                pruned.push(c.index);
+            }
+        }
+        let missing = m - selected.len();
+        selected.extend(pruned.into_iter().take(missing));
+        selected
+    }
+
+    fn insert(&mut self, index: usize) {
+        let level = self.random_level();
+        self.links.push(vec![Vec::new(); level + 1]);
+
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(index);
+            return;
+        };
+        let top = self.links[entry_point].len() - 1;
+        let query = self.items[index].clone();
+
+        let mut entry = self.descend(&query, level);
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, &entry, self.ef_construction, layer);
+            let neighbors = self.select_neighbors(&found, self.m);
+            for &n in &neighbors {
+                self.links[n][layer].push(index);
+                if self.links[n][layer].len() > self.max_links(layer) {
+                    self.shrink_links(n, layer);
+                }
+            }
+            self.links[index][layer] = neighbors;
+            entry = found;
+        }
+
+        if level > top {
+            self.entry_point = Some(index);
+        }
+    }
+
+    fn shrink_links(&mut self, index: usize, layer: usize) {
+        let mut candidates: Vec<Neighbor> = self.links[index][layer]
+            .iter()
+            .map(|&n| Neighbor {
+                dist: self.distance(&self.items[index], &self.items[n]),
+                index: n,
+            })
+            .collect();
+        candidates.sort();
+        self.links[index][layer] = self.select_neighbors(&candidates, self.max_links(layer));
+    }
+
+    fn rebuild(&mut self) {
+        let items: Vec<T> = (0..self.items.len())
+            .filter(|&i| !self.removed[i])
+            .map(|i| self.items[i].clone())
+            .collect();
+        self.clear();
+        self.add_multiple(items);
+    }
+
+    /// The elements nearest to `data` found with a beam of `ef` elements that have not been
+    /// removed, sorted by distance. The beam is widened by the number of removed elements it
+    /// holds until it holds enough others.
+    fn search(&self, data: &T, ef: usize) -> Vec<Neighbor> {
+        let entry = self.descend(data, 0);
+        if entry.is_empty() {
+            return entry;
+        }
+        let wanted = ef.min(self.size);
+        let mut width = ef;
+        loop {
+            let found = self.search_layer(data, &entry, width, 0);
+            let removed = found.iter().filter(|n| self.removed[n.index]).count();
+            if removed == 0 || found.len() - removed >= wanted || width >= self.items.len() {
+                return found
+                    .into_iter()
+                    .filter(|n| !self.removed[n.index])
+                    .collect();
+            }
+            width += removed;
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> NearestNeighbors<T> for NearestNeighborsHNSW<T> {
+    /// Set the distance function, rebuilding the graph if it is not empty.
+    fn set_distance_function(&mut self, dist_fn: LocalDistanceFunction<T>) {
+        self.dist_fn = Some(dist_fn);
+        if self.entry_point.is_some() {
+            self.rebuild();
+        }
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.dist_fn.as_deref()
+    }
+
+    fn add(&mut self, data: T) {
+        self.items.push(data);
+        self.removed.push(false);
+        self.size += 1;
+        self.insert(self.items.len() - 1);
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        for d in data {
+            self.add(d);
+        }
+    }
+
+    /// Remove an element equal to `data`, which takes time linear in the number of elements.
+    fn remove(&mut self, data: &T) -> bool {
+        let Some(index) =
+            (0..self.items.len()).find(|&i| !self.removed[i] && self.items[i] == *data)
+        else {
+            return false;
+        };
+        self.removed[index] = true;
+        self.size -= 1;
+        if 2 * self.size < self.items.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        self.nearest_k(data, 1).pop()
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        self.nearest_k_with_distances(data, k)
+            .into_iter()
+            .map(|(d, _)| d)
+            .collect()
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.nearest_r_with_distances(data, radius)
+            .into_iter()
+            .map(|(d, _)| d)
+            .collect()
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        if k == 0 {
+            return;
+        }
+        let found = self.search(data, self.ef.max(k));
+        out.extend(
+            found
+                .into_iter()
+                .take(k)
+                .map(|n| (self.items[n.index].clone(), n.dist)),
+        );
+    }
+
+    /// The elements within `radius` of `data`. The beam is widened until it reaches beyond the
+    /// radius, so that no more than the usual recall is lost.
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        let mut ef = self.ef;
+        let found = loop {
+            let found = self.search(data, ef);
+            let beyond = found.last().is_some_and(|n| n.dist > radius);
+            if beyond || ef >= self.items.len() {
+                break found;
+            }
+            ef *= 2;
+        };
+        out.clear();
+        out.extend(
+            found
+                .into_iter()
+                .take_while(|n| n.dist <= radius)
+                .map(|n| (self.items[n.index].clone(), n.dist)),
+        );
+    }
+
+    fn clear(&mut self) {
+        self.items.clear();
+        self.links.clear();
+        self.removed.clear();
+        self.size = 0;
+        self.entry_point = None;
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastructure::nearest_neighbours::measure_recall;
+    use crate::datastructure::nearest_neighbours_linear::NearestNeighborsLinear;
+
+    #[allow(clippy::ptr_arg)]
+    fn euclidean(a: &Vec<f64>, b: &Vec<f64>) -> f64 {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    #[test]
+    fn test_hnsw_recall() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut random_point = || (0..20).map(|_| rng.gen::<f64>()).collect::<Vec<f64>>();
+        let points: Vec<Vec<f64>> = (0..800).map(|_| random_point()).collect();
+        let queries: Vec<Vec<f64>> = (0..50).map(|_| random_point()).collect();
+
+        let mut hnsw = NearestNeighborsHNSW::new();
+        hnsw.set_ef_construction(100);
+        hnsw.set_distance_function(Box::new(euclidean));
+        let mut linear = NearestNeighborsLinear::new();
+        linear.set_distance_function(Box::new(euclidean));
+        for p in &points {
+            hnsw.add(p.clone());
+            linear.add(p.clone());
+        }
+
+        let recall = measure_recall(&hnsw, &linear, &queries, 10);
+        assert!(recall > 0.9, "recall {}", recall);
+
+        // exact matches are always found
+        assert_eq!(hnsw.nearest(&points[42]), Some(points[42].clone()));
+        let within = hnsw.nearest_r(&points[42], 1.0);
+        assert_eq!(within.first(), Some(&points[42]));
+        assert!(within.iter().all(|p| euclidean(p, &points[42]) <= 1.0));
+
+        for p in &points[..500] {
+            assert!(hnsw.remove(p));
+            linear.remove(p);
+        }
+        assert_eq!(hnsw.size(), 300);
+        assert!(measure_recall(&hnsw, &linear, &queries, 10) > 0.9);
+    }
+
+    #[test]
+    fn test_hnsw_nearest_k_after_removals() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let points: Vec<Vec<f64>> = (0..60)
+            .map(|_| (0..3).map(|_| rng.gen::<f64>()).collect())
+            .collect();
+        let mut hnsw = NearestNeighborsHNSW::new();
+        hnsw.set_distance_function(Box::new(euclidean));
+        hnsw.add_multiple(points.clone());
+
+        // removing less than half of the elements keeps them in the graph
+        for p in &points[..29] {
+            assert!(hnsw.remove(p));
+        }
+        assert_eq!(hnsw.size(), 31);
+        for query in &points[..5] {
+            let found = hnsw.nearest_k(query, 30);
+            assert_eq!(found.len(), 30);
+            assert!(found.iter().all(|p| !points[..29].contains(p)));
+            assert_eq!(hnsw.nearest_k(query, 40).len(), 31);
+        }
+    }
+}
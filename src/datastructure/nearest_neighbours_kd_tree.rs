@@ -4,7 +4,12 @@
 /// feature removed.
 use std::{collections::BinaryHeap, marker::PhantomData, rc::Rc, vec};
 
-use crate::base::{state_allocator::StateId, statespace::StateSpace};
+use crate::base::{
+    state_allocator::{StateId, StateKey},
+    statespace::StateSpace,
+};
+
+use super::nearest_neighbours::{DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors};
 
 pub trait Metric {
     type PointType;
@@ -67,6 +72,9 @@ where
     }
 
     pub fn bulk_insert(metric: PointMetric, data: Vec<Point>) -> Self {
+        if data.is_empty() {
+            return Self::new(metric);
+        }
         let indices: Vec<usize> = (1..data.len()).collect();
         let nodes = (0..data.len())
             .map(|ind| Node::new_leaf(ind, None))
@@ -565,9 +573,9 @@ where
         let to_remove = self
             .nn_index_iter(value)
             .take_while(|nn| nn.1 <= 0.0)
-            .find(|nn| self.data[nn.0].location() == value);
+            .find(|nn| self.data[nn.0].location() == value)?;
 
-        Some(self.remove_index(to_remove.unwrap().0))
+        Some(self.remove_index(to_remove.0))
     }
 
     // TODO: DONT BE DUM
@@ -1091,6 +1099,143 @@ impl VpTreeObject for StateId {
     }
 }
 
+impl VpTreeObject for StateKey {
+    type PointType = Self;
+
+    fn location(&self) -> &Self::PointType {
+        self
+    }
+}
+
+/// A metric given by a `LocalDistanceFunction`, which lets a `VpAvl` be used through the
+/// `NearestNeighbors` trait. Computing a distance before the function is set panics.
+///
+/// With a `DistanceFunction`, the tree can be shared between threads (see
+/// `ConcurrentNearestNeighbors`).
+pub struct DistanceFunctionMetric<T, D = LocalDistanceFunction<T>> {
+    dist_fn: Option<D>,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T, D> DistanceFunctionMetric<T, D> {
+    pub fn new(dist_fn: D) -> Self {
+        Self {
+            dist_fn: Some(dist_fn),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, D> Default for DistanceFunctionMetric<T, D> {
+    fn default() -> Self {
+        Self {
+            dist_fn: None,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, D: Fn(&T, &T) -> f64> Metric for DistanceFunctionMetric<T, D> {
+    type PointType = T;
+
+    fn distance(&self, p1: &T, p2: &T) -> f64 {
+        let dist_fn = self
+            .dist_fn
+            .as_ref()
+            .expect("The distance function must be set before using the nearest neighbors");
+        dist_fn(p1, p2)
+    }
+}
+
+impl<T, D> NearestNeighbors<T, D> for VpAvl<T, DistanceFunctionMetric<T, D>>
+where
+    T: VpTreeObject<PointType = T> + Clone + PartialEq,
+    D: Fn(&T, &T) -> f64,
+{
+    /// Set the distance function, rebuilding the tree if it is not empty.
+    fn set_distance_function(&mut self, dist_fn: D) {
+        let data = std::mem::take(&mut self.data);
+        *self = VpAvl::bulk_insert(DistanceFunctionMetric::new(dist_fn), data);
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.metric
+            .dist_fn
+            .as_ref()
+            .map(|f| f as DistanceFunctionRef<T>)
+    }
+
+    fn add(&mut self, data: T) {
+        self.insert(data);
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        if self.data.is_empty() {
+            let metric = std::mem::take(&mut self.metric);
+            *self = VpAvl::bulk_insert(metric, data);
+        } else {
+            for d in data {
+                self.insert(d);
+            }
+        }
+    }
+
+    fn remove(&mut self, data: &T) -> bool {
+        VpAvl::remove(self, data).is_some()
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        self.nn_iter(data).next().cloned()
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        self.nn_iter(data).take(k).cloned().collect()
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.nn_dist_iter(data)
+            .take_while(|(_, d)| *d <= radius)
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        out.extend(self.nn_dist_iter(data).take(k).map(|(p, d)| (p.clone(), d)));
+    }
+
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        out.extend(
+            self.nn_dist_iter(data)
+                .take_while(|(_, d)| *d <= radius)
+                .map(|(p, d)| (p.clone(), d)),
+        );
+    }
+
+    /// The iterator yields neighbors lazily, so the search stops at the first neighbor beyond
+    /// `radius`.
+    fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        out.extend(
+            self.nn_dist_iter(data)
+                .take(k)
+                .take_while(|(_, d)| *d <= radius)
+                .map(|(p, d)| (p.clone(), d)),
+        );
+    }
+
+    fn clear(&mut self) {
+        self.root = 0;
+        self.nodes.clear();
+        self.data.clear();
+    }
+
+    fn size(&self) -> usize {
+        VpAvl::size(self)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -1146,6 +1291,55 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_nearest_neighbors_trait() {
+        use crate::datastructure::nearest_neighbours::state_space_distance_function;
+
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 10.0);
+        let space = Rc::new(space);
+        let state = |x: f64| {
+            space
+                .alloc_arena_state_with_value(RealVectorState {
+                    values: DVector::from_vec(vec![x]),
+                })
+                .key()
+        };
+        let value = |s: &StateKey| s.with_id(|s| space.with_state(s, |s| s.values[0]));
+
+        let mut nn: Box<dyn NearestNeighbors<StateKey>> =
+            Box::new(VpAvl::new(DistanceFunctionMetric::default()));
+        nn.set_distance_function(state_space_distance_function(space.clone()));
+        let states: Vec<StateKey> = (0..10).map(|i| state(i as f64)).collect();
+        nn.add_multiple(states[..5].to_vec());
+        for s in &states[5..] {
+            nn.add(*s);
+        }
+        assert_eq!(nn.size(), 10);
+
+        let query = state(3.2);
+        assert_eq!(nn.nearest(&query).map(|s| value(&s)), Some(3.0));
+        let k: Vec<f64> = nn.nearest_k(&query, 3).iter().map(value).collect();
+        assert_eq!(k, vec![3.0, 4.0, 2.0]);
+        let r: Vec<f64> = nn.nearest_r(&query, 1.5).iter().map(value).collect();
+        assert_eq!(r, vec![3.0, 4.0, 2.0]);
+
+        assert!(nn.remove(&states[3]));
+        assert!(!nn.remove(&states[3]));
+        assert_eq!(nn.nearest(&query).map(|s| value(&s)), Some(4.0));
+
+        // doubling the distances halves the neighborhoods
+        let distance = state_space_distance_function(space.clone());
+        nn.set_distance_function(Box::new(move |a, b| 2.0 * distance(a, b)));
+        assert_eq!(nn.size(), 9);
+        let r: Vec<f64> = nn.nearest_r(&query, 2.0).iter().map(value).collect();
+        assert_eq!(r, vec![4.0]);
+
+        nn.clear();
+        assert_eq!(nn.size(), 0);
+        assert!(nn.nearest(&query).is_none());
+    }
+
     #[test]
     fn test_distance() {
         let mut tree = VpAvl::new(EuclideanMetric::default());
@@ -0,0 +1,276 @@
+use std::cell::Cell;
+
+use super::nearest_neighbours::{DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors};
+
+/// Nearest neighbors by brute force: every query computes the distance to every element.
+///
+/// Queries are exact and the distance function needs no property at all, which makes this the
+/// reference implementation for testing the other data structures. With a
+/// `DistanceFunction`, it can be shared between threads (see
+/// `ConcurrentNearestNeighbors`).
+pub struct NearestNeighborsLinear<T, D = LocalDistanceFunction<T>> {
+    data: Vec<T>,
+    dist_fn: Option<D>,
+}
+
+impl<T, D> Default for NearestNeighborsLinear<T, D> {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            dist_fn: None,
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> NearestNeighborsLinear<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T, D> NearestNeighborsLinear<T, D>
+where
+    T: Clone + PartialEq,
+    D: Fn(&T, &T) -> f64,
+{
+    pub fn set_distance_function(&mut self, dist_fn: D) {
+        self.dist_fn = Some(dist_fn);
+    }
+
+    /// All the elements, in insertion order (removals aside).
+    pub fn get_data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn add_multiple(&mut self, data: Vec<T>) {
+        self.data.extend(data);
+    }
+
+    pub fn remove(&mut self, data: &T) -> bool {
+        // search from the end, recently added elements being the most likely to be removed
+        match self.data.iter().rposition(|d| d == data) {
+            Some(i) => {
+                self.data.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write the `k` nearest elements within distance `radius` and their distances to `out`,
+    /// from the nearest to the furthest.
+    pub fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        out.clear();
+        out.extend(
+            self.sorted_distances(data)
+                .into_iter()
+                .take(k)
+                .take_while(|(dist, _)| *dist <= radius)
+                .map(|(dist, d)| (d.clone(), dist)),
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn distance(&self, a: &T, b: &T) -> f64 {
+        let dist_fn = self
+            .dist_fn
+            .as_ref()
+            .expect("The distance function must be set before using the nearest neighbors");
+        dist_fn(a, b)
+    }
+
+    /// Distances from `data` to every element, sorted.
+    fn sorted_distances(&self, data: &T) -> Vec<(f64, &T)> {
+        let mut dists: Vec<(f64, &T)> = self
+            .data
+            .iter()
+            .map(|d| (self.distance(data, d), d))
+            .collect();
+        dists.sort_by(|a, b| a.0.total_cmp(&b.0));
+        dists
+    }
+}
+
+impl<T, D> NearestNeighbors<T, D> for NearestNeighborsLinear<T, D>
+where
+    T: Clone + PartialEq,
+    D: Fn(&T, &T) -> f64,
+{
+    fn set_distance_function(&mut self, dist_fn: D) {
+        NearestNeighborsLinear::set_distance_function(self, dist_fn);
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.dist_fn.as_ref().map(|f| f as DistanceFunctionRef<T>)
+    }
+
+    fn add(&mut self, data: T) {
+        self.data.push(data);
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        NearestNeighborsLinear::add_multiple(self, data);
+    }
+
+    fn remove(&mut self, data: &T) -> bool {
+        NearestNeighborsLinear::remove(self, data)
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        self.data
+            .iter()
+            .map(|d| (self.distance(data, d), d))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, d)| d.clone())
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        let mut dists = self.sorted_distances(data);
+        dists.truncate(k);
+        dists.into_iter().map(|(_, d)| d.clone()).collect()
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.sorted_distances(data)
+            .into_iter()
+            .take_while(|(dist, _)| *dist <= radius)
+            .map(|(_, d)| d.clone())
+            .collect()
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        NearestNeighborsLinear::nearest_k_within_into(self, data, k, f64::INFINITY, out);
+    }
+
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        NearestNeighborsLinear::nearest_k_within_into(self, data, usize::MAX, radius, out);
+    }
+
+    fn nearest_k_within_into(&self, data: &T, k: usize, radius: f64, out: &mut Vec<(T, f64)>) {
+        NearestNeighborsLinear::nearest_k_within_into(self, data, k, radius, out);
+    }
+
+    fn clear(&mut self) {
+        NearestNeighborsLinear::clear(self);
+    }
+
+    fn size(&self) -> usize {
+        NearestNeighborsLinear::size(self)
+    }
+}
+
+/// Nearest neighbors by checking about √n of the n elements for `nearest`, a different subset
+/// being checked by consecutive queries. The other queries are exact.
+///
+/// As it does not rely on the triangle inequality, this is the default for spaces that are not
+/// metric.
+pub struct NearestNeighborsSqrtApprox<T> {
+    linear: NearestNeighborsLinear<T>,
+    checks: usize,
+    offset: Cell<usize>,
+}
+
+impl<T> Default for NearestNeighborsSqrtApprox<T> {
+    fn default() -> Self {
+        Self {
+            linear: NearestNeighborsLinear::default(),
+            checks: 0,
+            offset: Cell::new(0),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> NearestNeighborsSqrtApprox<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of elements checked by `nearest`.
+    pub fn get_checks(&self) -> usize {
+        self.checks
+    }
+
+    fn update_check_count(&mut self) {
+        self.checks = 1 + (self.linear.size() as f64).sqrt().floor() as usize;
+        self.offset.set(self.offset.get() % self.checks);
+    }
+}
+
+impl<T: Clone + PartialEq> NearestNeighbors<T> for NearestNeighborsSqrtApprox<T> {
+    fn set_distance_function(&mut self, dist_fn: LocalDistanceFunction<T>) {
+        self.linear.set_distance_function(dist_fn);
+    }
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, T>> {
+        self.linear.dist_fn.as_deref()
+    }
+
+    fn add(&mut self, data: T) {
+        self.linear.add(data);
+        self.update_check_count();
+    }
+
+    fn add_multiple(&mut self, data: Vec<T>) {
+        self.linear.add_multiple(data);
+        self.update_check_count();
+    }
+
+    fn remove(&mut self, data: &T) -> bool {
+        let removed = self.linear.remove(data);
+        if removed {
+            self.update_check_count();
+        }
+        removed
+    }
+
+    fn nearest(&self, data: &T) -> Option<T> {
+        let elements = self.linear.get_data();
+        let n = elements.len();
+        if n == 0 {
+            return None;
+        }
+
+        // elements offset, offset + checks, offset + 2 checks, ... (modulo n)
+        let offset = self.offset.get();
+        let nearest = (0..self.checks)
+            .map(|j| &elements[(j * self.checks + offset) % n])
+            .map(|d| (self.linear.distance(data, d), d))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, d)| d.clone());
+        self.offset.set((offset + 1) % self.checks);
+        nearest
+    }
+
+    fn nearest_k(&self, data: &T, k: usize) -> Vec<T> {
+        self.linear.nearest_k(data, k)
+    }
+
+    fn nearest_r(&self, data: &T, radius: f64) -> Vec<T> {
+        self.linear.nearest_r(data, radius)
+    }
+
+    fn nearest_k_into(&self, data: &T, k: usize, out: &mut Vec<(T, f64)>) {
+        self.linear.nearest_k_into(data, k, out);
+    }
+
+    fn nearest_r_into(&self, data: &T, radius: f64, out: &mut Vec<(T, f64)>) {
+        self.linear.nearest_r_into(data, radius, out);
+    }
+
+    fn clear(&mut self) {
+        self.linear.clear();
+        self.checks = 0;
+        self.offset.set(0);
+    }
+
+    fn size(&self) -> usize {
+        self.linear.size()
+    }
+}
@@ -0,0 +1,545 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    rc::Rc,
+};
+
+use crate::base::{
+    spaces::real_vector_state_space::RealVectorStateSpace, state_allocator::StateKey,
+};
+use crate::prelude::CanStateAllocateTrait;
+
+use super::nearest_neighbours::{DistanceFunctionRef, LocalDistanceFunction, NearestNeighbors};
+
+/// A k-d tree over the states of a `RealVectorStateSpace`.
+///
+/// The coordinates of every state are copied once into a contiguous buffer, so queries never
+/// go through the state allocator. Distances are weighted Euclidean distances,
+/// `sqrt(sum_i w_i * d_i^2)`, where `d_i` is the difference along dimension `i`, taken around
+/// the circle for wrap-around dimensions (whose period is given by the bounds of the space).
+///
+/// The tree computes distances itself: the function given to `set_distance_function` is
+/// ignored, and the weights and wrap-around dimensions must be set to match the intended
+/// distance instead.
+///
+/// Insertions descend the tree, which is rebuilt balanced whenever its size doubles. Removed
+/// states are only marked as removed until they make up half of the tree.
+pub struct NearestNeighborsKdTree {
+    space: Rc<RealVectorStateSpace>,
+    dimension: usize,
+    weights: Vec<f64>,
+    // bounds of the wrap-around dimensions
+    wrap_around: Vec<Option<(f64, f64)>>,
+    coordinates: Vec<f64>,
+    states: Vec<StateKey>,
+    alive: Vec<bool>,
+    positions: HashMap<StateKey, Vec<usize>>,
+    // node `i` holds element `i`
+    nodes: Vec<Node>,
+    root: Option<usize>,
+    size: usize,
+    built_size: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    axis: usize,
+    // elements with a coordinate along `axis` lower than or equal to the one of the node
+    left: Option<usize>,
+    // elements with a coordinate along `axis` greater than or equal to the one of the node
+    right: Option<usize>,
+}
+
+/// A found element, the furthest one being on top of the heap.
+#[derive(Clone, Copy)]
+struct Neighbor {
+    dist: f64,
+    index: usize,
+}
+
+impl PartialEq for Neighbor {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.dist.total_cmp(&other.dist)
+    }
+}
+
+enum Collector {
+    Nearest {
+        k: usize,
+        radius: f64,
+        found: BinaryHeap<Neighbor>,
+    },
+    Within {
+        radius: f64,
+        found: Vec<Neighbor>,
+    },
+}
+
+impl Collector {
+    /// Distance beyond which elements are not collected.
+    fn bound(&self) -> f64 {
+        match self {
+            Collector::Nearest { k, radius, found } if found.len() == *k => {
+                found.peek().unwrap().dist.min(*radius)
+            }
+            Collector::Nearest { radius, .. } => *radius,
+            Collector::Within { radius, .. } => *radius,
+        }
+    }
+
+    fn offer(&mut self, neighbor: Neighbor) {
+        match self {
+            Collector::Nearest { k, radius, found } => {
+                if neighbor.dist > *radius {
+                    return;
+                }
+                if found.len() < *k {
+                    found.push(neighbor);
+                } else if neighbor.dist < found.peek().unwrap().dist {
+                    found.pop();
+                    found.push(neighbor);
+                }
+            }
+            Collector::Within { radius, found } => {
+                if neighbor.dist <= *radius {
+                    found.push(neighbor);
+                }
+            }
+        }
+    }
+
+    fn into_sorted_vec(self) -> Vec<Neighbor> {
+        match self {
+            Collector::Nearest { found, .. } => found.into_sorted_vec(),
+            Collector::Within { mut found, .. } => {
+                found.sort();
+                found
+            }
+        }
+    }
+}
+
+impl NearestNeighborsKdTree {
+    pub fn new(space: Rc<RealVectorStateSpace>) -> Self {
+        let dimension = space.bounds.low.len();
+        Self {
+            space,
+            dimension,
+            weights: vec![1.0; dimension],
+            wrap_around: vec![None; dimension],
+            coordinates: Vec::new(),
+            states: Vec::new(),
+            alive: Vec::new(),
+            positions: HashMap::new(),
+            nodes: Vec::new(),
+            root: None,
+            size: 0,
+            built_size: 0,
+        }
+    }
+
+    /// Set the weight of every dimension in the distance. Weights must not be negative.
+    pub fn set_weights(&mut self, weights: Vec<f64>) {
+        if weights.len() != self.dimension {
+            panic!("Expected one weight per dimension");
+        }
+        if weights.iter().any(|w| *w < 0.0) {
+            panic!("Weights cannot be negative");
+        }
+        self.weights = weights;
+    }
+
+    pub fn get_weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Make distances along dimension `dim` wrap around, its lower and upper bounds in the
+    /// state space being the same point (as for angles). States must lie within the bounds.
+    pub fn set_wrap_around(&mut self, dim: usize, wrap_around: bool) {
+        self.wrap_around[dim] = if wrap_around {
+            Some((self.space.bounds.low[dim], self.space.bounds.high[dim]))
+        } else {
+            None
+        };
+        self.rebuild();
+    }
+
+    pub fn is_wrap_around(&self, dim: usize) -> bool {
+        self.wrap_around[dim].is_some()
+    }
+
+    fn point(&self, index: usize) -> &[f64] {
+        &self.coordinates[index * self.dimension..(index + 1) * self.dimension]
+    }
+
+    fn axis_difference(&self, axis: usize, a: f64, b: f64) -> f64 {
+        let d = (a - b).abs();
+        match self.wrap_around[axis] {
+            Some((low, high)) => {
+                let period = high - low;
+                let d = d % period;
+                d.min(period - d)
+            }
+            None => d,
+        }
+    }
+
+    fn distance(&self, query: &[f64], index: usize) -> f64 {
+        let point = self.point(index);
+        (0..self.dimension)
+            .map(|i| self.weights[i] * self.axis_difference(i, query[i], point[i]).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Lower bound on the distance between the query and the points of the box `[low, high]`.
+    fn box_distance(&self, query: &[f64], low: &[f64], high: &[f64]) -> f64 {
+        (0..self.dimension)
+            .map(|i| {
+                let d = if query[i] >= low[i] && query[i] <= high[i] {
+                    0.0
+                } else if self.wrap_around[i].is_some() {
+                    self.axis_difference(i, query[i], low[i])
+                        .min(self.axis_difference(i, query[i], high[i]))
+                } else {
+                    (low[i] - query[i]).max(query[i] - high[i])
+                };
+                self.weights[i] * d * d
+            })
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    fn coordinates_of(&self, state: &StateKey) -> Vec<f64> {
+        state.with_id(|state| {
+            self.space
+                .with_state(state, |s| s.values.iter().copied().collect())
+        })
+    }
+
+    fn push_element(&mut self, state: StateKey) -> usize {
+        let index = self.states.len();
+        let coordinates = self.coordinates_of(&state);
+        assert_eq!(
+            coordinates.len(),
+            self.dimension,
+            "State does not match the dimension of the tree"
+        );
+        self.coordinates.extend(coordinates);
+        self.positions.entry(state).or_default().push(index);
+        self.states.push(state);
+        self.alive.push(true);
+        self.nodes.push(Node {
+            axis: 0,
+            left: None,
+            right: None,
+        });
+        self.size += 1;
+        index
+    }
+
+    /// Build a balanced subtree over `indices`, splitting along the axis of largest spread.
+    fn build(&mut self, indices: &mut [usize]) -> Option<usize> {
+        if indices.is_empty() {
+            return None;
+        }
+        let axis = (0..self.dimension)
+            .map(|axis| {
+                let values = indices.iter().map(|&i| self.point(i)[axis]);
+                let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+                    (lo.min(v), hi.max(v))
+                });
+                (self.weights[axis] * (max - min).powi(2), axis)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(0, |(_, axis)| axis);
+
+        let median = indices.len() / 2;
+        indices.select_nth_unstable_by(median, |&a, &b| {
+            self.point(a)[axis].total_cmp(&self.point(b)[axis])
+        });
+        let node = indices[median];
+        let (left, rest) = indices.split_at_mut(median);
+        let left = self.build(left);
+        let right = self.build(&mut rest[1..]);
+        self.nodes[node] = Node { axis, left, right };
+        Some(node)
+    }
+
+    /// Rebuild a balanced tree over the states that have not been removed.
+    fn rebuild(&mut self) {
+        let states: Vec<StateKey> = (0..self.states.len())
+            .filter(|&i| self.alive[i])
+            .map(|i| self.states[i])
+            .collect();
+        self.clear();
+        for state in states {
+            self.push_element(state);
+        }
+        let mut indices: Vec<usize> = (0..self.states.len()).collect();
+        self.root = self.build(&mut indices);
+        self.built_size = self.size;
+    }
+
+    fn insert(&mut self, index: usize) {
+        let Some(mut node) = self.root else {
+            self.root = Some(index);
+            return;
+        };
+        let value = |tree: &Self, axis: usize| tree.point(index)[axis];
+        loop {
+            let axis = self.nodes[node].axis;
+            let go_left = value(self, axis) < self.point(node)[axis];
+            let child = if go_left {
+                &mut self.nodes[node].left
+            } else {
+                &mut self.nodes[node].right
+            };
+            match child {
+                Some(next) => node = *next,
+                None => {
+                    *child = Some(index);
+                    self.nodes[index].axis = (axis + 1) % self.dimension;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn search(
+        &self,
+        node: Option<usize>,
+        query: &[f64],
+        low: &mut [f64],
+        high: &mut [f64],
+        collector: &mut Collector,
+    ) {
+        let Some(node) = node else {
+            return;
+        };
+        if self.box_distance(query, low, high) > collector.bound() {
+            return;
+        }
+        if self.alive[node] {
+            collector.offer(Neighbor {
+                dist: self.distance(query, node),
+                index: node,
+            });
+        }
+
+        let Node { axis, left, right } = self.nodes[node];
+        let split = self.point(node)[axis];
+        let search_left = |tree: &Self, low: &mut [f64], high: &mut [f64], c: &mut Collector| {
+            let saved = std::mem::replace(&mut high[axis], split);
+            tree.search(left, query, low, high, c);
+            high[axis] = saved;
+        };
+        let search_right = |tree: &Self, low: &mut [f64], high: &mut [f64], c: &mut Collector| {
+            let saved = std::mem::replace(&mut low[axis], split);
+            tree.search(right, query, low, high, c);
+            low[axis] = saved;
+        };
+        // the side of the query first, as it is the most likely to hold neighbors
+        if query[axis] < split {
+            search_left(self, low, high, collector);
+            search_right(self, low, high, collector);
+        } else {
+            search_right(self, low, high, collector);
+            search_left(self, low, high, collector);
+        }
+    }
+
+    /// Collect the neighbors of `data` into `out`, with their distances, sorted by distance.
+    fn query(&self, data: &StateKey, mut collector: Collector, out: &mut Vec<(StateKey, f64)>) {
+        let query = self.coordinates_of(data);
+        let (mut low, mut high): (Vec<f64>, Vec<f64>) = self
+            .wrap_around
+            .iter()
+            .map(|wrap| wrap.unwrap_or((f64::NEG_INFINITY, f64::INFINITY)))
+            .unzip();
+        self.search(self.root, &query, &mut low, &mut high, &mut collector);
+        out.clear();
+        out.extend(
+            collector
+                .into_sorted_vec()
+                .into_iter()
+                .map(|n| (self.states[n.index], n.dist)),
+        );
+    }
+}
+
+impl NearestNeighbors<StateKey> for NearestNeighborsKdTree {
+    /// The tree computes weighted Euclidean distances on its own, see `set_weights` and
+    /// `set_wrap_around`; the distance function is ignored.
+    fn set_distance_function(&mut self, _dist_fn: LocalDistanceFunction<StateKey>) {}
+
+    fn get_distance_function(&self) -> Option<DistanceFunctionRef<'_, StateKey>> {
+        None
+    }
+
+    fn add(&mut self, data: StateKey) {
+        let index = self.push_element(data);
+        if self.states.len() >= 2 * self.built_size.max(16) {
+            self.rebuild();
+        } else {
+            self.insert(index);
+        }
+    }
+
+    fn add_multiple(&mut self, data: Vec<StateKey>) {
+        for state in data {
+            self.push_element(state);
+        }
+        self.rebuild();
+    }
+
+    fn remove(&mut self, data: &StateKey) -> bool {
+        let Some(index) = self
+            .positions
+            .get_mut(data)
+            .and_then(|positions| positions.pop())
+        else {
+            return false;
+        };
+        self.alive[index] = false;
+        self.size -= 1;
+        if 2 * self.size < self.states.len() {
+            self.rebuild();
+        }
+        true
+    }
+
+    fn nearest(&self, data: &StateKey) -> Option<StateKey> {
+        self.nearest_k(data, 1).pop()
+    }
+
+    fn nearest_k(&self, data: &StateKey, k: usize) -> Vec<StateKey> {
+        self.nearest_k_with_distances(data, k)
+            .into_iter()
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    fn nearest_r(&self, data: &StateKey, radius: f64) -> Vec<StateKey> {
+        self.nearest_r_with_distances(data, radius)
+            .into_iter()
+            .map(|(s, _)| s)
+            .collect()
+    }
+
+    fn nearest_k_into(&self, data: &StateKey, k: usize, out: &mut Vec<(StateKey, f64)>) {
+        self.nearest_k_within_into(data, k, f64::INFINITY, out);
+    }
+
+    fn nearest_r_into(&self, data: &StateKey, radius: f64, out: &mut Vec<(StateKey, f64)>) {
+        let found = Vec::new();
+        self.query(data, Collector::Within { radius, found }, out);
+    }
+
+    fn nearest_k_within_into(
+        &self,
+        data: &StateKey,
+        k: usize,
+        radius: f64,
+        out: &mut Vec<(StateKey, f64)>,
+    ) {
+        if k == 0 {
+            out.clear();
+            return;
+        }
+        let found = BinaryHeap::with_capacity(k + 1);
+        self.query(data, Collector::Nearest { k, radius, found }, out);
+    }
+
+    fn clear(&mut self) {
+        self.coordinates.clear();
+        self.states.clear();
+        self.alive.clear();
+        self.positions.clear();
+        self.nodes.clear();
+        self.root = None;
+        self.size = 0;
+        self.built_size = 0;
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::spaces::real_vector_state_space::RealVectorState;
+    use crate::datastructure::nearest_neighbours_linear::NearestNeighborsLinear;
+    use nalgebra::DVector;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_kd_tree_weights_and_wrap_around() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, -1.0, 1.0);
+        space.add_dimension(None, -PI, PI);
+        let space = Rc::new(space);
+
+        let mut tree = NearestNeighborsKdTree::new(space.clone());
+        tree.set_weights(vec![2.0, 0.5]);
+        tree.set_wrap_around(1, true);
+        assert!(tree.is_wrap_around(1) && !tree.is_wrap_around(0));
+
+        let mut linear = NearestNeighborsLinear::new();
+        let space2 = space.clone();
+        linear.set_distance_function(Box::new(move |a, b| {
+            let (a, b) = (values_of(&space2, a), values_of(&space2, b));
+            let dyaw = (a.1 - b.1).abs();
+            let dyaw = dyaw.min(2.0 * PI - dyaw);
+            (2.0 * (a.0 - b.0).powi(2) + 0.5 * dyaw.powi(2)).sqrt()
+        }));
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut random_state = || {
+            let values = vec![rng.gen_range(-1.0..1.0), rng.gen_range(-PI..PI)];
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(values),
+            })
+        };
+        for _ in 0..300 {
+            let state = random_state();
+            tree.add(state.key());
+            linear.add(state.key());
+        }
+
+        for _ in 0..30 {
+            let query = random_state().key();
+            assert_eq!(tree.nearest_k(&query, 5), linear.nearest_k(&query, 5));
+            assert_eq!(tree.nearest_r(&query, 0.5), linear.nearest_r(&query, 0.5));
+        }
+
+        // neighbors across the wrap-around boundary
+        let query = space
+            .alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![0.0, PI - 0.01]),
+            })
+            .key();
+        assert_eq!(tree.nearest(&query), linear.nearest(&query));
+    }
+
+    fn values_of(space: &RealVectorStateSpace, s: &StateKey) -> (f64, f64) {
+        s.with_id(|s| space.with_state(s, |s| (s.values[0], s.values[1])))
+    }
+}
@@ -7,3 +7,14 @@ pub enum ProlateHyperspheroidError {
     #[error("The transformation is not up to date. Has the transverse diameter been set?")]
     TransformationNotUpToDate,
 }
+
+#[derive(Error, Debug)]
+pub enum LoadError {
+    #[error("Unable to read the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid {format} data: {message}")]
+    InvalidFormat {
+        format: &'static str,
+        message: String,
+    },
+}
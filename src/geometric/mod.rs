@@ -0,0 +1,2 @@
+pub mod path_geometric;
+pub mod planners;
@@ -0,0 +1,366 @@
+use std::{collections::HashSet, rc::Rc};
+
+use crate::base::{
+    motion_validator::MotionValidator,
+    objectives::{motion_length, Cost, OptimizationObjective},
+    state_allocator::StateId,
+    state_validity_checker::StateValidityChecker,
+    statespace::StateSpace,
+};
+
+/// A path made of straight motions (as given by `StateSpace::interpolate`) between a sequence
+/// of states.
+///
+/// The path owns its states: they are allocated in its space when added (states passed by
+/// reference are copied) and freed when removed or when the path is dropped.
+pub struct PathGeometric {
+    state_space: Rc<dyn StateSpace>,
+    states: Vec<StateId>,
+}
+
+impl PathGeometric {
+    /// Create an empty path.
+    pub fn new(state_space: Rc<dyn StateSpace>) -> Self {
+        Self {
+            state_space,
+            states: Vec::new(),
+        }
+    }
+
+    /// Create a path taking ownership of the given states, which must have been allocated in
+    /// `state_space` and be distinct, as the path frees each of them.
+    pub fn from_states(state_space: Rc<dyn StateSpace>, states: Vec<StateId>) -> Self {
+        let mut keys = HashSet::with_capacity(states.len());
+        if !states.iter().all(|s| keys.insert(s.key())) {
+            panic!("The states of a path must be distinct");
+        }
+        Self {
+            state_space,
+            states,
+        }
+    }
+
+    pub fn get_state_space(&self) -> &Rc<dyn StateSpace> {
+        &self.state_space
+    }
+
+    pub fn get_state_count(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn get_state(&self, index: usize) -> &StateId {
+        &self.states[index]
+    }
+
+    pub fn get_states(&self) -> &[StateId] {
+        &self.states
+    }
+
+    /// The sum of the distances between consecutive states.
+    pub fn length(&self) -> f64 {
+        self.states
+            .windows(2)
+            .map(|w| motion_length(self.state_space.as_ref(), &w[0], &w[1]))
+            .sum()
+    }
+
+    /// The cost of the path according to `objective`.
+    pub fn cost(&self, objective: &dyn OptimizationObjective) -> Cost {
+        objective.path_cost(&self.states)
+    }
+
+    /// Whether the first state of the path is valid according to `checker` and every motion of
+    /// the path is valid according to `motion_validator`.
+    pub fn check(
+        &self,
+        checker: &dyn StateValidityChecker,
+        motion_validator: &dyn MotionValidator,
+    ) -> bool {
+        let Some(first) = self.states.first() else {
+            return true;
+        };
+        checker.is_valid(first)
+            && self
+                .states
+                .windows(2)
+                .all(|w| motion_validator.check_motion(&w[0], &w[1]))
+    }
+
+    /// Insert states along the motions so that the path has (about) `count` states, spread in
+    /// proportion to the length of the motions. Nothing is done if the path already has at
+    /// least `count` states.
+    pub fn interpolate(&mut self, count: usize) {
+        let n = self.states.len();
+        if n < 2 || count <= n {
+            return;
+        }
+
+        let mut remaining_length = self.length();
+        let mut remaining_count = count;
+        let old_states = std::mem::take(&mut self.states);
+        let mut inserted_states = Vec::with_capacity(n - 1);
+        for (i, w) in old_states.windows(2).enumerate() {
+            // the most states the motion can take while leaving room for the end states of
+            // the following motions
+            let max_states = (remaining_count + i).saturating_sub(n);
+            if max_states == 0 {
+                remaining_count -= 1;
+                inserted_states.push(Vec::new());
+                continue;
+            }
+            let length = motion_length(self.state_space.as_ref(), &w[0], &w[1]);
+            let inserted = if i + 2 == n {
+                max_states
+            } else {
+                let share = (remaining_count as f64 * length / remaining_length).round() as usize;
+                share.saturating_sub(1).min(max_states)
+            };
+            inserted_states.push(self.motion_states(&w[0], &w[1], inserted));
+            remaining_count -= inserted + 1;
+            remaining_length -= length;
+        }
+        self.states = interleave(old_states, inserted_states);
+    }
+
+    /// Insert states along the motions at the resolution of the space (see
+    /// `StateSpace::valid_segment_count`), i.e. at the states a motion validator would check.
+    pub fn interpolate_to_resolution(&mut self) {
+        let old_states = std::mem::take(&mut self.states);
+        let inserted_states = old_states
+            .windows(2)
+            .map(|w| {
+                if w[0] == w[1] {
+                    return Vec::new();
+                }
+                let nd = self.state_space.valid_segment_count(&w[0], &w[1]) as usize;
+                self.motion_states(&w[0], &w[1], nd.saturating_sub(1))
+            })
+            .collect();
+        self.states = interleave(old_states, inserted_states);
+    }
+
+    /// `count` new states evenly spread along the motion from `s1` to `s2`, end states
+    /// excluded.
+    fn motion_states(&self, s1: &StateId, s2: &StateId, count: usize) -> Vec<StateId> {
+        (1..=count)
+            .map(|j| {
+                let mut state = self.state_space.alloc_state();
+                self.state_space
+                    .interpolate(s1, s2, j as f64 / (count + 1) as f64, &mut state);
+                state
+            })
+            .collect()
+    }
+
+    /// Add a copy of `state` at the end of the path.
+    pub fn append_state(&mut self, state: &StateId) {
+        self.states.push(self.state_space.clone_state(state));
+    }
+
+    /// Add copies of the states of `path` at the end of this path. The paths must be in the
+    /// same space.
+    pub fn append(&mut self, path: &PathGeometric) {
+        for state in &path.states {
+            self.append_state(state);
+        }
+    }
+
+    /// Add a copy of `state` at the start of the path.
+    pub fn prepend_state(&mut self, state: &StateId) {
+        self.states.insert(0, self.state_space.clone_state(state));
+    }
+
+    /// Add copies of the states of `path` at the start of this path. The paths must be in the
+    /// same space.
+    pub fn prepend(&mut self, path: &PathGeometric) {
+        let states = path.states.iter().map(|s| self.state_space.clone_state(s));
+        self.states.splice(0..0, states);
+    }
+
+    pub fn reverse(&mut self) {
+        self.states.reverse();
+    }
+
+    /// A copy of the part of the path from state `i` to state `j`, both included. If `j` is
+    /// before `i`, the part is reversed.
+    pub fn subpath(&self, i: usize, j: usize) -> PathGeometric {
+        let mut path = PathGeometric::new(self.state_space.clone());
+        if i <= j {
+            self.states[i..=j].iter().for_each(|s| path.append_state(s));
+        } else {
+            self.states[j..=i]
+                .iter()
+                .rev()
+                .for_each(|s| path.append_state(s));
+        }
+        path
+    }
+
+    /// The index of the state of the path closest to `state`, or `None` if the path is empty.
+    pub fn get_closest_index(&self, state: &StateId) -> Option<usize> {
+        self.states
+            .iter()
+            .map(|s| motion_length(self.state_space.as_ref(), s, state))
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Remove the states before `state`, which is meant to be on the path (e.g. the current
+    /// state of a robot following it). The closest state of the path is kept if `state` lies
+    /// before it, and removed if `state` lies past it, i.e. is closer to the next state than
+    /// to the previous one.
+    pub fn keep_after(&mut self, state: &StateId) {
+        let Some(mut index) = self.get_closest_index(state) else {
+            return;
+        };
+        if index > 0 && index + 1 < self.states.len() {
+            let before = motion_length(self.state_space.as_ref(), state, &self.states[index - 1]);
+            let after = motion_length(self.state_space.as_ref(), state, &self.states[index + 1]);
+            if before > after {
+                index += 1;
+            }
+        }
+        if index > 0 {
+            let removed = self.states.drain(..index).collect();
+            self.free_states(removed);
+        }
+    }
+
+    /// Remove the states after `state`, which is meant to be on the path. The closest state of
+    /// the path is kept if `state` lies past it, and removed if `state` lies before it.
+    pub fn keep_before(&mut self, state: &StateId) {
+        let Some(mut index) = self.get_closest_index(state) else {
+            return;
+        };
+        if index > 0 && index + 1 < self.states.len() {
+            let before = motion_length(self.state_space.as_ref(), state, &self.states[index - 1]);
+            let after = motion_length(self.state_space.as_ref(), state, &self.states[index + 1]);
+            if before < after {
+                index -= 1;
+            }
+        }
+        let removed = self.states.split_off(index + 1);
+        self.free_states(removed);
+    }
+
+    /// Remove and free all the states.
+    pub fn clear(&mut self) {
+        let states = std::mem::take(&mut self.states);
+        self.free_states(states);
+    }
+
+    fn free_states(&self, states: Vec<StateId>) {
+        for state in states {
+            self.state_space.free_state(&state);
+        }
+    }
+}
+
+/// The given states, each followed by the states inserted on the motion that starts at it.
+fn interleave(states: Vec<StateId>, inserted: Vec<Vec<StateId>>) -> Vec<StateId> {
+    let inserted = inserted.into_iter().chain(std::iter::once(Vec::new()));
+    states
+        .into_iter()
+        .zip(inserted)
+        .flat_map(|(state, after)| std::iter::once(state).chain(after))
+        .collect()
+}
+
+impl Clone for PathGeometric {
+    /// A copy of the path, with copies of its states.
+    fn clone(&self) -> Self {
+        let mut path = PathGeometric::new(self.state_space.clone());
+        path.append(self);
+        path
+    }
+}
+
+impl Drop for PathGeometric {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::motion_validator::clearance_motion_validator::ClearanceMotionValidator;
+    use crate::base::objectives::path_length_optimization_objective::PathLengthOptimizationObjective;
+    use crate::base::spaces::real_vector_state_space::{RealVectorState, RealVectorStateSpace};
+    use crate::base::state_validity_checker::bounds_validity_checker::BoundsValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_path_geometric() {
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, -1.0, 10.0);
+        space.add_dimension(None, -1.0, 10.0);
+        space.state_space_data_mut().longest_valid_segment = 0.5;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+        let xs = |path: &PathGeometric| -> Vec<f64> {
+            path.get_states()
+                .iter()
+                .map(|s| space.with_state(s, |s| s.values[0]))
+                .collect()
+        };
+
+        let mut path = PathGeometric::from_states(space.clone(), vec![state(0.0, 0.0)]);
+        path.append_state(&state(3.0, 0.0));
+        path.prepend_state(&state(0.0, 4.0));
+        assert_eq!(path.get_state_count(), 3);
+        assert_eq!(path.length(), 7.0);
+
+        // 4 states on the first motion, 2 on the second
+        path.interpolate(9);
+        assert_eq!(path.get_state_count(), 9);
+        assert!((path.length() - 7.0).abs() < 1e-12);
+        assert_eq!(xs(&path), vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 3.0]);
+
+        let mut part = path.subpath(8, 5);
+        assert_eq!(xs(&part), vec![3.0, 2.0, 1.0, 0.0]);
+        part.reverse();
+        part.append(&path.subpath(7, 8));
+        assert_eq!(xs(&part), vec![0.0, 1.0, 2.0, 3.0, 2.0, 3.0]);
+
+        // the state closest to a robot is kept, unless the robot has gone past it
+        let mut copy = path.subpath(5, 8);
+        copy.keep_after(&state(0.6, 0.0));
+        assert_eq!(xs(&copy), vec![1.0, 2.0, 3.0]);
+        copy.keep_before(&state(2.4, 0.0));
+        assert_eq!(xs(&copy), vec![1.0, 2.0]);
+        copy.keep_before(&state(1.4, 0.0));
+        assert_eq!(xs(&copy), vec![1.0]);
+        copy.prepend(&part);
+        assert_eq!(copy.get_state_count(), 7);
+        copy.clear();
+        assert_eq!(copy.length(), 0.0);
+        assert_eq!(path.get_state_count(), 9);
+
+        let objective = PathLengthOptimizationObjective::new(space.clone());
+        assert_eq!(path.cost(&objective), Cost(7.0));
+        let checker = Arc::new(BoundsValidityChecker::new(space.clone()));
+        let validator = ClearanceMotionValidator::new(space.clone(), checker.clone());
+        assert!(path.check(checker.as_ref(), &validator));
+        path.append_state(&state(11.0, 0.0));
+        assert!(!path.check(checker.as_ref(), &validator));
+        path.reverse();
+        assert!(!path.subpath(0, 0).check(checker.as_ref(), &validator));
+
+        let mut dense = path.subpath(0, 1);
+        dense.interpolate_to_resolution();
+        assert_eq!(
+            dense.get_state_count() as u32,
+            space.valid_segment_count(path.get_state(0), path.get_state(1)) + 1
+        );
+    }
+}
@@ -0,0 +1,461 @@
+use std::{collections::HashMap, rc::Rc};
+
+use tracing::info;
+
+use crate::base::{
+    motion_validator::MotionValidator,
+    objectives::{Cost, OptimizationObjective},
+    problem_definition::ProblemDefinition,
+    state_allocator::{StateId, StateKey},
+    state_sampler::StateSampler,
+    statespace::StateSpace,
+};
+use crate::datastructure::nearest_neighbours::{
+    get_default_nearest_neighbors, state_space_distance_function, NearestNeighbors,
+};
+use crate::geometric::path_geometric::PathGeometric;
+use crate::randomness::RNG;
+
+struct Motion {
+    state: StateId,
+    parent: Option<usize>,
+    cost: Cost,
+}
+
+/// Transition-based RRT (Jaillet, Cortés and Siméon, 2010), an RRT that follows the valleys of
+/// a cost landscape, such as the one of a `CostMapOptimizationObjective`.
+///
+/// Every new state goes through a transition test based on the state costs of the objective:
+/// moving to a cheaper state is always accepted, while climbing by a slope `s` (the cost
+/// increase per unit of distance) is only accepted if `exp(-s / T) > 0.5`. The temperature `T`
+/// adapts to the landscape: it decreases after every accepted climb, and increases by a factor
+/// `exp(temperature_change_factor)` after `max_states_failed` consecutive rejections. States
+/// of infinite cost, or worse than the cost threshold, are always rejected.
+///
+/// The minimum expansion control limits the refinement of the explored region: extensions
+/// shorter than the frontier threshold may only make up a `frontier_node_ratio` of the tree.
+///
+/// T-RRT does not optimize the path it returns, but keeps it in low-cost regions.
+pub struct TRRT {
+    state_space: Rc<dyn StateSpace>,
+    motion_validator: Box<dyn MotionValidator>,
+    sampler: Box<dyn StateSampler>,
+    rng: RNG,
+    nn: Box<dyn NearestNeighbors<StateKey>>,
+    motions: Vec<Motion>,
+    motion_of_state: HashMap<StateKey, usize>,
+
+    range: f64,
+    goal_bias: f64,
+    goal_threshold: f64,
+    max_states_failed: u32,
+    temperature_change_factor: f64,
+    init_temperature: f64,
+    frontier_threshold: f64,
+    frontier_node_ratio: f64,
+    cost_threshold: Cost,
+
+    temperature: f64,
+    states_failed: u32,
+    best_cost: Cost,
+    worst_cost: Cost,
+    frontier_count: u32,
+    non_frontier_count: u32,
+}
+
+impl TRRT {
+    pub fn new(
+        state_space: Rc<dyn StateSpace>,
+        motion_validator: Box<dyn MotionValidator>,
+        sampler: Box<dyn StateSampler>,
+    ) -> Self {
+        let mut nn = get_default_nearest_neighbors(state_space.as_ref());
+        nn.set_distance_function(state_space_distance_function(state_space.clone()));
+        let extent = state_space.get_maximum_extent();
+        Self {
+            state_space,
+            motion_validator,
+            sampler,
+            rng: RNG::new(),
+            nn,
+            motions: Vec::new(),
+            motion_of_state: HashMap::new(),
+            range: 0.2 * extent,
+            goal_bias: 0.05,
+            goal_threshold: 0.0,
+            max_states_failed: 10,
+            temperature_change_factor: 0.1,
+            init_temperature: 100.0,
+            frontier_threshold: 0.01 * extent,
+            frontier_node_ratio: 0.1,
+            cost_threshold: Cost(f64::INFINITY),
+            temperature: 100.0,
+            states_failed: 0,
+            best_cost: Cost(f64::INFINITY),
+            worst_cost: Cost(f64::NEG_INFINITY),
+            frontier_count: 1,
+            non_frontier_count: 1,
+        }
+    }
+
+    /// Set the maximum length of a motion added to the tree.
+    pub fn set_range(&mut self, range: f64) {
+        if range <= 0.0 {
+            panic!("The range must be strictly positive");
+        }
+        self.range = range;
+    }
+
+    pub fn get_range(&self) -> f64 {
+        self.range
+    }
+
+    /// Set the probability of extending the tree towards the goal rather than a random state.
+    pub fn set_goal_bias(&mut self, goal_bias: f64) {
+        if !(0.0..=1.0).contains(&goal_bias) {
+            panic!("The goal bias must be between 0 and 1");
+        }
+        self.goal_bias = goal_bias;
+    }
+
+    pub fn get_goal_bias(&self) -> f64 {
+        self.goal_bias
+    }
+
+    /// Set the distance to the goal state under which a state solves the problem.
+    pub fn set_goal_threshold(&mut self, goal_threshold: f64) {
+        if goal_threshold < 0.0 {
+            panic!("The goal threshold must be non-negative");
+        }
+        self.goal_threshold = goal_threshold;
+    }
+
+    pub fn get_goal_threshold(&self) -> f64 {
+        self.goal_threshold
+    }
+
+    /// Set the number of consecutive failed transition tests after which the temperature is
+    /// increased.
+    pub fn set_max_states_failed(&mut self, max_states_failed: u32) {
+        self.max_states_failed = max_states_failed;
+    }
+
+    pub fn get_max_states_failed(&self) -> u32 {
+        self.max_states_failed
+    }
+
+    pub fn set_temperature_change_factor(&mut self, factor: f64) {
+        if factor <= 0.0 {
+            panic!("The temperature change factor must be strictly positive");
+        }
+        self.temperature_change_factor = factor;
+    }
+
+    pub fn get_temperature_change_factor(&self) -> f64 {
+        self.temperature_change_factor
+    }
+
+    /// Set the temperature at the start of every `solve`.
+    pub fn set_init_temperature(&mut self, temperature: f64) {
+        if temperature <= 0.0 {
+            panic!("The temperature must be strictly positive");
+        }
+        self.init_temperature = temperature;
+    }
+
+    pub fn get_init_temperature(&self) -> f64 {
+        self.init_temperature
+    }
+
+    /// The current temperature of the transition test.
+    pub fn get_temperature(&self) -> f64 {
+        self.temperature
+    }
+
+    /// Set the length above which an extension expands the frontier of the tree.
+    pub fn set_frontier_threshold(&mut self, frontier_threshold: f64) {
+        self.frontier_threshold = frontier_threshold;
+    }
+
+    pub fn get_frontier_threshold(&self) -> f64 {
+        self.frontier_threshold
+    }
+
+    /// Set the ratio of non-frontier (refining) extensions to frontier extensions.
+    pub fn set_frontier_node_ratio(&mut self, ratio: f64) {
+        self.frontier_node_ratio = ratio;
+    }
+
+    pub fn get_frontier_node_ratio(&self) -> f64 {
+        self.frontier_node_ratio
+    }
+
+    /// Set the state cost above which states are always rejected.
+    pub fn set_cost_threshold(&mut self, cost_threshold: Cost) {
+        self.cost_threshold = cost_threshold;
+    }
+
+    pub fn get_cost_threshold(&self) -> Cost {
+        self.cost_threshold
+    }
+
+    /// The number of states in the tree.
+    pub fn get_tree_size(&self) -> usize {
+        self.motions.len()
+    }
+
+    /// Free the tree and reset the temperature.
+    pub fn clear(&mut self) {
+        for motion in self.motions.drain(..) {
+            self.state_space.free_state(&motion.state);
+        }
+        self.motion_of_state.clear();
+        self.nn.clear();
+        self.temperature = self.init_temperature;
+        self.states_failed = 0;
+        self.best_cost = Cost(f64::INFINITY);
+        self.worst_cost = Cost(f64::NEG_INFINITY);
+        self.frontier_count = 1;
+        self.non_frontier_count = 1;
+    }
+
+    /// Grow a tree from the start states of `pdef` for at most `max_iterations` iterations,
+    /// using the optimization objective of `pdef` (the path length by default) for the state
+    /// costs. Returns the path from a start state to the goal, or `None` if the goal was not
+    /// reached. Every call plans from scratch.
+    pub fn solve(
+        &mut self,
+        pdef: &mut ProblemDefinition,
+        max_iterations: usize,
+    ) -> Option<PathGeometric> {
+        self.clear();
+        let objective = pdef.get_optimization_objective_or_default();
+        let goal = pdef
+            .get_goal_state()
+            .expect("The problem definition must have a goal state");
+        for start in pdef.get_start_states() {
+            let cost = objective.state_cost(start);
+            self.add_motion(self.state_space.clone_state(start), None, cost);
+        }
+        if self.motions.is_empty() {
+            panic!("The problem definition must have at least one start state");
+        }
+
+        let mut solution = self
+            .motions
+            .iter()
+            .position(|m| self.reaches(&m.state, goal));
+        let mut random = self.state_space.alloc_state();
+        let mut extended = self.state_space.alloc_state();
+        for _ in 0..max_iterations {
+            if solution.is_some() {
+                break;
+            }
+            if self.rng.uniform01() < self.goal_bias {
+                self.state_space.copy_state(&mut random, goal);
+            } else {
+                self.sampler.sample_uniform(&mut random);
+            }
+
+            let parent = self.motion_of_state[&self.nn.nearest(&random.key()).unwrap()];
+            let near = &self.motions[parent].state;
+            let mut distance = self.state_space.distance(near, &random);
+            if distance > self.range {
+                self.state_space
+                    .interpolate(near, &random, self.range / distance, &mut extended);
+                distance = self.range;
+            } else {
+                self.state_space.copy_state(&mut extended, &random);
+            }
+            if distance <= f64::EPSILON || !self.min_expansion_control(distance) {
+                continue;
+            }
+            let near = &self.motions[parent].state;
+            if !self.motion_validator.check_motion(near, &extended) {
+                continue;
+            }
+
+            let cost = objective.state_cost(&extended);
+            if !self.transition_test(
+                objective.as_ref(),
+                self.motions[parent].cost,
+                cost,
+                distance,
+            ) {
+                continue;
+            }
+            if self.reaches(&extended, goal) {
+                solution = Some(self.motions.len());
+            }
+            self.add_motion(self.state_space.clone_state(&extended), Some(parent), cost);
+        }
+        self.state_space.free_state(&random);
+        self.state_space.free_state(&extended);
+
+        info!(
+            states = self.motions.len(),
+            temperature = self.temperature,
+            solved = solution.is_some(),
+            "T-RRT finished"
+        );
+        let mut current = solution?;
+        let mut states = vec![self.state_space.clone_state(&self.motions[current].state)];
+        while let Some(parent) = self.motions[current].parent {
+            states.push(self.state_space.clone_state(&self.motions[parent].state));
+            current = parent;
+        }
+        states.reverse();
+        Some(PathGeometric::from_states(self.state_space.clone(), states))
+    }
+
+    fn add_motion(&mut self, state: StateId, parent: Option<usize>, cost: Cost) {
+        if cost.value() < self.best_cost.value() {
+            self.best_cost = cost;
+        }
+        if cost.value() > self.worst_cost.value() {
+            self.worst_cost = cost;
+        }
+        self.motion_of_state.insert(state.key(), self.motions.len());
+        self.nn.add(state.key());
+        self.motions.push(Motion {
+            state,
+            parent,
+            cost,
+        });
+    }
+
+    fn reaches(&self, state: &StateId, goal: &StateId) -> bool {
+        state == goal || self.state_space.distance(state, goal) <= self.goal_threshold
+    }
+
+    /// Whether the tree may grow from a state of cost `parent_cost` to a state of cost
+    /// `child_cost` at the given distance, adapting the temperature.
+    fn transition_test(
+        &mut self,
+        objective: &dyn OptimizationObjective,
+        parent_cost: Cost,
+        child_cost: Cost,
+        distance: f64,
+    ) -> bool {
+        if !objective.is_finite(child_cost)
+            || objective.is_cost_better_than(self.cost_threshold, child_cost)
+        {
+            return false;
+        }
+        // going down is always accepted
+        if !objective.is_cost_better_than(parent_cost, child_cost) {
+            return true;
+        }
+
+        let slope = (child_cost.value() - parent_cost.value()).abs() / distance;
+        if (-slope / self.temperature).exp() > 0.5 {
+            let cost_range = (self.worst_cost.value() - self.best_cost.value()).abs();
+            if cost_range > f64::EPSILON {
+                self.temperature /= (slope / (0.1 * cost_range)).exp();
+            }
+            self.states_failed = 0;
+            return true;
+        }
+        if self.states_failed >= self.max_states_failed {
+            // the climb is too hard, heat up
+            self.temperature *= self.temperature_change_factor.exp();
+            self.states_failed = 0;
+            return true;
+        }
+        self.states_failed += 1;
+        false
+    }
+
+    /// Whether an extension of the given length may be added, limiting the number of
+    /// extensions that only refine the explored region.
+    fn min_expansion_control(&mut self, distance: f64) -> bool {
+        if distance > self.frontier_threshold {
+            self.frontier_count += 1;
+            true
+        } else if self.non_frontier_count as f64 / self.frontier_count as f64
+            > self.frontier_node_ratio
+        {
+            false
+        } else {
+            self.non_frontier_count += 1;
+            true
+        }
+    }
+}
+
+impl Drop for TRRT {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::arc_with_non_send_sync)]
+mod tests {
+    use super::*;
+    use crate::base::motion_validator::clearance_motion_validator::ClearanceMotionValidator;
+    use crate::base::spaces::real_vector_state_space::{
+        RealVectorState, RealVectorStateSampler, RealVectorStateSpace,
+    };
+    use crate::base::state_validity_checker::bounds_validity_checker::BoundsValidityChecker;
+    use crate::base::statespace::HasStateSpaceData;
+    use crate::collision::cost_map::{CostMap, CostMapOptimizationObjective};
+    use crate::prelude::CanStateAllocateTrait;
+    use nalgebra::DVector;
+    use std::sync::Arc;
+
+    // a wall that cannot be crossed, but through a gap at the top, and a costly hill at the
+    // bottom
+    const MAP: &str = "
+        1 1 1 1 1 1
+        1 1 1 1 1 1
+        1 1 1 1 1 1
+        1 1 inf inf 1 1
+        1 1 inf inf 1 1
+        1 1 inf inf 1 1
+        1 1 50 50 1 1
+        1 1 50 50 1 1
+    ";
+
+    #[test]
+    fn test_trrt_stays_in_low_cost_regions() {
+        let map = CostMap::parse_text(MAP).unwrap();
+        let mut space = RealVectorStateSpace::new();
+        space.add_dimension(None, 0.0, 1.0);
+        space.add_dimension(None, 0.0, 1.0);
+        map.set_space_bounds(&mut space);
+        space.state_space_data_mut().longest_valid_segment = 0.05;
+        let space = Rc::new(space);
+        let state = |x: f64, y: f64| {
+            space.alloc_arena_state_with_value(RealVectorState {
+                values: DVector::from_vec(vec![x, y]),
+            })
+        };
+
+        let checker = Arc::new(BoundsValidityChecker::new(space.clone()));
+        let validator = ClearanceMotionValidator::new(space.clone(), checker);
+        let sampler = RealVectorStateSampler::from_state_space(space.clone());
+        let mut planner = TRRT::new(space.clone(), Box::new(validator), Box::new(sampler));
+        planner.set_range(0.5);
+        planner.set_goal_threshold(0.1);
+        planner.set_cost_threshold(Cost(10.0));
+
+        let objective = Rc::new(CostMapOptimizationObjective::new(space.clone(), map));
+        let mut pdef = ProblemDefinition::new(space.clone());
+        pdef.set_start_and_goal_states(state(0.5, 0.5), state(5.5, 0.5));
+        pdef.set_optimization_objective(objective.clone());
+
+        let path = planner.solve(&mut pdef, 20000).unwrap();
+        let states = path.get_states();
+        assert!(planner.get_tree_size() >= states.len());
+        assert!(space.distance(&states[0], &pdef.get_start_states()[0]) < 1e-12);
+        assert!(space.distance(states.last().unwrap(), pdef.get_goal_state().unwrap()) <= 0.1);
+        // the path goes around the wall and the hill, both too wide to jump over
+        for s in states {
+            assert!(objective.state_cost(s).value() <= 1.0);
+        }
+        assert!(states
+            .iter()
+            .any(|s| space.with_state(s, |s| s.values[1]) >= 5.0));
+    }
+}
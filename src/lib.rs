@@ -1,6 +1,8 @@
 pub mod base;
+pub mod collision;
 pub mod datastructure;
 pub mod error;
+pub mod geometric;
 pub mod macros;
 pub mod randomness;
 pub mod tools;
@@ -0,0 +1,124 @@
+/// Exact squared Euclidean distance transform of a sampled 1D function, following
+/// Felzenszwalb and Huttenlocher, "Distance Transforms of Sampled Functions".
+///
+/// `f` holds the initial cost of each sample (zero for sites, infinity elsewhere) and the
+/// result is written to `d`, which must have the same length.
+pub fn squared_distance_transform_1d(f: &[f64], d: &mut [f64]) {
+    let n = f.len();
+    assert_eq!(n, d.len());
+    if n == 0 {
+        return;
+    }
+
+    // locations of the parabolas in the lower envelope, and the boundaries between them
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0; n + 1];
+    let mut k = 0;
+
+    // skip leading samples at infinity, they do not contribute a parabola
+    let Some(first) = f.iter().position(|x| x.is_finite()) else {
+        d.fill(f64::INFINITY);
+        return;
+    };
+    v[0] = first;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+
+    for q in first + 1..n {
+        if !f[q].is_finite() {
+            continue;
+        }
+        let intersection =
+            |p: usize| ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2.0 * (q - p) as f64);
+        let mut s = intersection(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+
+    k = 0;
+    for (q, dq) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let p = v[k];
+        let diff = q as f64 - p as f64;
+        *dq = diff * diff + f[p];
+    }
+}
+
+/// Exact Euclidean distance transform of an N-dimensional grid, stored in row-major order
+/// (the last dimension varies fastest).
+///
+/// Returns, for every cell, the distance (in cells) from its center to the center of the
+/// nearest cell for which `is_site` is true, or infinity if there is no such cell.
+pub fn euclidean_distance_transform(dims: &[usize], is_site: &[bool]) -> Vec<f64> {
+    let total: usize = dims.iter().product();
+    assert_eq!(
+        total,
+        is_site.len(),
+        "Grid size does not match its dimensions"
+    );
+
+    let mut grid: Vec<f64> = is_site
+        .iter()
+        .map(|&site| if site { 0.0 } else { f64::INFINITY })
+        .collect();
+
+    let mut line = Vec::new();
+    let mut out = Vec::new();
+    for axis in 0..dims.len() {
+        let len = dims[axis];
+        let stride: usize = dims[axis + 1..].iter().product();
+        line.resize(len, 0.0);
+        out.resize(len, 0.0);
+
+        // every line along `axis` starts at an index whose coordinate along `axis` is zero
+        for start in 0..total {
+            if !(start / stride).is_multiple_of(len) {
+                continue;
+            }
+            for i in 0..len {
+                line[i] = grid[start + i * stride];
+            }
+            squared_distance_transform_1d(&line, &mut out);
+            for i in 0..len {
+                grid[start + i * stride] = out[i];
+            }
+        }
+    }
+
+    grid.iter_mut().for_each(|d| *d = d.sqrt());
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_euclidean_distance_transform() {
+        // 4 x 5 grid with sites at (0, 0) and (3, 4)
+        let dims = [4, 5];
+        let mut sites = vec![false; 20];
+        sites[0] = true;
+        sites[19] = true;
+
+        let dt = euclidean_distance_transform(&dims, &sites);
+        for r in 0..4 {
+            for c in 0..5 {
+                let d0 = ((r * r + c * c) as f64).sqrt();
+                let d1 = (((3 - r) * (3 - r) + (4 - c) * (4 - c)) as f64).sqrt();
+                assert!((dt[r * 5 + c] - d0.min(d1)).abs() < 1e-12);
+            }
+        }
+
+        let empty = euclidean_distance_transform(&dims, &[false; 20]);
+        assert!(empty.iter().all(|d| d.is_infinite()));
+    }
+}
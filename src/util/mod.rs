@@ -1 +1,2 @@
+pub mod distance_transform;
 pub mod prolate_hyperspheroid;